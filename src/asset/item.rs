@@ -0,0 +1,112 @@
+use bevy::{asset::{AssetLoader, Asset, io::Reader, AsyncReadExt}, reflect::{TypeUuid, TypePath}};
+use serde::Deserialize;
+
+use crate::{registry::item::settings::ItemSettings, identifier::Identifier, i18n::Translatable};
+
+fn id_default() -> Identifier {
+	Identifier::from_str("null", "null")
+}
+
+fn trans_default() -> Translatable {
+	Translatable::from_str("null")
+}
+
+#[derive(Debug, Deserialize, Asset, TypeUuid, TypePath)]
+#[uuid = "3c0e1a5f-7b4d-4f62-9a8e-5d1f0b6c2e97"]
+pub struct ItemDef {
+	#[serde(skip, default = "id_default")]
+	identifier: Identifier,
+	#[serde(default)]
+	settings: ItemSettings,
+	#[serde(default = "trans_default")]
+	name: Translatable,
+	#[serde(default = "trans_default")]
+	description: Translatable,
+	/// The tile that is placed when the player uses this item.
+	#[serde(default)]
+	places: Option<Identifier>,
+}
+
+impl ItemDef {
+	pub fn new(identifier: Identifier, settings: ItemSettings) -> Self {
+		Self {
+			name: Translatable::new(format!("{}:item.name.{}", identifier.namespace(), identifier.path())),
+			description: Translatable::new(format!("{}:item.description.{}", identifier.namespace(), identifier.path())),
+			identifier,
+			settings,
+			places: None,
+		}
+	}
+	
+	pub fn settings(&self) -> &ItemSettings {
+		&self.settings
+	}
+	
+	pub fn name(&self) -> &Translatable {
+		&self.name
+	}
+	
+	pub fn description(&self) -> &Translatable {
+		&self.description
+	}
+	
+	pub fn places(&self) -> Option<&Identifier> {
+		self.places.as_ref()
+	}
+	
+	pub fn identifier(&self) -> &Identifier {
+		&self.identifier
+	}
+	
+	pub fn is_missingno(&self) -> bool {
+		self.identifier.namespace() == "null" && self.identifier.path() == "null"
+	}
+}
+
+#[derive(Default)]
+pub struct ItemDefLoader;
+
+impl AssetLoader for ItemDefLoader {
+	type Asset = ItemDef;
+	type Settings = ();
+	type Error = anyhow::Error;
+	
+	fn load<'a>(
+		&'a self,
+		reader: &'a mut Reader,
+		_settings: &'a Self::Settings,
+		load_context: &'a mut bevy::asset::LoadContext,
+	) -> bevy::utils::BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+		Box::pin(async move {
+			let mut bytes = vec![];
+			reader.read_to_end(&mut bytes).await?;
+			
+			let mut def: ItemDef = ron::de::from_bytes(&bytes)?;
+			let default_path = load_context.path().file_name().expect("file path should not terminate with \"..\"").to_string_lossy().to_string().replace(".item.ron", "");
+			let default_namespace = load_context.path().ancestors().nth(2).expect("item definition file should be in directory \"<namespace>/items\"").file_name().expect("directory name should not end in ..").to_string_lossy().to_string();
+			if def.identifier.path() == "null" && def.identifier.namespace() == "null" {
+				def.identifier = Identifier::new(default_namespace.clone(), default_path);
+			}
+			
+			if let Some(places) = def.places.as_mut() {
+				if places.namespace() == "null" {
+					*places = Identifier::new(default_namespace, places.path().to_string());
+				}
+			}
+			
+			if def.name().key() == "null" {
+				def.name = Translatable::new(format!("{}:item.name.{}", def.identifier.namespace(), def.identifier.path()));
+			}
+			
+			if def.description().key() == "null" {
+				def.description = Translatable::new(format!("{}:item.description.{}", def.identifier.namespace(), def.identifier.path()));
+			}
+			
+			Ok(def)
+		})
+	}
+	
+	fn extensions(&self) -> &[&str] {
+		&["item.ron"]
+	}
+}
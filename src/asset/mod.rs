@@ -1,5 +1,7 @@
 pub mod locale;
 pub mod tile;
+pub mod item;
+pub mod recipe;
 pub mod image;
 
 /// Gets the proper location of the asset at the given location with format `<namespace>:<asset>`
@@ -0,0 +1,326 @@
+use std::cmp::Ordering;
+
+use bevy::{asset::{AssetLoader, Asset, io::Reader, AsyncReadExt}, reflect::{TypeUuid, TypePath}};
+use serde::Deserialize;
+
+use crate::identifier::Identifier;
+use crate::utils::BevyHashMap;
+
+fn id_default() -> Identifier {
+	Identifier::from_str("null", "null")
+}
+
+fn count_default() -> u16 {
+	1
+}
+
+/// A number of items consumed or produced by a recipe.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipeStack {
+	item: Identifier,
+	#[serde(default = "count_default")]
+	count: u16,
+}
+
+impl RecipeStack {
+	pub fn item(&self) -> &Identifier {
+		&self.item
+	}
+	
+	pub fn count(&self) -> u16 {
+		self.count
+	}
+}
+
+/// How the ingredients of a recipe must be arranged in the crafting grid.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipeLayout {
+	/// The ingredients must form the pattern, which may be placed anywhere in the grid.
+	///
+	/// Each row of the pattern is a string whose characters are looked up in the key; spaces are empty slots.
+	Shaped {
+		pattern: Vec<String>,
+		key: BevyHashMap<char, Identifier>,
+	},
+	/// The ingredients may be placed anywhere in the grid.
+	Shapeless(Vec<RecipeStack>),
+}
+
+#[derive(Debug, Deserialize, Asset, TypeUuid, TypePath)]
+#[uuid = "8f4b2d61-0c3e-4a7f-b5d9-1e6a7c3f9b20"]
+pub struct RecipeDef {
+	#[serde(skip, default = "id_default")]
+	identifier: Identifier,
+	layout: RecipeLayout,
+	output: RecipeStack,
+	/// The tiles the player must be near to craft this. Any one of them suffices.
+	#[serde(default)]
+	stations: Vec<Identifier>,
+}
+
+impl RecipeDef {
+	pub fn identifier(&self) -> &Identifier {
+		&self.identifier
+	}
+	
+	pub fn layout(&self) -> &RecipeLayout {
+		&self.layout
+	}
+	
+	pub fn output(&self) -> &RecipeStack {
+		&self.output
+	}
+	
+	pub fn stations(&self) -> &[Identifier] {
+		&self.stations
+	}
+	
+	/// Returns whether the grid (given row by row, top to bottom) holds exactly the ingredients of this recipe.
+	pub fn matches(&self, width: usize, grid: &[Option<Identifier>]) -> bool {
+		match &self.layout {
+			RecipeLayout::Shaped { pattern, key } => {
+				let pattern: Vec<Vec<Option<&Identifier>>> = pattern
+					.iter()
+					.map(|row| row.chars().map(|c| key.get(&c)).collect())
+					.collect();
+				
+				trim_grid(&pattern) == trim_grid(&grid_rows(width, grid))
+			},
+			RecipeLayout::Shapeless(ingredients) => {
+				let mut remaining: BevyHashMap<&Identifier, u32> = BevyHashMap::new();
+				for ingredient in ingredients.iter() {
+					*remaining.entry(&ingredient.item).or_default() += ingredient.count as u32;
+				}
+				
+				for item in grid.iter().flatten() {
+					match remaining.get_mut(item) {
+						Some(count) if *count > 0 => *count -= 1,
+						_ => return false,
+					}
+				}
+				
+				remaining.values().all(|count| *count == 0)
+			},
+		}
+	}
+	
+	/// Orders recipes by which one is crafted when several match the same grid: shaped recipes come before shapeless ones,
+	/// since they're more specific, and otherwise the one whose identifier comes first.
+	pub fn cmp_precedence(&self, other: &Self) -> Ordering {
+		let key = |def: &Self| (matches!(def.layout, RecipeLayout::Shapeless(_)), def.identifier.namespace().to_string(), def.identifier.path().to_string());
+		key(self).cmp(&key(other))
+	}
+	
+	/// Returns how many of each item a single craft consumes.
+	pub fn ingredients(&self) -> BevyHashMap<Identifier, u32> {
+		let mut ingredients = BevyHashMap::new();
+		match &self.layout {
+			RecipeLayout::Shaped { pattern, key } => {
+				for c in pattern.iter().flat_map(|row| row.chars()) {
+					if let Some(item) = key.get(&c) {
+						*ingredients.entry(item.clone()).or_default() += 1;
+					}
+				}
+			},
+			RecipeLayout::Shapeless(stacks) => {
+				for stack in stacks.iter() {
+					*ingredients.entry(stack.item.clone()).or_default() += stack.count as u32;
+				}
+			},
+		}
+		ingredients
+	}
+}
+
+fn grid_rows(width: usize, grid: &[Option<Identifier>]) -> Vec<Vec<Option<&Identifier>>> {
+	if width == 0 {
+		return vec![]
+	}
+	
+	grid
+		.chunks(width)
+		.map(|row| row.iter().map(Option::as_ref).collect())
+		.collect()
+}
+
+/// Removes the empty rows & columns around the grid so that shapes match regardless of where they were placed.
+///
+/// Rows are padded to the same width.
+fn trim_grid<'a>(rows: &[Vec<Option<&'a Identifier>>]) -> Vec<Vec<Option<&'a Identifier>>> {
+	let width = rows.iter().map(Vec::len).max().unwrap_or_default();
+	let cell = |row: usize, column: usize| rows[row].get(column).copied().flatten();
+	let occupied_rows: Vec<usize> = (0..rows.len()).filter(|&row| (0..width).any(|column| cell(row, column).is_some())).collect();
+	let occupied_columns: Vec<usize> = (0..width).filter(|&column| (0..rows.len()).any(|row| cell(row, column).is_some())).collect();
+	
+	let (Some(&top), Some(&bottom)) = (occupied_rows.first(), occupied_rows.last()) else {
+		return vec![]
+	};
+	let (left, right) = (occupied_columns[0], occupied_columns[occupied_columns.len() - 1]);
+	
+	(top..=bottom)
+		.map(|row| (left..=right).map(|column| cell(row, column)).collect())
+		.collect()
+}
+
+#[derive(Default)]
+pub struct RecipeDefLoader;
+
+impl AssetLoader for RecipeDefLoader {
+	type Asset = RecipeDef;
+	type Settings = ();
+	type Error = anyhow::Error;
+	
+	fn load<'a>(
+		&'a self,
+		reader: &'a mut Reader,
+		_settings: &'a Self::Settings,
+		load_context: &'a mut bevy::asset::LoadContext,
+	) -> bevy::utils::BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+		Box::pin(async move {
+			let mut bytes = vec![];
+			reader.read_to_end(&mut bytes).await?;
+			
+			let mut def: RecipeDef = ron::de::from_bytes(&bytes)?;
+			let default_path = load_context.path().file_name().expect("file path should not terminate with \"..\"").to_string_lossy().to_string().replace(".recipe.ron", "");
+			let default_namespace = load_context.path().ancestors().nth(2).expect("recipe definition file should be in directory \"<namespace>/recipes\"").file_name().expect("directory name should not end in ..").to_string_lossy().to_string();
+			if def.identifier.path() == "null" && def.identifier.namespace() == "null" {
+				def.identifier = Identifier::new(default_namespace.clone(), default_path);
+			}
+			
+			let with_namespace = |id: &mut Identifier| {
+				if id.namespace() == "null" {
+					*id = Identifier::new(default_namespace.clone(), id.path().to_string());
+				}
+			};
+			
+			match &mut def.layout {
+				RecipeLayout::Shaped { key, .. } => key.values_mut().for_each(with_namespace),
+				RecipeLayout::Shapeless(stacks) => stacks.iter_mut().for_each(|stack| with_namespace(&mut stack.item)),
+			}
+			with_namespace(&mut def.output.item);
+			def.stations.iter_mut().for_each(with_namespace);
+			
+			Ok(def)
+		})
+	}
+	
+	fn extensions(&self) -> &[&str] {
+		&["recipe.ron"]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn id(path: &str) -> Identifier {
+		Identifier::from_str("test", path)
+	}
+	
+	fn shaped(path: &str, pattern: &[&str], key: &[(char, &str)]) -> RecipeDef {
+		RecipeDef {
+			identifier: id(path),
+			layout: RecipeLayout::Shaped {
+				pattern: pattern.iter().map(|row| row.to_string()).collect(),
+				key: key.iter().map(|(c, item)| (*c, id(item))).collect(),
+			},
+			output: RecipeStack { item: id(path), count: 1 },
+			stations: vec![],
+		}
+	}
+	
+	fn shapeless(path: &str, ingredients: &[(&str, u16)]) -> RecipeDef {
+		RecipeDef {
+			identifier: id(path),
+			layout: RecipeLayout::Shapeless(ingredients.iter().map(|(item, count)| RecipeStack { item: id(item), count: *count }).collect()),
+			output: RecipeStack { item: id(path), count: 1 },
+			stations: vec![],
+		}
+	}
+	
+	/// Turns rows of a 3x3 grid into slots, with `.` for empty slots and the first letter of an item otherwise.
+	fn grid(rows: [&str; 3]) -> Vec<Option<Identifier>> {
+		rows
+			.iter()
+			.flat_map(|row| row.chars())
+			.map(|c| match c {
+				'w' => Some(id("wood")),
+				's' => Some(id("stone")),
+				_ => None,
+			})
+			.collect()
+	}
+	
+	#[test]
+	fn trimming() {
+		let (wood, stone) = (id("wood"), id("stone"));
+		let rows = vec![
+			vec![None, None, None],
+			vec![None, Some(&wood), None],
+			vec![None, Some(&stone)],
+		];
+		assert_eq!(trim_grid(&rows), vec![vec![Some(&wood)], vec![Some(&stone)]]);
+		assert_eq!(trim_grid(&[vec![None, None], vec![None, None]]), Vec::<Vec<Option<&Identifier>>>::new());
+		assert_eq!(trim_grid(&[]), Vec::<Vec<Option<&Identifier>>>::new());
+		
+		// the gap in the middle stays
+		let rows = vec![vec![Some(&wood), None, Some(&stone)]];
+		assert_eq!(trim_grid(&rows), rows);
+	}
+	
+	#[test]
+	fn shaped_recipes_match_anywhere() {
+		let stick = shaped("stick", &["w", "w"], &[('w', "wood")]);
+		assert!(stick.matches(3, &grid(["w..", "w..", "..."])));
+		assert!(stick.matches(3, &grid(["...", "..w", "..w"])));
+		assert!(!stick.matches(3, &grid(["ww.", "...", "..."])));
+		assert!(!stick.matches(3, &grid(["w..", "...", "w.."])));
+		assert!(!stick.matches(3, &grid(["w..", "w..", "w.."])));
+		assert!(!stick.matches(3, &grid(["w..", "s..", "..."])));
+		assert!(!stick.matches(3, &grid(["...", "...", "..."])));
+		
+		// patterns with spaces keep their shape
+		let hoe = shaped("hoe", &["ss", " w"], &[('s', "stone"), ('w', "wood")]);
+		assert!(hoe.matches(3, &grid(["...", ".ss", "..w"])));
+		assert!(!hoe.matches(3, &grid(["...", "ss.", "w.."])));
+	}
+	
+	#[test]
+	fn shapeless_recipes_count_ingredients() {
+		let mix = shapeless("mix", &[("wood", 2), ("stone", 1)]);
+		assert!(mix.matches(3, &grid(["w..", "..s", ".w."])));
+		assert!(mix.matches(3, &grid(["wws", "...", "..."])));
+		assert!(!mix.matches(3, &grid(["w..", "..s", "..."])));
+		assert!(!mix.matches(3, &grid(["ww.", "..s", "..w"])));
+		assert!(!mix.matches(3, &grid(["ww.", "..s", "..s"])));
+	}
+	
+	#[test]
+	fn ambiguous_grids() {
+		let grid = grid(["w..", "w..", "..."]);
+		let recipes = [
+			shapeless("a_shapeless", &[("wood", 2)]),
+			shaped("z_stick", &["w", "w"], &[('w', "wood")]),
+			shaped("b_stick", &["w", "w"], &[('w', "wood")]),
+			shaped("torch", &["s", "w"], &[('s', "stone"), ('w', "wood")]),
+		];
+		
+		// whatever order they're found in
+		for rotation in 0..recipes.len() {
+			let found = recipes
+				.iter()
+				.cycle()
+				.skip(rotation)
+				.take(recipes.len())
+				.filter(|def| def.matches(3, &grid))
+				.min_by(|a, b| a.cmp_precedence(b))
+				.unwrap();
+			assert_eq!(found.identifier(), &id("b_stick"));
+		}
+		
+		assert_eq!(recipes[0].cmp_precedence(&recipes[1]), Ordering::Greater);
+		assert_eq!(recipes[2].cmp_precedence(&recipes[1]), Ordering::Less);
+		assert_eq!(recipes[2].cmp_precedence(&recipes[2]), Ordering::Equal);
+	}
+}
@@ -1,7 +1,7 @@
 use bevy::{asset::{AssetLoader, LoadedAsset, Asset, io::Reader, AsyncReadExt}, reflect::{TypeUuid, TypePath}};
 use serde::Deserialize;
 
-use crate::{registry::tile::settings::TileSettings, identifier::Identifier, i18n::Translatable};
+use crate::{registry::tile::{growth::TileGrowth, liquid::TileLiquid, settings::TileSettings}, tile::entity::TileDataSchema, identifier::Identifier, i18n::Translatable};
 
 fn id_default() -> Identifier {
 	Identifier::from_str("null", "null")
@@ -11,6 +11,28 @@ fn trans_default() -> Translatable {
 	Translatable::from_str("null")
 }
 
+fn drop_count_default() -> u16 {
+	1
+}
+
+/// An item that is given to the player who breaks a tile.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileDrop {
+	item: Identifier,
+	#[serde(default = "drop_count_default")]
+	count: u16,
+}
+
+impl TileDrop {
+	pub fn item(&self) -> &Identifier {
+		&self.item
+	}
+	
+	pub fn count(&self) -> u16 {
+		self.count
+	}
+}
+
 #[derive(Debug, Deserialize, Asset, TypeUuid, TypePath)]
 #[uuid = "e9916291-4fef-4058-8b1b-d4e8f8a23aaf"]
 pub struct TileDef {
@@ -20,6 +42,17 @@ pub struct TileDef {
 	settings: TileSettings,
 	#[serde(default = "trans_default")]
 	name: Translatable,
+	#[serde(default)]
+	drops: Vec<TileDrop>,
+	/// How the tile grows when it is randomly ticked. Tiles without this are never ticked.
+	#[serde(default)]
+	growth: Option<TileGrowth>,
+	/// The fields of the tile's [TileEntity](crate::tile::entity::TileEntity). Tiles without this don't have one.
+	#[serde(default)]
+	data: Option<TileDataSchema>,
+	/// Makes the tile a liquid.
+	#[serde(default)]
+	liquid: Option<TileLiquid>,
 }
 
 impl TileDef {
@@ -28,6 +61,10 @@ impl TileDef {
 			name: Translatable::new(format!("{}:tile.name.{}", identifier.namespace(), identifier.path())),
 			identifier,
 			settings,
+			drops: vec![],
+			growth: None,
+			data: None,
+			liquid: None,
 		}
 	}
 	
@@ -43,6 +80,22 @@ impl TileDef {
 		&self.identifier
 	}
 	
+	pub fn drops(&self) -> &[TileDrop] {
+		&self.drops
+	}
+	
+	pub fn growth(&self) -> Option<&TileGrowth> {
+		self.growth.as_ref()
+	}
+	
+	pub fn data_schema(&self) -> Option<&TileDataSchema> {
+		self.data.as_ref()
+	}
+	
+	pub fn liquid(&self) -> Option<&TileLiquid> {
+		self.liquid.as_ref()
+	}
+	
 	pub fn is_air(&self) -> bool {
 		self.identifier.namespace() == "null" && self.identifier.path() == "air"
 	}
@@ -74,13 +127,23 @@ impl AssetLoader for TileDefLoader {
 			let default_path = load_context.path().file_name().expect("file path should not terminate with \"..\"").to_string_lossy().to_string().replace(".tile.ron", "");
 			let default_namespace = load_context.path().ancestors().nth(2).expect("tile definition file should be in directory \"<namespace>/tiles\"").file_name().expect("directory name should not end in ..").to_string_lossy().to_string();
 			if def.identifier.path() == "null" && def.identifier.namespace() == "null" {
-				def.identifier = Identifier::new(default_namespace, default_path);
+				def.identifier = Identifier::new(default_namespace.clone(), default_path);
 			}
 			
 			if def.name().key() == "null" {
 				def.name = Translatable::new(format!("{}:tile.name.{}", def.identifier.namespace(), def.identifier.path()));
 			}
 			
+			for drop in def.drops.iter_mut() {
+				if drop.item.namespace() == "null" {
+					drop.item = Identifier::new(default_namespace.clone(), drop.item.path().to_string());
+				}
+			}
+			
+			if let Some(growth) = def.growth.as_mut() {
+				growth.fill_namespaces(&default_namespace);
+			}
+			
 			Ok(def)
 		})
 	}
@@ -0,0 +1,262 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::client::networking::LocalPlayer;
+use crate::creature::player::Player;
+use crate::networking::protocol::ClientId;
+use crate::utils::math::TILE_SIZE_PX;
+use crate::world::{ChunkPos, ClientGameWorld, CHUNK_SIZE};
+use crate::{env, GameState, is_debug};
+
+/// The smallest zoom level (one screen pixel per texture pixel).
+pub const MIN_ZOOM: u8 = 1;
+/// The largest zoom level.
+pub const MAX_ZOOM: u8 = 8;
+/// The zoom level the camera starts at.
+pub const DEFAULT_ZOOM: u8 = 4;
+/// How quickly the camera catches up to its target. Higher is snappier.
+pub const FOLLOW_SHARPNESS: f32 = 8.0;
+/// The speed of the free camera in tiles per second at [DEFAULT_ZOOM].
+pub const FREE_CAM_SPEED: f32 = 32.0;
+
+/// Marker component for the main (world & UI) camera.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct MainCamera;
+
+/// The current zoom level of the camera.
+///
+/// Each level maps one texture pixel to `n` screen pixels, so sprites stay pixel-perfect at every step.
+#[derive(Resource, Debug, Copy, Clone, PartialEq, Eq, Deref)]
+pub struct CameraZoom(u8);
+
+impl Default for CameraZoom {
+	fn default() -> Self {
+		Self(DEFAULT_ZOOM)
+	}
+}
+
+impl CameraZoom {
+	/// Returns the [OrthographicProjection] scale for this zoom level.
+	#[inline]
+	pub fn scale(&self) -> f32 {
+		1.0 / self.0 as f32
+	}
+	
+	/// Returns the size of one screen pixel in world units at this zoom level.
+	#[inline]
+	pub fn pixel_size(&self) -> f32 {
+		1.0 / (TILE_SIZE_PX * self.0 as f32)
+	}
+}
+
+/// The area (in world units) that the camera may show, derived from the loaded chunks.
+#[derive(Resource, Debug, Default, Copy, Clone)]
+pub struct CameraBounds(pub Option<Rect>);
+
+/// Whether the camera is detached from the local player.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FreeCam(pub bool);
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<CameraZoom>()
+			.init_resource::<CameraBounds>()
+			.init_resource::<FreeCam>()
+			.add_systems(
+				Update,
+				(
+					zoom,
+					update_bounds,
+					follow_local_player
+						.run_if(resource_equals(FreeCam(false))),
+					free_cam
+						.run_if(resource_equals(FreeCam(true))),
+				)
+					.chain()
+					.run_if(in_state(GameState::InWorld))
+					.run_if(env::is_client)
+			)
+			.add_systems(
+				Update,
+				toggle_free_cam
+					.run_if(in_state(GameState::InWorld))
+					.run_if(is_debug)
+					.run_if(env::is_client)
+			)
+			.add_systems(OnExit(GameState::InWorld), reset_camera);
+	}
+}
+
+/// Steps the zoom level with the mouse wheel.
+fn zoom(
+	mut ev_mouse_wheel: EventReader<MouseWheel>,
+	mut camera_zoom: ResMut<CameraZoom>,
+	mut projection_query: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+	let mut steps = 0.0;
+	for event in ev_mouse_wheel.iter() {
+		steps += match event.unit {
+			MouseScrollUnit::Line => event.y,
+			MouseScrollUnit::Pixel => event.y / TILE_SIZE_PX,
+		};
+	}
+	
+	if steps != 0.0 {
+		let level = (camera_zoom.0 as f32 + steps.signum()).clamp(MIN_ZOOM as f32, MAX_ZOOM as f32);
+		camera_zoom.0 = level as u8;
+	}
+	
+	for mut projection in projection_query.iter_mut() {
+		if projection.scale != camera_zoom.scale() {
+			projection.scale = camera_zoom.scale();
+		}
+	}
+}
+
+/// Recomputes the [CameraBounds] whenever the loaded tiles change.
+fn update_bounds(
+	client_world: Option<Res<ClientGameWorld>>,
+	mut bounds: ResMut<CameraBounds>,
+) {
+	let Some(client_world) = client_world else {
+		return
+	};
+	
+	if !client_world.is_changed() {
+		return
+	}
+	
+	// tiles are centered on their position, so each chunk spans half a tile further in every direction
+	bounds.0 = client_world.chunk_bounds().map(|(min, max): (ChunkPos, ChunkPos)| {
+		let min = min.origin();
+		let max = max.origin();
+		Rect::new(
+			min.x as f32 - 0.5,
+			min.y as f32 - 0.5,
+			(max.x + CHUNK_SIZE) as f32 - 0.5,
+			(max.y + CHUNK_SIZE) as f32 - 0.5,
+		)
+	});
+}
+
+/// Smoothly moves the camera towards the local player and keeps it inside the [CameraBounds].
+fn follow_local_player(
+	time: Res<Time>,
+	camera_zoom: Res<CameraZoom>,
+	bounds: Res<CameraBounds>,
+	local_player_query: Query<&ClientId, With<LocalPlayer>>,
+	player_query: Query<(&ClientId, &Transform), (With<Player>, Without<MainCamera>)>,
+	mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+	let Ok(local_client_id) = local_player_query.get_single() else {
+		return
+	};
+	
+	let target = player_query
+		.iter()
+		.find_map(|(client_id, transform)| if client_id == local_client_id { Some(transform.translation.truncate()) } else { None });
+	let Some(target) = target else {
+		return
+	};
+	
+	for (mut camera_transform, projection) in camera_query.iter_mut() {
+		let current = camera_transform.translation.truncate();
+		let t = 1.0 - (-FOLLOW_SHARPNESS * time.delta_seconds()).exp();
+		let mut next = current.lerp(target, t);
+		
+		if let Some(bounds) = bounds.0 {
+			next = clamp_to_bounds(next, projection.area.size(), bounds);
+		}
+		
+		let next = snap_to_pixel(next, *camera_zoom);
+		camera_transform.translation.x = next.x;
+		camera_transform.translation.y = next.y;
+	}
+}
+
+/// Keeps a view of the given size centered at `center` inside `bounds`.
+///
+/// If the view is larger than the bounds along an axis, the view is centered on the bounds along that axis.
+pub fn clamp_to_bounds(center: Vec2, view_size: Vec2, bounds: Rect) -> Vec2 {
+	let half_view = view_size / 2.0;
+	let clamp_axis = |value: f32, min: f32, max: f32, half: f32| {
+		if max - min <= half * 2.0 {
+			(min + max) / 2.0
+		} else {
+			value.clamp(min + half, max - half)
+		}
+	};
+	
+	Vec2::new(
+		clamp_axis(center.x, bounds.min.x, bounds.max.x, half_view.x),
+		clamp_axis(center.y, bounds.min.y, bounds.max.y, half_view.y),
+	)
+}
+
+/// Rounds the position to the nearest screen pixel so that sprites don't shimmer while the camera moves.
+#[inline]
+pub fn snap_to_pixel(position: Vec2, zoom: CameraZoom) -> Vec2 {
+	let pixel_size = zoom.pixel_size();
+	(position / pixel_size).round() * pixel_size
+}
+
+fn toggle_free_cam(
+	keyboard_input: Res<Input<KeyCode>>,
+	mut free_cam: ResMut<FreeCam>,
+) {
+	if keyboard_input.just_pressed(KeyCode::F4) {
+		free_cam.0 = !free_cam.0;
+		println!("Free camera {}", if free_cam.0 { "enabled" } else { "disabled" });
+	}
+}
+
+/// Moves the camera with the arrow keys, ignoring the [CameraBounds].
+fn free_cam(
+	time: Res<Time>,
+	keyboard_input: Res<Input<KeyCode>>,
+	camera_zoom: Res<CameraZoom>,
+	mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+	let mut direction = Vec2::ZERO;
+	if keyboard_input.pressed(KeyCode::Left) {
+		direction.x -= 1.0;
+	}
+	if keyboard_input.pressed(KeyCode::Right) {
+		direction.x += 1.0;
+	}
+	if keyboard_input.pressed(KeyCode::Down) {
+		direction.y -= 1.0;
+	}
+	if keyboard_input.pressed(KeyCode::Up) {
+		direction.y += 1.0;
+	}
+	
+	if direction == Vec2::ZERO {
+		return
+	}
+	
+	let speed = FREE_CAM_SPEED * DEFAULT_ZOOM as f32 / camera_zoom.0 as f32;
+	for mut camera_transform in camera_query.iter_mut() {
+		let next = camera_transform.translation.truncate() + direction.normalize() * speed * time.delta_seconds();
+		let next = snap_to_pixel(next, *camera_zoom);
+		camera_transform.translation.x = next.x;
+		camera_transform.translation.y = next.y;
+	}
+}
+
+/// Puts the camera back at the origin once the player leaves the world.
+fn reset_camera(
+	mut free_cam: ResMut<FreeCam>,
+	mut bounds: ResMut<CameraBounds>,
+	mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+	free_cam.0 = false;
+	bounds.0 = None;
+	for mut camera_transform in camera_query.iter_mut() {
+		camera_transform.translation.x = 0.0;
+		camera_transform.translation.y = 0.0;
+	}
+}
@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::camera::MainCamera;
+use crate::cursor::cursor_tile_pos;
+use crate::networking::error::NetworkError;
+use crate::networking::protocol::ClientMessage;
+use crate::tile::layer::TileLayer;
+use crate::world::ClientGameWorld;
+
+use super::networking::{send_message, NetClient};
+
+/// Returns the layer that the player is interacting with: the background while Shift is held, otherwise the foreground.
+fn target_layer(keyboard_input: &Input<KeyCode>) -> TileLayer {
+	if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+		TileLayer::Background
+	} else {
+		TileLayer::Foreground
+	}
+}
+
+/// Asks the server to break the tile under the cursor when the left mouse button is clicked.
+///
+/// Overlay tiles are broken before anything in the targeted layer.
+pub fn break_tile(
+	mouse_input: Res<Input<MouseButton>>,
+	keyboard_input: Res<Input<KeyCode>>,
+	window_query: Query<&Window, With<PrimaryWindow>>,
+	camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+	client_world: Res<ClientGameWorld>,
+	mut client: NetClient,
+) -> Result<(), NetworkError> {
+	if !mouse_input.just_pressed(MouseButton::Left) {
+		return Ok(())
+	}
+	
+	let (Ok(window), Ok((camera, camera_transform))) = (window_query.get_single(), camera_query.get_single()) else {
+		return Ok(())
+	};
+	
+	let Some(pos) = cursor_tile_pos(window, camera, camera_transform) else {
+		return Ok(())
+	};
+	
+	let occupied = |layer: TileLayer| client_world.get_tile(layer, &pos).is_some_and(|tile| !tile.is_air());
+	let layer = if occupied(TileLayer::Overlay) { TileLayer::Overlay } else { target_layer(&keyboard_input) };
+	
+	// todo: hold to break according to the tile's hardness
+	if occupied(layer) {
+		send_message!(client, ClientMessage::BreakTile(layer, pos));
+	}
+	
+	Ok(())
+}
+
+/// Asks the server to place the held item's tile under the cursor when the right mouse button is clicked.
+///
+/// The server decides whether the held item can be placed there at all, and puts it in the layer of its salience.
+pub fn place_tile(
+	mouse_input: Res<Input<MouseButton>>,
+	keyboard_input: Res<Input<KeyCode>>,
+	window_query: Query<&Window, With<PrimaryWindow>>,
+	camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+	mut client: NetClient,
+) -> Result<(), NetworkError> {
+	if !mouse_input.just_pressed(MouseButton::Right) {
+		return Ok(())
+	}
+	
+	let (Ok(window), Ok((camera, camera_transform))) = (window_query.get_single(), camera_query.get_single()) else {
+		return Ok(())
+	};
+	
+	let Some(pos) = cursor_tile_pos(window, camera, camera_transform) else {
+		return Ok(())
+	};
+	
+	send_message!(client, ClientMessage::PlaceTile(target_layer(&keyboard_input), pos));
+	
+	Ok(())
+}
@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+
+use crate::asset::tile::TileDef;
+use crate::raw_id::tile::RawTileIds;
+use crate::registry::tile::TileRegistry;
+use crate::tile::TileSprite;
+use crate::tile::light::MAX_LIGHT;
+use crate::world::{ClientGameWorld, SetTileEvent};
+use crate::TilePos;
+
+/// How bright a tile with no light at all is drawn, so that dark areas aren't pitch black.
+pub const MIN_BRIGHTNESS: f32 = 0.08;
+
+/// Advances the world time between syncs from the server.
+pub fn advance_world_time(
+	time: Res<Time>,
+	mut client_world: ResMut<ClientGameWorld>,
+) {
+	client_world.time.advance(time.delta());
+}
+
+/// Updates the light around every tile that has just been set.
+pub fn update_light(
+	mut client_world: ResMut<ClientGameWorld>,
+	mut ev_set_tile: EventReader<SetTileEvent>,
+	tile_registry: Res<TileRegistry>,
+	raw_tile_ids: Res<RawTileIds>,
+	tile_def_assets: Res<Assets<TileDef>>,
+) {
+	let positions: Vec<TilePos> = ev_set_tile.iter().map(|event| event.pos).collect();
+	if positions.is_empty() {
+		return
+	}
+	
+	let settings = |raw_id| {
+		let def = tile_def_assets.get(&tile_registry.get(raw_tile_ids.get_id(raw_id)?)?)?;
+		Some(*def.settings())
+	};
+	let ClientGameWorld { tiles, light, .. } = &mut *client_world;
+	light.update_many(tiles, positions, &settings);
+}
+
+/// Tints tile sprites according to their light level and the time of day.
+/// 
+/// Every sprite is only retinted when the light or daylight changes; new sprites are tinted right away.
+pub fn tint_tiles(
+	client_world: Res<ClientGameWorld>,
+	mut sprite_query: Query<(&Transform, &mut Sprite, Ref<TileSprite>)>,
+	mut last_tint: Local<Option<(u64, u8)>>,
+) {
+	let daylight = client_world.time.daylight();
+	let tint = (client_world.light.revision(), daylight);
+	let retint_all = *last_tint != Some(tint);
+	*last_tint = Some(tint);
+	
+	for (transform, mut sprite, tile_sprite) in sprite_query.iter_mut() {
+		if !retint_all && !tile_sprite.is_added() {
+			continue
+		}
+		
+		let pos = TilePos {
+			x: transform.translation.x.round() as i32,
+			y: transform.translation.y.round() as i32,
+		};
+		let level = client_world.light.surface(&pos).with_daylight(daylight);
+		let brightness = MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * level as f32 / MAX_LIGHT as f32;
+		sprite.color = Color::rgb(brightness, brightness, brightness);
+	}
+}
@@ -1 +1,4 @@
 pub mod networking;
+pub mod interaction;
+pub mod light;
+pub mod servers;
@@ -2,26 +2,39 @@ use std::net::SocketAddr;
 use std::net::UdpSocket;
 use std::time::{Duration, Instant};
 
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::render::texture::DEFAULT_IMAGE_HANDLE;
 use bevy_renet::RenetClientPlugin;
-use bevy_renet::transport::NetcodeClientPlugin;
-use renet::{ConnectionConfig, DefaultChannel, RenetClient};
+use renet::{Bytes, RenetClient};
 use renet::transport::{ClientAuthentication, NetcodeClientTransport};
 
 use crate::asset::tile::TileDef;
+use crate::client::interaction::{break_tile, place_tile};
+use crate::client::light::{advance_world_time, tint_tiles, update_light};
 use crate::creature::player::PLAYER_Z;
 use crate::creature::player::Player;
 use crate::creature::player::SPAWN_PLAYER_EVENT_ERROR_MESSAGE;
+use crate::creature::player::PLAYER_DECORATION_ERROR_MESSAGE;
 use crate::creature::player::SpawnPlayerEvent;
 use crate::creature::player::spawn_player_event;
+use crate::creature::player::player_decoration;
+use crate::creature::player::update_name_tags;
 use crate::networking;
+use crate::menu::inventory::InventoryOpen;
+use crate::networking::channel::{self, Channel};
+use crate::networking::conditioner::{ConditionerProxy, NetworkConditioner, NetworkConditions};
+use crate::networking::envelope::{Encoder, Reassembler};
+use crate::networking::handshake::{Capabilities, Handshake};
 use crate::networking::protocol::Packet;
+use crate::networking::transport::{ClientTransport, ClientTransportPlugin, MemoryNetwork};
 use crate::raw_id::tile::RawTileIds;
 use crate::registry::tile::TileRegistry;
+use crate::registry::tile::liquid::MAX_LIQUID_LEVEL;
 use crate::registry::tile::settings::TileSalience;
+use crate::server::integrated::stop_integrated_server_on_exit;
 use crate::server::networking::Players;
-use crate::tile::WorldTile;
+use crate::tile::{TileSprite, WorldTile};
 use crate::utils::asset::load_image;
 use crate::utils::math::HANDLE_TO_SCALE_ERROR_MESSAGE;
 use crate::utils::math::handle_to_scale;
@@ -29,12 +42,12 @@ use crate::world::ClientGameWorld;
 use crate::world::SetTileEvent;
 use crate::world::TILE_EVENT_ERROR_MESSAGE;
 use crate::world::TileEventError;
-use crate::{env, GameState, ServerConnectAddress, utils};
+use crate::{env, GameState, is_debug, ServerConnectAddress};
 use crate::networking::{DisconnectReason, Ping, protocol, time_since_epoch, Username};
 use crate::networking::error::{NETWORK_ERROR_MESSAGE, NetworkError};
-use crate::networking::protocol::{PlayerData, ServerMessage, ServerResponse};
+use crate::networking::protocol::{PlayerData, RosterEntry, ServerMessage, ServerResponse};
 use crate::player::Target;
-use crate::utils::nonfatal_error_systems;
+use crate::utils::{BevyHashMap, nonfatal_error_systems, strip_formatting};
 
 pub struct NetworkingPlugin;
 
@@ -42,8 +55,15 @@ impl Plugin for NetworkingPlugin {
 	fn build(&self, app: &mut App) {
 		app
 			.add_plugins(RenetClientPlugin)
-			.add_plugins(NetcodeClientPlugin)
+			.add_plugins(ClientTransportPlugin)
 			.init_resource::<ServerConnectAddress>()
+			.init_resource::<Reassembler>()
+			.init_resource::<Encoder>()
+			.add_systems(
+				Startup,
+				setup_conditioner
+					.run_if(env::is_client)
+			)
 			.add_systems(
 				OnEnter(GameState::ClientConnecting),
 				setup
@@ -72,8 +92,16 @@ impl Plugin for NetworkingPlugin {
 			.add_systems(
 				Update,
 				(
-					nonfatal_error_systems!(TILE_EVENT_ERROR_MESSAGE, TileEventError, set_tile_event),
+					(
+						nonfatal_error_systems!(TILE_EVENT_ERROR_MESSAGE, TileEventError, set_tile_event),
+						update_light,
+						advance_world_time,
+						tint_tiles,
+					)
+						.chain(),
 					nonfatal_error_systems!(SPAWN_PLAYER_EVENT_ERROR_MESSAGE, anyhow::Error, spawn_player_event),
+					nonfatal_error_systems!(PLAYER_DECORATION_ERROR_MESSAGE, anyhow::Error, player_decoration),
+					update_name_tags,
 					nonfatal_error_systems!(HANDLE_TO_SCALE_ERROR_MESSAGE, anyhow::Error, handle_to_scale),
 				)
 					.run_if(
@@ -81,22 +109,57 @@ impl Plugin for NetworkingPlugin {
 							.or_else(in_state(GameState::LoadingWorld))
 					)
 					.run_if(env::is_client)
+			)
+			.add_systems(
+				Update,
+				nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, break_tile, place_tile)
+					.run_if(in_state(GameState::InWorld))
+					.run_if(resource_equals(InventoryOpen(false)))
+					.run_if(env::is_client)
+			)
+			.add_systems(
+				Last,
+				stop_integrated_server_on_exit
+					.run_if(env::is_client)
 			);
 	}
 }
 
 macro_rules! send_message {
-    ($client:expr, $channel_id:expr, $message:expr) => {
+    ($client:expr, $message:expr) => {
 		{
-			$crate::utils::struct_enforce!($client, renet::RenetClient, ResMut<'_, renet::RenetClient>);
-			$crate::utils::trait_enforce!($channel_id, Into<u8>);
-			$client.send_message($channel_id, TryInto::<renet::Bytes>::try_into($message)?);
+			$crate::utils::struct_enforce!($client, $crate::client::networking::NetClient<'_>);
+			let packet = $crate::networking::protocol::Packet::from($message);
+			let channel = $crate::networking::channel::MessageChannel::channel(&packet);
+			for payload in $client.encode(&packet, channel)? {
+				$client.send_message(channel, payload);
+			}
 		}
 	};
 }
 
 pub(crate) use send_message;
 
+/// The [RenetClient], along with the [Encoder] that everything sent to the server goes through.
+#[derive(SystemParam, Deref, DerefMut)]
+pub struct NetClient<'w> {
+	#[deref]
+	client: ResMut<'w, RenetClient>,
+	encoder: ResMut<'w, Encoder>,
+	/// The handshake negotiated with the server, once it has accepted the join request.
+	handshake: Option<Res<'w, Handshake>>,
+}
+
+impl<'w> NetClient<'w> {
+	/// Encodes the packet with the capabilities negotiated with the server.
+	pub fn encode(&mut self, packet: &Packet, channel: Channel) -> Result<Vec<Bytes>, NetworkError> {
+		let capabilities = self.handshake
+			.as_ref()
+			.map_or(Capabilities::NONE, |handshake| handshake.capabilities);
+		self.encoder.encode(packet, channel, capabilities)
+	}
+}
+
 use networking::protocol::ClientId;
 
 #[derive(Debug, Default, Component)]
@@ -115,6 +178,49 @@ struct ConnectedTime(Instant);
 #[derive(Debug, Resource)]
 struct ConnectingTimeout(Duration);
 
+/// Turns on the network conditioner if [CONDITIONS_ENV_VAR](networking::conditioner::CONDITIONS_ENV_VAR) is set, or with
+/// perfect conditions in debug builds so that they can be changed from the debug overlay.
+fn setup_conditioner(
+	mut commands: Commands,
+) {
+	let conditions = match NetworkConditions::from_env() {
+		Some(Ok(conditions)) => conditions,
+		Some(Err(err)) => {
+			eprintln!("Ignoring invalid network conditions: {}", err);
+			return
+		},
+		None if is_debug() => NetworkConditions::default(),
+		None => return,
+	};
+	
+	println!("Simulating network conditions: {:?}", conditions);
+	commands.insert_resource(NetworkConditioner::new(conditions));
+}
+
+/// The smoothed round-trip time of every player, in milliseconds, as last reported by the server.
+#[derive(Debug, Default, Clone, Deref, Resource)]
+pub struct PlayerPings(BevyHashMap<ClientId, u32>);
+
+/// Every player on the server, whether or not they're in our world, as last reported by the server.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct Roster(BevyHashMap<ClientId, RosterEntry>);
+
+impl Roster {
+	pub fn get(&self, client_id: ClientId) -> Option<&RosterEntry> {
+		self.0.get(&client_id)
+	}
+	
+	pub fn iter(&self) -> impl Iterator<Item = &RosterEntry> {
+		self.0.values()
+	}
+	
+	/// Returns the name to show for the player: their nickname if they have one, otherwise their username.
+	pub fn display_name(&self, client_id: ClientId) -> Option<&str> {
+		let entry = self.0.get(&client_id)?;
+		Some(entry.nickname.as_deref().unwrap_or(&entry.data.username.0))
+	}
+}
+
 /// Indicates that the connection has been established and that we have already sent the initial packets.
 #[derive(Debug, Default, Resource)]
 struct ConnectionEstablished;
@@ -122,10 +228,12 @@ struct ConnectionEstablished;
 fn setup(
 	server_address: Res<ServerConnectAddress>,
 	username: Res<Username>,
+	memory_network: Option<Res<MemoryNetwork>>,
+	conditioner: Option<Res<NetworkConditioner>>,
 	mut commands: Commands,
 	mut next_state: ResMut<NextState<GameState>>,
 ) {
-	let connection_config = ConnectionConfig::default();
+	let connection_config = channel::connection_config();
 	
 	let client_id = time_since_epoch().as_millis() as u64;
 	
@@ -140,45 +248,81 @@ fn setup(
 		}
 	);
 	
-	let client_addr = "0.0.0.0:0"; // request dynamic port
-	let socket = UdpSocket::bind(&client_addr).expect(&format!("Failed to bind to address \"{}\"", client_addr)); // fixme: kick to disconnect screen
-	let current_time = time_since_epoch();
-	let server_addr: Option<SocketAddr> = { // weird hack
-		// basically what this does is it goes to the disconnect screen if there's an error parsing the address
-		let server_address = server_address.into_inner();
-		let res = server_address.try_into();
-		match res {
-			Ok(addr) => Some(addr),
-			Err(error) => {
-				commands.insert_resource(DisconnectReason::AddrParseError(error));
-				next_state.set(GameState::TitleScreen); // todo: disconnect screen
-				return;
-			},
-		}
-	};
-	let server_addr: SocketAddr = server_addr.unwrap(); // should've returned if none, so we can unwrap
-	
-	// todo: authentication
-	let authentication = ClientAuthentication::Unsecure {
-		protocol_id: protocol::PROTOCOL_ID,
-		client_id,
-		server_addr,
-		user_data: Some(username.to_user_data()),
+	let transport = match memory_network {
+		Some(memory_network) => {
+			let transport = memory_network.connect(client_id, username.to_user_data());
+			ClientTransport::Memory(match conditioner {
+				Some(conditioner) => transport.conditioned(conditioner.clone()),
+				None => transport,
+			})
+		},
+		None => {
+			let client_addr = "0.0.0.0:0"; // request dynamic port
+			let socket = UdpSocket::bind(&client_addr).expect(&format!("Failed to bind to address \"{}\"", client_addr)); // fixme: kick to disconnect screen
+			let current_time = time_since_epoch();
+			let server_addr: Option<SocketAddr> = { // weird hack
+				// basically what this does is it goes to the disconnect screen if there's an error parsing the address
+				let server_address = server_address.into_inner();
+				let res = server_address.try_into();
+				match res {
+					Ok(addr) => Some(addr),
+					Err(error) => {
+						commands.insert_resource(DisconnectReason::AddrParseError(error));
+						next_state.set(GameState::TitleScreen); // todo: disconnect screen
+						return;
+					},
+				}
+			};
+			let server_addr: SocketAddr = server_addr.unwrap(); // should've returned if none, so we can unwrap
+			
+			// a proxy from an earlier connection is replaced, which stops it
+			let proxy = conditioner.and_then(|conditioner| {
+				ConditionerProxy::start(server_addr, conditioner.clone())
+					.map_err(|err| eprintln!("Failed to start the network conditioner, connecting directly: {}", err))
+					.ok()
+			});
+			
+			// todo: authentication
+			let authentication = match proxy {
+				Some(proxy) => {
+					let authentication = proxy
+						.authentication(current_time, client_id, server_addr, &username.to_user_data())
+						.expect("Failed to generate a connect token for the network conditioner");
+					commands.insert_resource(proxy);
+					authentication
+				},
+				None => ClientAuthentication::Unsecure {
+					protocol_id: protocol::PROTOCOL_ID,
+					client_id,
+					server_addr,
+					user_data: Some(username.to_user_data()),
+				},
+			};
+			
+			ClientTransport::Netcode(NetcodeClientTransport::new(current_time, authentication, socket).expect("Failed to initialize NetcodeClientTransport"))
+		},
 	};
 	
 	let client = RenetClient::new(connection_config);
-	let transport = NetcodeClientTransport::new(current_time, authentication, socket).expect("Failed to initialize NetcodeClientTransport");
 	
 	commands.insert_resource(client);
 	commands.insert_resource(transport);
 	commands.insert_resource(ConnectedTime(Instant::now()));
 	commands.insert_resource(ConnectingTimeout(Duration::from_millis(protocol::CLIENT_TIMEOUT)));
 	commands.init_resource::<Players>();
+	commands.insert_resource(PlayerPings::default());
+	commands.insert_resource(Roster::default());
+	// fragments from an earlier connection will never be completed
+	commands.insert_resource(Reassembler::default());
+	// nor does anything negotiated with an earlier server still hold
+	commands.remove_resource::<Handshake>();
 }
 
 fn connecting(
 	client: Option<ResMut<RenetClient>>,
-	transport: Option<ResMut<NetcodeClientTransport>>,
+	encoder: ResMut<Encoder>,
+	mut reassembler: ResMut<Reassembler>,
+	transport: Option<ResMut<ClientTransport>>,
 	mut commands: Commands,
 	mut next_state: ResMut<NextState<GameState>>,
 	connected_time: Option<Res<ConnectedTime>>,
@@ -196,23 +340,24 @@ fn connecting(
 		return Ok(())
 	}
 	
-	let mut client = client.unwrap();
+	// the join request goes out before anything has been negotiated
+	let mut client = NetClient { client: client.unwrap(), encoder, handshake: None };
 	let transport = transport.unwrap();
 	let connected_time = connected_time.unwrap();
 	let connecting_timeout = connecting_timeout.unwrap();
 	
 	if connected_time.0.elapsed() >= connecting_timeout.0 {
-		on_disconnect(DisconnectReason::Transport(renet::transport::NetcodeDisconnectReason::ConnectionTimedOut), next_state.into_inner(), transport.into_inner(), client.into_inner());
+		on_disconnect(DisconnectReason::Transport(renet::transport::NetcodeDisconnectReason::ConnectionTimedOut), next_state.into_inner(), transport.into_inner(), &mut client);
 		return Ok(())
 	}
 	
 	if let Some(reason) = transport.disconnect_reason() {
-		on_disconnect(DisconnectReason::Transport(reason), next_state.into_inner(), transport.into_inner(), client.into_inner());
+		on_disconnect(DisconnectReason::Transport(reason), next_state.into_inner(), transport.into_inner(), &mut client);
 		return Ok(())
 	}
 	
 	if let Some(reason) = client.disconnect_reason() {
-		on_disconnect(DisconnectReason::Client(reason), next_state.into_inner(), transport.into_inner(), client.into_inner());
+		on_disconnect(DisconnectReason::Client(reason), next_state.into_inner(), transport.into_inner(), &mut client);
 		return Ok(())
 	}
 	
@@ -220,22 +365,43 @@ fn connecting(
 		if connection_established.is_none() {
 			commands.init_resource::<ConnectionEstablished>();
 			println!("Connection established");
-			send_message!(client, DefaultChannel::ReliableOrdered, protocol::ClientMessage::JoinRequest { protocol_ver: protocol::PROTOCOL_VER });
+			send_message!(client, protocol::ClientMessage::JoinRequest(Handshake::local()));
 		} else {
-			if let Some(buf) = client.receive_message(DefaultChannel::ReliableOrdered) {
-				let packet = utils::deserialize_be::<protocol::Packet>(&buf)?;
+			// only handle one whole packet per frame, since it may change the state
+			let mut packet = None;
+			while let Some(buf) = client.receive_message(Channel::Control) {
+				packet = reassembler.decode(&buf)?;
+				if packet.is_some() {
+					break
+				}
+			}
+			
+			if let Some(packet) = packet {
 				match packet {
 					protocol::Packet::ServerMessage(message) => {
 						match message {
-							ServerMessage::Disconnect(reason) => on_disconnect(DisconnectReason::Disconnected(reason), next_state.into_inner(), transport.into_inner(), client.into_inner()),
+							ServerMessage::Disconnect(reason) => on_disconnect(DisconnectReason::Disconnected(reason), next_state.into_inner(), transport.into_inner(), &mut client),
 							ServerMessage::RawTileIds(raw_tile_ids) => commands.insert_resource(raw_tile_ids),
+							ServerMessage::RawItemIds(raw_item_ids) => commands.insert_resource(raw_item_ids),
 							_ => warn!("Unexpected message from server: {:?}", message),
 						}
 					},
 					protocol::Packet::ServerResponse(response) => {
 						match response {
-							ServerResponse::JoinAccept => next_state.set(GameState::WorldSelect),
-							ServerResponse::JoinDeny(reason) => on_disconnect(DisconnectReason::Disconnected(reason), next_state.into_inner(), transport.into_inner(), client.into_inner()),
+							ServerResponse::JoinAccept(handshake) => {
+								if Handshake::local().accepts(&handshake) {
+									println!("Joined server running {} (protocol {}, capabilities: {})", handshake.version_string, handshake.protocol_ver, handshake.capabilities);
+									commands.insert_resource(handshake);
+									next_state.set(GameState::WorldSelect);
+								} else {
+									let reason = protocol::DisconnectReason::ProtocolReject {
+										required_protocol_ver: handshake.protocol_ver,
+										required_version_string: handshake.version_string,
+									};
+									on_disconnect(DisconnectReason::Disconnected(reason), next_state.into_inner(), transport.into_inner(), &mut client);
+								}
+							},
+							ServerResponse::JoinDeny(reason) => on_disconnect(DisconnectReason::Disconnected(reason), next_state.into_inner(), transport.into_inner(), &mut client),
 							_ => warn!("Unexpected message from server: {:?}", response),
 						}
 					},
@@ -250,7 +416,8 @@ fn connecting(
 
 fn client(
 	mut client: ResMut<RenetClient>,
-	transport: ResMut<NetcodeClientTransport>,
+	mut reassembler: ResMut<Reassembler>,
+	transport: ResMut<ClientTransport>,
 	mut commands: Commands,
 	next_state: ResMut<NextState<GameState>>,
 ) -> Result<(), NetworkError> {
@@ -264,12 +431,12 @@ fn client(
 		return Ok(())
 	}
 	
-	for channel_id in 0..=2 {
-		while let Some(buf) = client.receive_message(channel_id) {
-			let packet = utils::deserialize_be::<Packet>(&buf);
+	for channel in Channel::ALL {
+		while let Some(buf) = client.receive_message(channel) {
+			let packet = reassembler.decode(&buf);
 			
 			// allow processing all packets regardless of error
-			if let Ok(packet) = packet {
+			if let Ok(Some(packet)) = packet {
 				match packet {
 					Packet::ServerMessage(_) => {
 						commands.spawn(packet);
@@ -290,16 +457,18 @@ fn client(
 
 fn receive_message(
 	message_query: Query<(Entity, &Packet)>,
-	mut client: ResMut<RenetClient>,
+	mut client: NetClient,
 	mut commands: Commands,
 	mut next_state: ResMut<NextState<GameState>>,
-	mut transport: ResMut<NetcodeClientTransport>,
+	mut transport: ResMut<ClientTransport>,
 	raw_tile_ids: Res<RawTileIds>,
 	mut ev_set_tile: EventWriter<SetTileEvent>,
 	mut spawn_player_event: EventWriter<SpawnPlayerEvent>,
 	mut client_world: Option<ResMut<ClientGameWorld>>,
-	players: Res<Players>,
+	mut players: ResMut<Players>,
+	mut roster: ResMut<Roster>,
 	mut player_transform_query: Query<&mut Transform, With<Player>>,
+	local_player_query: Query<Entity, With<LocalPlayer>>,
 ) -> Result<(), NetworkError> {
 	for (entity, packet) in message_query.iter() {
 		commands.entity(entity).despawn();
@@ -307,39 +476,97 @@ fn receive_message(
 		if let Packet::ServerMessage(message) = packet {
 			match message {
 				ServerMessage::Ping { timestamp } => {
-					send_message!(client, DefaultChannel::ReliableOrdered, protocol::ClientResponse::PingAck { timestamp: *timestamp });
+					send_message!(client, protocol::ClientResponse::PingAck { timestamp: *timestamp });
 				},
 				ServerMessage::ChatMessage(chat_message) => {
+					let source = chat_message.source.render(|client_id| roster.display_name(client_id).map(str::to_string));
+					println!("{}", strip_formatting(&format!("{} {}", source, chat_message.content.0)));
 					commands.spawn(chat_message.clone());
 				},
 				ServerMessage::Disconnect(reason) => {
 					on_disconnect(networking::DisconnectReason::Disconnected(reason.clone()), &mut next_state, &mut transport, &mut client);
 				},
-				ServerMessage::WorldTiles(tiles) => {
-					for (pos, tile) in tiles.clone() {
-						let id = raw_tile_ids.get_id(tile.0);
-						if id.is_none() {
+				ServerMessage::TileUpdateBatch(batch) => {
+					for (layer, pos, tile) in batch.tiles()? {
+						let Some(id) = raw_tile_ids.get_id(tile.0) else {
 							return Err(NetworkError::TileEventError(TileEventError::InvalidRawId(tile.0, pos)))
-						}
+						};
 						
 						ev_set_tile.send(
 							SetTileEvent {
+								layer,
 								pos,
-								id: id.unwrap().clone(),
+								id: id.clone(),
 								data: tile.1,
 							}
 						);
 					}
 				},
-				ServerMessage::PlayerJoin(client_id, data, spawnpoint) => {
+				ServerMessage::TileEntities(chunk_pos, tile_entities) => {
+					if let Some(client_world) = client_world.as_mut() {
+						client_world.tile_entities.set_chunk(*chunk_pos, tile_entities.clone());
+					}
+				},
+				ServerMessage::SetTileEntity(layer, pos, tile_entity) => {
+					if let Some(client_world) = client_world.as_mut() {
+						match tile_entity {
+							Some(tile_entity) => {
+								client_world.tile_entities.insert(*layer, *pos, tile_entity.clone());
+							},
+							None => {
+								client_world.tile_entities.remove(*layer, pos);
+							},
+						}
+					}
+				},
+				ServerMessage::Inventory(inventory) => {
+					if let Ok(local_player) = local_player_query.get_single() {
+						commands.entity(local_player).insert(inventory.clone());
+					}
+				},
+				ServerMessage::WorldTime(time) => {
+					if let Some(client_world) = client_world.as_mut() {
+						client_world.time = *time;
+					}
+				},
+				ServerMessage::PlayerJoin(client_id, data, position) => {
 					spawn_player_event.send(
 						SpawnPlayerEvent {
-							transform: Transform::from_xyz(spawnpoint.x, spawnpoint.y, PLAYER_Z),
+							transform: Transform::from_xyz(position.x, position.y, PLAYER_Z),
 							id: *client_id,
 							data: data.clone(),
 						}
 					);
 				},
+				ServerMessage::PlayerLeave(client_id) => {
+					if let Some(player_entity) = players.0.remove(client_id) {
+						if let Some(player_commands) = commands.get_entity(player_entity) {
+							player_commands.despawn_recursive();
+						}
+					}
+				},
+				ServerMessage::Roster(entries) => {
+					roster.0 = entries.iter().map(|entry| (entry.client_id, entry.clone())).collect();
+				},
+				ServerMessage::RosterJoin(entry) => {
+					roster.0.insert(entry.client_id, entry.clone());
+				},
+				ServerMessage::RosterLeave(client_id) => {
+					roster.0.remove(client_id);
+				},
+				ServerMessage::RosterWorld(client_id, world) => {
+					if let Some(entry) = roster.0.get_mut(client_id) {
+						entry.world = world.clone();
+					}
+				},
+				ServerMessage::PlayerNick(client_id, nickname) => {
+					if let Some(entry) = roster.0.get_mut(client_id) {
+						entry.nickname = (!nickname.is_empty()).then(|| nickname.clone());
+					}
+				},
+				ServerMessage::PlayerPings(pings) => {
+					commands.insert_resource(PlayerPings(pings.iter().copied().collect()));
+				},
 				ServerMessage::PlayerPosition(client_id, position) => {
 					if let Some(&player_entity) = players.get(client_id) {
 						if let Ok(mut transform) = player_transform_query.get_mut(player_entity) {
@@ -366,6 +593,34 @@ fn receive_message(
 					commands.remove_resource::<ClientGameWorld>();
 					println!("Failed to enter world. Reason: {reason:?}");
 				},
+				ServerResponse::LeaveWorldAccept => {
+					// nothing more about the world comes after this, so it can all go
+					if let Some(client_world) = client_world.as_ref() {
+						for tile_sprite in client_world.tile_sprites.values() {
+							if let Some(sprite_commands) = commands.get_entity(*tile_sprite) {
+								sprite_commands.despawn_recursive();
+							}
+						}
+					}
+					commands.remove_resource::<ClientGameWorld>();
+					
+					for (_, player_entity) in players.0.drain() {
+						if let Some(player_commands) = commands.get_entity(player_entity) {
+							player_commands.despawn_recursive();
+						}
+					}
+					
+					next_state.set(GameState::WorldSelect);
+				},
+				ServerResponse::CraftAccept(recipe) => {
+					println!("Crafted {recipe}");
+				},
+				ServerResponse::CraftDeny(reason) => {
+					println!("Failed to craft. Reason: {reason:?}");
+				},
+				ServerResponse::NickDeny(reason) => {
+					println!("Failed to change nickname: {reason}");
+				},
 				_ => {},
 			}
 		}
@@ -385,8 +640,9 @@ fn set_tile_event(
 ) -> Result<(), TileEventError> {
 	for event in ev_set_tile.iter() {
 		// clear current tile
-		client_world.tiles.remove(&event.pos);
-		let tile_sprite = client_world.tile_sprites.remove(&event.pos);
+		client_world.tiles.remove(event.layer, &event.pos);
+		client_world.tile_entities.remove(event.layer, &event.pos);
+		let tile_sprite = client_world.tile_sprites.remove(&(event.layer, event.pos));
 		if let Some(tile_sprite) = tile_sprite {
 			let sprite_commands = commands.get_entity(tile_sprite);
 			if let Some(sprite_commands) = sprite_commands {
@@ -394,6 +650,11 @@ fn set_tile_event(
 			}
 		}
 		
+		// air only needs the old tile cleared
+		if raw_tile_ids.get_raw_id(&event.id).is_some_and(|raw_id| raw_id.is_air()) {
+			continue
+		}
+		
 		let def_handle = tile_registry.get(&event.id);
 		if def_handle.is_none() {
 			return Err(TileEventError::TileDefNotFound(event.id.clone(), event.pos))
@@ -411,29 +672,43 @@ fn set_tile_event(
 				return Err(TileEventError::InvalidId(event.id.clone(), event.pos))
 			}
 			
-			client_world.tiles.insert(event.pos.clone(), WorldTile(raw_id.unwrap(), event.data.clone()));
+			client_world.tiles.insert(event.layer, event.pos, WorldTile(raw_id.unwrap(), event.data.clone()));
 			
 			// don't render the tile if it's invisible
 			if def.settings().salience() == TileSalience::Invisible {
-				return Ok(())
+				continue
 			}
 			
 			let mut tile_image_handle: Handle<Image> = DEFAULT_IMAGE_HANDLE.typed();
 			if !def.is_missingno() { // if it isn't missingno, we can safely load the image
-				tile_image_handle = load_image(&asset_server, format!("{}/textures/tile/{}.png", event.id.namespace(), event.id.path()));
+				// tiles with several growth stages have a texture for each stage
+				let texture = match def.growth() {
+					Some(growth) if growth.stages() > 1 => format!("{}_{}", event.id.path(), event.data.0),
+					_ => event.id.path().to_string(),
+				};
+				tile_image_handle = load_image(&asset_server, format!("{}/textures/tile/{}.png", event.id.namespace(), texture));
 			}
 			
-			commands.spawn(
+			// liquids only fill the bottom of the tile, depending on their level
+			let height = match def.liquid() {
+				Some(_) => (event.data.0.min(MAX_LIQUID_LEVEL) as f32 / MAX_LIQUID_LEVEL as f32).max(1.0 / MAX_LIQUID_LEVEL as f32),
+				None => 1.0,
+			};
+			
+			let tile_sprite = commands.spawn(
 				SpriteBundle {
 					texture: tile_image_handle,
-					transform: Transform::from_xyz(event.pos.x as f32, event.pos.y as f32, def.settings().salience().into_z()),
+					transform: Transform::from_xyz(event.pos.x as f32, event.pos.y as f32 - (1.0 - height) / 2.0, event.layer.into_z()),
 					sprite: Sprite {
-						custom_size: Some(Vec2::new(1.0, 1.0)),
+						custom_size: Some(Vec2::new(1.0, height)),
 						..default()
 					},
 					..default()
 				}
-			);
+			)
+				.insert(TileSprite)
+				.id();
+			client_world.tile_sprites.insert((event.layer, event.pos), tile_sprite);
 		}
 	}
 	
@@ -441,15 +716,15 @@ fn set_tile_event(
 }
 
 pub fn send_chat(
-	mut client: ResMut<RenetClient>,
+	mut client: NetClient,
 	target: Target,
 	message: String,
 ) -> Result<(), NetworkError> {
-	send_message!(client, 0, protocol::ClientMessage::ChatMessage(target, message));
+	send_message!(client, protocol::ClientMessage::ChatMessage(target, message));
 	Ok(())
 }
 
-pub fn disconnect(reason: DisconnectReason, transport: &mut NetcodeClientTransport, client: &mut RenetClient, disconnect_client: bool) {
+pub fn disconnect(reason: DisconnectReason, transport: &mut ClientTransport, client: &mut RenetClient, disconnect_client: bool) {
 	if disconnect_client {
 		transport.disconnect();
 		client.disconnect_due_to_transport();
@@ -457,7 +732,7 @@ pub fn disconnect(reason: DisconnectReason, transport: &mut NetcodeClientTranspo
 	println!("Disconnected.\nReason: {}", reason);
 }
 
-fn on_disconnect(reason: DisconnectReason, next_state: &mut NextState<GameState>, transport: &mut NetcodeClientTransport, client: &mut RenetClient) {
+fn on_disconnect(reason: DisconnectReason, next_state: &mut NextState<GameState>, transport: &mut ClientTransport, client: &mut RenetClient) {
 	disconnect(reason, transport, client, false);
 	next_state.set(GameState::TitleScreen); // todo: disconnect screen
 }
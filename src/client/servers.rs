@@ -0,0 +1,210 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::networking::query::{ServerStatus, StatusQuery};
+use crate::utils::BevyHashMap;
+
+/// The directory that client settings are kept in, relative to the working directory.
+pub const CONFIG_DIR: &'static str = "config";
+
+const SERVER_LIST_FILE: &'static str = "servers.ron";
+
+/// How long to wait for a server to answer a status query.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ServerListError {
+	#[error("I/O error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("error during serialization: {0}")]
+	Serialization(#[from] ron::Error),
+	#[error("error during deserialization: {0}")]
+	Deserialization(#[from] ron::error::SpannedError),
+}
+
+pub const SERVER_LIST_ERROR_MESSAGE: &'static str = "An error occurred while saving the server list";
+
+/// A server in the server list.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SavedServer {
+	pub name: String,
+	/// The `ip:port` to connect to.
+	pub address: String,
+	/// The port the server answers status queries on, if it isn't the conventional one.
+	#[serde(default)]
+	pub query_port: Option<u16>,
+}
+
+impl SavedServer {
+	/// Returns the address status queries are sent to. Unless the server has its own query port, that's the port right
+	/// after the game port, by convention.
+	pub fn query_address(&self) -> Option<SocketAddr> {
+		let mut address = SocketAddr::from_str(&self.address).ok()?;
+		let port = match self.query_port {
+			Some(port) => port,
+			None => address.port().checked_add(1)?,
+		};
+		address.set_port(port);
+		Some(address)
+	}
+}
+
+/// The servers the player has saved, in the order they are shown.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct SavedServers(Vec<SavedServer>);
+
+impl SavedServers {
+	fn path() -> PathBuf {
+		let mut path = std::env::current_dir().unwrap();
+		path.push(CONFIG_DIR);
+		path.push(SERVER_LIST_FILE);
+		path
+	}
+	
+	/// Reads the server list from the config directory. A missing list is empty.
+	pub fn load() -> Result<Self, ServerListError> {
+		let path = Self::path();
+		if !path.exists() {
+			return Ok(Self::default())
+		}
+		
+		Ok(Self(ron::from_str(&std::fs::read_to_string(path)?)?))
+	}
+	
+	/// Writes the server list to the config directory, creating it if needed.
+	pub fn save(&self) -> Result<(), ServerListError> {
+		let path = Self::path();
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		
+		std::fs::write(path, ron::ser::to_string_pretty(&self.0, ron::ser::PrettyConfig::default())?)?;
+		Ok(())
+	}
+	
+	pub fn iter(&self) -> impl Iterator<Item = &SavedServer> {
+		self.0.iter()
+	}
+	
+	pub fn get(&self, index: usize) -> Option<&SavedServer> {
+		self.0.get(index)
+	}
+	
+	pub fn push(&mut self, server: SavedServer) {
+		self.0.push(server);
+	}
+	
+	/// Replaces the server at the index. Does nothing if there is no such server.
+	pub fn replace(&mut self, index: usize, server: SavedServer) {
+		if let Some(entry) = self.0.get_mut(index) {
+			*entry = server;
+		}
+	}
+	
+	pub fn remove(&mut self, index: usize) -> Option<SavedServer> {
+		(index < self.0.len()).then(|| self.0.remove(index))
+	}
+	
+	/// Swaps the server at the index with the one above it.
+	pub fn move_up(&mut self, index: usize) {
+		if index > 0 && index < self.0.len() {
+			self.0.swap(index - 1, index);
+		}
+	}
+	
+	/// Swaps the server at the index with the one below it.
+	pub fn move_down(&mut self, index: usize) {
+		if index + 1 < self.0.len() {
+			self.0.swap(index, index + 1);
+		}
+	}
+}
+
+/// The outcome of pinging a server.
+pub enum PingStatus {
+	Pinging(StatusQuery),
+	Online(ServerStatus, Duration),
+	Offline(String),
+}
+
+/// The status of each saved server, by address.
+#[derive(Default, Resource)]
+pub struct ServerStatuses(BevyHashMap<String, PingStatus>);
+
+impl ServerStatuses {
+	pub fn get(&self, address: &str) -> Option<&PingStatus> {
+		self.0.get(address)
+	}
+	
+	/// Starts pinging every saved server, replacing any earlier statuses.
+	pub fn ping_all(&mut self, servers: &SavedServers) {
+		self.0.clear();
+		for server in servers.iter() {
+			self.ping(server);
+		}
+	}
+	
+	/// Starts pinging the server. The answer is picked up by [poll_pings].
+	pub fn ping(&mut self, server: &SavedServer) {
+		let status = match server.query_address() {
+			Some(address) => match StatusQuery::send(address, STATUS_TIMEOUT) {
+				Ok(query) => PingStatus::Pinging(query),
+				Err(err) => PingStatus::Offline(err.to_string()),
+			},
+			None => PingStatus::Offline(format!("invalid address \"{}\"", server.address)),
+		};
+		self.0.insert(server.address.clone(), status);
+	}
+}
+
+/// Collects the answers to pings, without waiting for the ones that haven't come back yet.
+pub fn poll_pings(mut statuses: ResMut<ServerStatuses>) {
+	for status in statuses.0.values_mut() {
+		let PingStatus::Pinging(query) = status else {
+			continue
+		};
+		
+		if let Some(result) = query.poll() {
+			*status = match result {
+				Ok((server_status, ping)) => PingStatus::Online(server_status, ping),
+				Err(err) => PingStatus::Offline(err.to_string()),
+			};
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn server(address: &str, query_port: Option<u16>) -> SavedServer {
+		SavedServer {
+			name: "test".to_string(),
+			address: address.to_string(),
+			query_port,
+		}
+	}
+	
+	#[test]
+	fn query_addresses() {
+		assert_eq!(server("127.0.0.1:7000", None).query_address(), Some("127.0.0.1:7001".parse().unwrap()));
+		assert_eq!(server("127.0.0.1:7000", Some(9000)).query_address(), Some("127.0.0.1:9000".parse().unwrap()));
+		assert_eq!(server("[::1]:7000", Some(9000)).query_address(), Some("[::1]:9000".parse().unwrap()));
+		// there's no port after the last one to fall back on
+		assert_eq!(server("127.0.0.1:65535", None).query_address(), None);
+		assert_eq!(server("127.0.0.1:65535", Some(80)).query_address(), Some("127.0.0.1:80".parse().unwrap()));
+		assert_eq!(server("localhost", Some(80)).query_address(), None);
+	}
+	
+	#[test]
+	fn older_server_lists_still_load() {
+		let servers: Vec<SavedServer> = ron::from_str(r#"[(name: "test", address: "127.0.0.1:7000")]"#).unwrap();
+		assert_eq!(servers, vec![server("127.0.0.1:7000", None)]);
+	}
+}
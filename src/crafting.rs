@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::raw_id::item::RawItemIds;
+
+/// The width & height of the crafting grid.
+pub const CRAFTING_GRID_SIZE: usize = 3;
+
+/// The items a player has arranged in the crafting grid, row by row from the top.
+///
+/// Each occupied slot stands for a single item, which is taken from the player's inventory when crafting.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CraftingGrid {
+	slots: Vec<Option<RawId>>,
+}
+
+impl Default for CraftingGrid {
+	fn default() -> Self {
+		Self {
+			slots: vec![None; CRAFTING_GRID_SIZE * CRAFTING_GRID_SIZE],
+		}
+	}
+}
+
+impl CraftingGrid {
+	pub fn slots(&self) -> &[Option<RawId>] {
+		&self.slots
+	}
+	
+	pub fn get(&self, x: usize, y: usize) -> Option<RawId> {
+		if x >= CRAFTING_GRID_SIZE {
+			return None
+		}
+		
+		*self.slots.get(y * CRAFTING_GRID_SIZE + x)?
+	}
+	
+	/// Puts an item into the slot. Out-of-range slots are ignored.
+	pub fn set(&mut self, x: usize, y: usize, item: Option<RawId>) {
+		if x >= CRAFTING_GRID_SIZE {
+			return
+		}
+		
+		if let Some(slot) = self.slots.get_mut(y * CRAFTING_GRID_SIZE + x) {
+			*slot = item;
+		}
+	}
+	
+	/// Returns whether the grid has the right number of slots. Grids sent by clients must be checked before use.
+	pub fn is_valid(&self) -> bool {
+		self.slots.len() == CRAFTING_GRID_SIZE * CRAFTING_GRID_SIZE
+	}
+	
+	/// Converts the raw IDs in the grid into [Identifier]s, or returns [None] if any of them are unknown.
+	pub fn to_identifiers(&self, raw_item_ids: &RawItemIds) -> Option<Vec<Option<Identifier>>> {
+		self.slots
+			.iter()
+			.map(|slot| match slot {
+				Some(raw_id) => Some(Some(raw_item_ids.get_id(*raw_id)?.clone())),
+				None => Some(None),
+			})
+			.collect()
+	}
+}
+
+/// The reason a crafting request has been denied.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CraftDenyReason {
+	/// The grid doesn't match any recipe.
+	NoMatchingRecipe,
+	/// The player's inventory doesn't hold the items in the grid.
+	MissingIngredients,
+	/// The player isn't close enough to any of the tiles the recipe requires.
+	MissingStation(Vec<Identifier>),
+	/// The output doesn't fit in the player's inventory.
+	InventoryFull,
+	/// The player isn't in a world.
+	NotInWorld,
+}
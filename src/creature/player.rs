@@ -1,9 +1,14 @@
 use bevy::prelude::*;
+use bevy::sprite::Anchor;
 
+use crate::asset::from_asset_loc;
+use crate::client::networking::{LocalPlayer, Roster};
 use crate::networking::Username;
 use crate::networking::protocol::{ClientId, PlayerData};
+use crate::server::networking::Players;
 use crate::utils::asset::load_image;
-use crate::utils::math::{Velocity, ToScale};
+use crate::utils::formatted_sections;
+use crate::utils::math::{Velocity, ToScale, TILE_SIZE_PX};
 use crate::NAMESPACE;
 
 use super::CreatureBundle;
@@ -16,6 +21,21 @@ pub const DEFAULT_EYE_COLOR: Color = Color::rgb(0.0, 0.388235294118, 0.639215686
 pub const SPAWN_PLAYER_EVENT_ERROR_MESSAGE: &'static str = "An error occurred while spawning a tile";
 pub const PLAYER_DECORATION_ERROR_MESSAGE: &'static str = "An error occurred while decorating the player";
 
+/// How far the eyes can look to either side, in tiles.
+const EYE_LOOK_MAX: f32 = 0.25;
+/// How fast the eyes move, in tiles per second.
+const EYE_LOOK_SPEED: f32 = 1.0;
+/// How fast a player has to move sideways (in tiles per second) for their eyes to follow.
+const EYE_LOOK_THRESHOLD: f32 = 0.05;
+
+/// The font size name tags are rendered at, before being scaled down to [NAME_TAG_HEIGHT].
+const NAME_TAG_FONT_SIZE: f32 = TILE_SIZE_PX;
+/// How tall the text of name tags is, in tiles.
+const NAME_TAG_HEIGHT: f32 = 0.5;
+/// How far above the middle of the player the bottom of their name tag is, in tiles.
+const NAME_TAG_OFFSET: f32 = 0.625;
+const NAME_TAG_COLOR: Color = Color::WHITE;
+
 #[derive(Event)]
 pub struct SpawnPlayerEvent { // TODO: handle player spawning and collision
 	pub transform: Transform,
@@ -27,9 +47,12 @@ pub fn spawn_player_event(
 	mut spawn_player_event: EventReader<SpawnPlayerEvent>,
 	mut commands: Commands,
 	asset_server: Res<AssetServer>,
+	mut players: ResMut<Players>,
+	local_player_query: Query<&ClientId, With<LocalPlayer>>,
 ) -> anyhow::Result<()> {
 	for event in spawn_player_event.into_iter() {
-		commands.spawn(
+		let is_local = local_player_query.get_single().is_ok_and(|client_id| *client_id == event.id);
+		let player_entity = commands.spawn((
 			PlayerBundle {
 				id: event.id,
 				data: event.data.clone(),
@@ -42,8 +65,9 @@ pub fn spawn_player_event(
 					..default()
 				},
 				..default()
-			}
-		)
+			},
+			PreviousPosition::default(),
+		))
 			.with_children(|builder| {
 				builder.spawn(
 					PlayerEyesBundle {
@@ -83,36 +107,102 @@ pub fn spawn_player_event(
 						..default()
 					}
 				);
-			});
+				
+				// you know who you are
+				if !is_local {
+					builder.spawn(
+						PlayerNameTagBundle {
+							text: Text2dBundle {
+								text_anchor: Anchor::BottomCenter,
+								transform: Transform::from_xyz(0.0, NAME_TAG_OFFSET, 2.0)
+									.with_scale(Vec3::splat(NAME_TAG_HEIGHT / NAME_TAG_FONT_SIZE)),
+								..default()
+							},
+							..default()
+						}
+					);
+				}
+			})
+			.id();
+		players.0.insert(event.id, player_entity);
 	}
 	
 	Ok(())
 }
 
+/// Writes the name of the player above their head: their nickname if they have one, otherwise their username. Only
+/// updates name tags when they're new or the [Roster] has changed.
+pub fn update_name_tags(
+	roster: Res<Roster>,
+	asset_server: Res<AssetServer>,
+	player_query: Query<(&ClientId, &PlayerData), With<Player>>,
+	mut name_tag_query: Query<(&Parent, &mut Text, Ref<PlayerNameTag>)>,
+) {
+	let monogram = asset_server.get_handle(from_asset_loc(NAMESPACE, "fonts/monogram/monogram-extended.ttf")).unwrap_or_default();
+	for (parent, mut text, name_tag) in name_tag_query.iter_mut() {
+		if !roster.is_changed() && !name_tag.is_added() {
+			continue
+		}
+		
+		let Ok((client_id, player_data)) = player_query.get(parent.get()) else {
+			continue
+		};
+		
+		let name = roster.display_name(*client_id).unwrap_or(&player_data.username.0);
+		text.sections = formatted_sections(name, NAME_TAG_COLOR)
+			.into_iter()
+			.map(|(section, color)| TextSection::new(section, TextStyle {
+				font: monogram.clone(),
+				font_size: NAME_TAG_FONT_SIZE,
+				color,
+			}))
+			.collect();
+	}
+}
+
 /// "Decorates" the player (handles its eyes & arms).
+/// 
+/// The eyes look the way the player is moving. Remote players are moved by the server rather than by their velocity, so
+/// their movement is measured from how far they've moved since the last frame.
 pub fn player_decoration(
 	time: Res<Time>,
-	player_query: Query<&Velocity, With<Player>>, // parent
-	mut eyes_query: Query<(&Parent, &mut Transform), With<PlayerEyes>>, // child
-	left_arm_query: Query<(&Parent, &Transform), With<PlayerLeftArm>>, // child
-	right_arm_query: Query<(&Parent, &Transform), With<PlayerRightArm>>, // child
+	mut player_query: Query<(&Velocity, &Transform, &mut PreviousPosition), With<Player>>, // parent
+	mut eyes_query: Query<(&Parent, &mut Transform), (With<PlayerEyes>, Without<Player>)>, // child
+	left_arm_query: Query<(&Parent, &Transform), (With<PlayerLeftArm>, Without<PlayerEyes>)>, // child
+	right_arm_query: Query<(&Parent, &Transform), (With<PlayerRightArm>, Without<PlayerEyes>)>, // child
 ) -> anyhow::Result<()> {
+	let delta = time.delta_seconds();
+	if delta <= 0.0 {
+		return Ok(())
+	}
+	
 	for (parent, mut eyes_transform) in eyes_query.iter_mut() {
-		let player_velocity = player_query.get(parent.get())?;
-		if eyes_transform.translation.x >= 0.25 || eyes_transform.translation.x <= -0.25 { // continue if maximum eye look reached
+		let (player_velocity, player_transform, mut previous_position) = player_query.get_mut(parent.get())?;
+		let position = player_transform.translation.truncate();
+		let moved = previous_position.0.map_or(0.0, |previous| (position.x - previous.x) / delta);
+		previous_position.0 = Some(position);
+		
+		let speed = if player_velocity.translation.x.abs() > EYE_LOOK_THRESHOLD { player_velocity.translation.x } else { moved };
+		let target = if speed > EYE_LOOK_THRESHOLD {
+			EYE_LOOK_MAX
+		} else if speed < -EYE_LOOK_THRESHOLD {
+			-EYE_LOOK_MAX
+		} else { // keep looking the same way when standing still
 			continue
-		}
+		};
 		
-		if player_velocity.translation.x > 0.05 {
-			eyes_transform.translation.x += time.delta_seconds();
-		} else if player_velocity.translation.x < 0.05 {
-			eyes_transform.translation.x -= time.delta_seconds();
-		}
+		let step = EYE_LOOK_SPEED * delta;
+		let x = eyes_transform.translation.x;
+		eyes_transform.translation.x = x + (target - x).clamp(-step, step);
 	}
 	
 	Ok(())
 }
 
+/// Where the player was on the last frame, if they've been decorated before.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct PreviousPosition(pub Option<Vec2>);
+
 #[derive(Component, Debug, Clone, Default)]
 pub struct PlayerEyes;
 
@@ -123,6 +213,16 @@ pub struct PlayerEyesBundle {
 	pub sprite: SpriteBundle,
 }
 
+/// The name above a remote player's head. The text is filled in by [update_name_tags].
+#[derive(Component, Debug, Clone, Default)]
+pub struct PlayerNameTag;
+
+#[derive(Bundle, Clone, Default)]
+pub struct PlayerNameTagBundle {
+	pub name_tag: PlayerNameTag,
+	pub text: Text2dBundle,
+}
+
 #[derive(Component, Debug, Clone, Default)]
 pub struct PlayerLeftArm;
 
@@ -11,3 +11,14 @@ pub struct CursorBundle {
 	pub position: TilePos,
 	pub sprite_bundle: SpriteBundle,
 }
+
+/// Returns the position of the tile under the mouse cursor, if the cursor is inside the window.
+pub fn cursor_tile_pos(window: &Window, camera: &Camera, camera_transform: &GlobalTransform) -> Option<TilePos> {
+	let world_pos = camera.viewport_to_world_2d(camera_transform, window.cursor_position()?)?;
+	Some(
+		TilePos {
+			x: world_pos.x.round() as i32,
+			y: world_pos.y.round() as i32,
+		}
+	)
+}
@@ -17,7 +17,8 @@ impl fmt::Display for EnvTypeFromStringError {
 pub enum EnvType {
 	#[default]
 	/// A client.<br>
-	/// This has client and integrated server logic.
+	/// This has client and integrated server logic. The integrated server itself runs as a separate
+	/// [Server](EnvType::Server) app on a background thread.
 	Client,
 	/// A dedicated server.<br>
 	/// This has dedicated server logic.
@@ -6,6 +6,7 @@ use serde::Deserialize;
 use thiserror::Error;
 
 use crate::asset::locale::LocaleAsset;
+use crate::NAMESPACE;
 
 /// Represents the currently loaded locale.
 #[derive(Debug, Resource)]
@@ -95,6 +96,16 @@ impl TranslationServer {
 		self.locales.get(locale.locale())?.get(namespace)?.get(key)
 	}
 	
+	/// Translates the [Translatable], whose key has the format `<namespace>:<key>`.
+	/// 
+	/// Falls back to the untranslated key so that missing translations are easy to spot.
+	pub fn translate_or_key(&self, translatable: &Translatable, locale: &CurrentLocale) -> String {
+		let (namespace, key) = translatable.key().split_once(':').unwrap_or((NAMESPACE, translatable.key()));
+		self.translate(namespace, key, locale)
+			.cloned()
+			.unwrap_or_else(|| translatable.key().to_string())
+	}
+	
 	/// Translates the given key or loads it.
 	/// 
 	/// Useful when you're using lazily loaded translation keys for some godawful reason.
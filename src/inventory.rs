@@ -0,0 +1,346 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::raw_id::item::RawItemIds;
+
+/// The number of slots in a player's inventory.
+pub const INVENTORY_SIZE: usize = 40;
+/// The number of slots in the hotbar. These are the first slots of the inventory.
+pub const HOTBAR_SIZE: usize = 10;
+
+/// A change to a player's inventory requested by the client.
+///
+/// The server validates every action and answers with the resulting inventory.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum InventoryAction {
+	/// Moves the stack into another slot, merging it with or swapping it for whatever is there.
+	Move {
+		from: usize,
+		to: usize,
+	},
+	/// Moves half of the stack into an empty slot.
+	Split {
+		from: usize,
+		to: usize,
+	},
+	/// Selects the hotbar slot.
+	SelectHotbar(usize),
+}
+
+/// The hotbar slot a player is holding.
+#[derive(Component, Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Deref)]
+pub struct SelectedSlot(pub usize);
+
+/// A number of identical items occupying a single inventory slot.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ItemStack {
+	pub item: RawId,
+	pub count: u16,
+}
+
+impl ItemStack {
+	pub fn new(item: RawId, count: u16) -> Self {
+		Self {
+			item,
+			count,
+		}
+	}
+}
+
+/// A server-authoritative collection of item stacks.
+///
+/// The client only ever receives copies of this; all changes happen on the server.
+#[derive(Component, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Inventory {
+	slots: Vec<Option<ItemStack>>,
+}
+
+impl Default for Inventory {
+	fn default() -> Self {
+		Self::new(INVENTORY_SIZE)
+	}
+}
+
+impl Inventory {
+	pub fn new(size: usize) -> Self {
+		Self {
+			slots: vec![None; size],
+		}
+	}
+	
+	pub fn len(&self) -> usize {
+		self.slots.len()
+	}
+	
+	pub fn slots(&self) -> &[Option<ItemStack>] {
+		&self.slots
+	}
+	
+	pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+		self.slots.get(slot)?.as_ref()
+	}
+	
+	/// Replaces the stack in the slot, returning the previous stack.
+	///
+	/// Out-of-range slots are ignored and return [None].
+	pub fn set(&mut self, slot: usize, stack: Option<ItemStack>) -> Option<ItemStack> {
+		let stack = stack.filter(|stack| stack.count > 0);
+		std::mem::replace(self.slots.get_mut(slot)?, stack)
+	}
+	
+	/// Returns the total number of the given item across all slots.
+	pub fn count(&self, item: RawId) -> u32 {
+		self.slots
+			.iter()
+			.flatten()
+			.filter(|stack| stack.item == item)
+			.map(|stack| stack.count as u32)
+			.sum()
+	}
+	
+	/// Adds the stack to the inventory, first topping up existing stacks of the same item and then filling empty slots.
+	///
+	/// Returns whatever didn't fit, if anything.
+	pub fn insert(&mut self, mut stack: ItemStack, max_stack: u16) -> Option<ItemStack> {
+		for slot in self.slots.iter_mut().flatten() {
+			if stack.count == 0 {
+				break
+			}
+			
+			if slot.item == stack.item && slot.count < max_stack {
+				let moved = stack.count.min(max_stack - slot.count);
+				slot.count += moved;
+				stack.count -= moved;
+			}
+		}
+		
+		for slot in self.slots.iter_mut() {
+			if stack.count == 0 {
+				break
+			}
+			
+			if slot.is_none() {
+				let moved = stack.count.min(max_stack);
+				*slot = Some(ItemStack::new(stack.item, moved));
+				stack.count -= moved;
+			}
+		}
+		
+		if stack.count > 0 { Some(stack) } else { None }
+	}
+	
+	/// Removes `count` of the item from the inventory.
+	///
+	/// Nothing is removed and `false` is returned if the inventory doesn't hold enough of the item.
+	pub fn remove(&mut self, item: RawId, count: u32) -> bool {
+		if self.count(item) < count {
+			return false
+		}
+		
+		let mut remaining = count;
+		for slot in self.slots.iter_mut() {
+			if remaining == 0 {
+				break
+			}
+			
+			if let Some(stack) = slot {
+				if stack.item != item {
+					continue
+				}
+				
+				let taken = (stack.count as u32).min(remaining);
+				stack.count -= taken as u16;
+				remaining -= taken;
+				if stack.count == 0 {
+					*slot = None;
+				}
+			}
+		}
+		
+		true
+	}
+	
+	/// Moves the stack in `from` onto `to`.
+	///
+	/// Stacks of the same item are merged (leaving whatever doesn't fit in `from`) and different items are swapped.
+	/// Returns `false` if the move is invalid.
+	pub fn move_stack(&mut self, from: usize, to: usize, max_stack: u16) -> bool {
+		if from == to || from >= self.len() || to >= self.len() {
+			return false
+		}
+		
+		let Some(mut source) = self.slots[from] else {
+			return false
+		};
+		
+		match self.slots[to].as_mut() {
+			Some(target) if target.item == source.item => {
+				if target.count >= max_stack {
+					return false
+				}
+				
+				let moved = source.count.min(max_stack - target.count);
+				target.count += moved;
+				source.count -= moved;
+				self.set(from, Some(source));
+			},
+			_ => self.slots.swap(from, to),
+		}
+		
+		true
+	}
+	
+	/// Moves half of the stack in `from` (rounded down) into the empty slot `to`.
+	///
+	/// Returns `false` if the split is invalid.
+	pub fn split_stack(&mut self, from: usize, to: usize) -> bool {
+		if from == to || from >= self.len() || to >= self.len() || self.slots[to].is_some() {
+			return false
+		}
+		
+		let Some(source) = self.slots[from].as_mut() else {
+			return false
+		};
+		
+		if source.count < 2 {
+			return false
+		}
+		
+		let half = source.count / 2;
+		source.count -= half;
+		let item = source.item;
+		self.slots[to] = Some(ItemStack::new(item, half));
+		
+		true
+	}
+	
+	/// Converts the raw IDs in this inventory into their [Identifier]s so that they survive raw ID changes (e.g., in a save).
+	pub fn to_saved(&self, raw_item_ids: &RawItemIds) -> SavedInventory {
+		SavedInventory(
+			self.slots
+				.iter()
+				.map(|slot| {
+					let stack = (*slot)?;
+					Some((raw_item_ids.get_id(stack.item)?.clone(), stack.count))
+				})
+				.collect()
+		)
+	}
+}
+
+/// An [Inventory] with [Identifier]s in place of raw IDs.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SavedInventory(pub Vec<Option<(Identifier, u16)>>);
+
+impl SavedInventory {
+	/// Converts this back into an [Inventory], dropping any items that no longer exist.
+	pub fn to_inventory(&self, raw_item_ids: &RawItemIds) -> Inventory {
+		let mut inventory = Inventory::new(self.0.len().max(INVENTORY_SIZE));
+		for (slot, stack) in self.0.iter().enumerate() {
+			if let Some((id, count)) = stack {
+				match raw_item_ids.get_raw_id(id) {
+					Some(raw_id) => {
+						inventory.set(slot, Some(ItemStack::new(raw_id, *count)));
+					},
+					None => warn!("Dropping unknown item {} x{} from saved inventory", id, count),
+				}
+			}
+		}
+		inventory
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const DIRT: RawId = RawId(0);
+	const STONE: RawId = RawId(1);
+	const MAX_STACK: u16 = 64;
+	
+	fn inventory(slots: &[Option<(RawId, u16)>]) -> Inventory {
+		let mut inventory = Inventory::new(slots.len());
+		for (slot, stack) in slots.iter().enumerate() {
+			inventory.set(slot, stack.map(|(item, count)| ItemStack::new(item, count)));
+		}
+		inventory
+	}
+	
+	#[test]
+	fn setting_empty_stacks() {
+		let mut inventory = inventory(&[Some((DIRT, 5)), None]);
+		assert_eq!(inventory.set(0, Some(ItemStack::new(STONE, 0))), Some(ItemStack::new(DIRT, 5)));
+		assert_eq!(inventory.get(0), None);
+		assert_eq!(inventory.set(2, Some(ItemStack::new(STONE, 1))), None);
+		assert_eq!(inventory.slots(), &[None, None]);
+	}
+	
+	#[test]
+	fn inserting_tops_up_stacks_first() {
+		let mut inventory = inventory(&[None, Some((DIRT, 60)), Some((STONE, 10)), Some((DIRT, 62))]);
+		assert_eq!(inventory.insert(ItemStack::new(DIRT, 10), MAX_STACK), None);
+		assert_eq!(inventory, self::inventory(&[Some((DIRT, 4)), Some((DIRT, 64)), Some((STONE, 10)), Some((DIRT, 64))]));
+		
+		assert_eq!(inventory.insert(ItemStack::new(DIRT, 60), MAX_STACK), None);
+		assert_eq!(inventory.get(0), Some(&ItemStack::new(DIRT, 64)));
+	}
+	
+	#[test]
+	fn inserting_into_a_full_inventory() {
+		let mut inventory = inventory(&[Some((DIRT, 60)), None]);
+		assert_eq!(inventory.insert(ItemStack::new(DIRT, 100), MAX_STACK), Some(ItemStack::new(DIRT, 32)));
+		assert_eq!(inventory, self::inventory(&[Some((DIRT, 64)), Some((DIRT, 64))]));
+		
+		assert_eq!(inventory.insert(ItemStack::new(STONE, 3), MAX_STACK), Some(ItemStack::new(STONE, 3)));
+		assert_eq!(inventory.count(STONE), 0);
+	}
+	
+	#[test]
+	fn removing_across_stacks() {
+		let mut inventory = inventory(&[Some((DIRT, 3)), Some((STONE, 7)), Some((DIRT, 5))]);
+		assert!(!inventory.remove(DIRT, 9));
+		assert_eq!(inventory.count(DIRT), 8);
+		
+		assert!(inventory.remove(DIRT, 4));
+		assert_eq!(inventory, self::inventory(&[None, Some((STONE, 7)), Some((DIRT, 4))]));
+		assert!(inventory.remove(DIRT, 4));
+		assert_eq!(inventory.count(DIRT), 0);
+		assert!(inventory.remove(DIRT, 0));
+	}
+	
+	#[test]
+	fn moving_stacks() {
+		let mut inventory = inventory(&[Some((DIRT, 40)), Some((DIRT, 50)), Some((STONE, 1)), None]);
+		
+		// only what fits is merged
+		assert!(inventory.move_stack(0, 1, MAX_STACK));
+		assert_eq!(inventory, self::inventory(&[Some((DIRT, 26)), Some((DIRT, 64)), Some((STONE, 1)), None]));
+		assert!(!inventory.move_stack(0, 1, MAX_STACK));
+		
+		// different items are swapped, and so is an empty slot
+		assert!(inventory.move_stack(0, 2, MAX_STACK));
+		assert_eq!(inventory, self::inventory(&[Some((STONE, 1)), Some((DIRT, 64)), Some((DIRT, 26)), None]));
+		assert!(inventory.move_stack(2, 3, MAX_STACK));
+		assert_eq!(inventory, self::inventory(&[Some((STONE, 1)), Some((DIRT, 64)), None, Some((DIRT, 26))]));
+		
+		assert!(!inventory.move_stack(2, 0, MAX_STACK));
+		assert!(!inventory.move_stack(0, 0, MAX_STACK));
+		assert!(!inventory.move_stack(0, 4, MAX_STACK));
+	}
+	
+	#[test]
+	fn splitting_stacks() {
+		let mut inventory = inventory(&[Some((DIRT, 7)), Some((STONE, 1)), None, None]);
+		assert!(inventory.split_stack(0, 2));
+		assert_eq!(inventory, self::inventory(&[Some((DIRT, 4)), Some((STONE, 1)), Some((DIRT, 3)), None]));
+		
+		assert!(!inventory.split_stack(1, 3));
+		assert!(!inventory.split_stack(0, 1));
+		assert!(!inventory.split_stack(3, 0));
+		assert!(!inventory.split_stack(0, 0));
+		assert!(!inventory.split_stack(0, 4));
+		assert_eq!(inventory, self::inventory(&[Some((DIRT, 4)), Some((STONE, 1)), Some((DIRT, 3)), None]));
+	}
+}
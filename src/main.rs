@@ -1,10 +1,16 @@
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
 use std::net::{AddrParseError, SocketAddr};
 use std::str::FromStr;
+use std::time::Duration;
 
 use asset::image::MissingnoImagePlugin;
+use asset::item::{ItemDef, ItemDefLoader};
+use asset::recipe::{RecipeDef, RecipeDefLoader};
 use asset::tile::{TileDef, TileDefLoader};
 use bevy::ecs::archetype::Archetypes;
 use bevy::ecs::component::ComponentId;
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 use creature::player::SpawnPlayerEvent;
@@ -22,6 +28,8 @@ use crate::identifier::Identifier;
 use crate::networking::Username;
 use crate::networking::debug::NetworkingDebugPlugin;
 use crate::registry::Registry;
+use crate::registry::item::ItemRegistry;
+use crate::registry::recipe::RecipeRegistry;
 use crate::registry::tile::TileRegistry;
 use crate::server::networking::ServerPort;
 
@@ -43,6 +51,9 @@ pub mod client;
 pub mod server;
 pub mod cursor;
 pub mod physics;
+pub mod camera;
+pub mod inventory;
+pub mod crafting;
 
 pub const NAMESPACE: &'static str = "botanica";
 
@@ -50,7 +61,11 @@ pub const VERSION_STRING: &'static str = "0.1.0-alpha.0";
 
 pub const DEFAULT_LOCALE: &'static str = "en_us";
 
-/// Whether the dedicated server is headless.
+/// How many times per second a headless server updates.
+pub const SERVER_TICK_RATE: f64 = 60.0;
+
+/// Whether the app runs without a window. Dedicated servers can be headless, and so are the clients that the lockstep
+/// tests drive, which have no menus.
 #[derive(Debug, Default, Copy, Clone, Resource)]
 pub struct Headless(bool);
 
@@ -114,6 +129,32 @@ pub fn main() {
 	let headless = Headless(std::env::args().find(|s| s.as_str() == "--headless").is_some());
 	let username = Username(std::env::args().find(|s| s.as_str().starts_with("--username=")).unwrap_or("Player".to_owned()));
 	
+	let mut app = build_app(env, headless);
+	
+	if env == EnvType::Client {
+		app
+			.add_plugins(client::networking::NetworkingPlugin)
+			.insert_resource(username);
+	} else {
+		let mut server_config = server::networking::ServerConfig::default();
+		if let Some(arg) = std::env::args().find(|s| s.starts_with("--query-port=")) {
+			let port = &arg["--query-port=".len()..];
+			server_config.query_port = match port {
+				"none" => None,
+				port => Some(ServerPort(port.parse().expect("--query-port must be a port number or \"none\""))),
+			};
+		}
+		
+		app
+			.insert_resource(server_config)
+			.add_plugins(server::networking::NetworkingPlugin);
+	}
+	
+	app.run();
+}
+
+/// Builds everything that the client and the server share, without their networking.
+pub(crate) fn build_app(env: EnvType, headless: Headless) -> App {
 	let mut app = App::new();
 	
 	app
@@ -124,14 +165,21 @@ pub fn main() {
 		.insert_resource(env)
 		.insert_resource(headless)
 		.init_resource::<TileRegistry>()
+		.init_resource::<ItemRegistry>()
+		.init_resource::<RecipeRegistry>()
 		.init_resource::<loading::AssetsLoading>()
 		.add_plugins(loading::LoadingPlugin)
 		.add_plugins(physics::PhysicsPlugin);
 	
-	if headless.0 && env == EnvType::Server {
+	if headless.0 {
 		app
-			.add_plugins(MinimalPlugins)
+			.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / SERVER_TICK_RATE))))
 			.add_plugins(AssetPlugin::default());
+		
+		if env == EnvType::Client {
+			// what the client's world rendering needs, minus the rendering
+			app.add_plugins((TransformPlugin, HierarchyPlugin, ImagePlugin::default_nearest()));
+		}
 	} else {
 		app
 			.add_plugins(
@@ -140,13 +188,17 @@ pub fn main() {
 					.set(ImagePlugin::default_nearest()) // so our sprites appear crisp and clear
 					.add_after::<ImagePlugin, _>(MissingnoImagePlugin)
 			)
-			.add_plugins((EguiPlugin, NetworkingDebugPlugin))
+			.add_plugins((EguiPlugin, NetworkingDebugPlugin, camera::CameraPlugin))
 			.add_plugins(
 				(
 					menu::bevy_splash::BevySplashPlugin,
 					menu::title_screen::TitleScreenPlugin,
 					menu::world_select::WorldSelectPlugin,
 					menu::server_select::ServerSelectPlugin,
+					menu::inventory::InventoryScreenPlugin,
+					menu::hotbar::HotbarPlugin,
+					menu::player_list::PlayerListPlugin,
+					menu::pause::PausePlugin,
 				)
 			)
 			.add_systems(
@@ -160,19 +212,14 @@ pub fn main() {
 		.init_asset_loader::<LocaleAssetLoader>()
 		.init_asset::<TileDef>()
 		.init_asset_loader::<TileDefLoader>()
+		.init_asset::<ItemDef>()
+		.init_asset_loader::<ItemDefLoader>()
+		.init_asset::<RecipeDef>()
+		.init_asset_loader::<RecipeDefLoader>()
 		.init_asset::<RawIds>()
 		.init_asset_loader::<RawIdsLoader>();
 	
-	if env == EnvType::Client {
-		app
-			.add_plugins(client::networking::NetworkingPlugin)
-			.insert_resource(username);
-	} else {
-		app
-			.add_plugins(server::networking::NetworkingPlugin);
-	}
-	
-	app.run();
+	app
 }
 
 /// Recursively despawns all entities with the component `T`.
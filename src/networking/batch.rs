@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tile::WorldTile;
+use crate::tile::layer::{TileLayer, TileLayers};
+use crate::utils::BevyHashMap;
+use crate::world::{CHUNK_SIZE, ChunkPos};
+use crate::TilePos;
+
+/// The number of tiles in a single layer of a chunk.
+const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// A palette index that marks a tile as unchanged.
+const UNCHANGED: u16 = 0;
+
+#[derive(Debug, Error)]
+pub enum TileBatchError {
+	#[error("palette index {0} is out of range (the palette has {1} entries)")]
+	InvalidPaletteIndex(u16, usize),
+	#[error("runs of layer {0:?} cover {1} tiles instead of {CHUNK_AREA}")]
+	WrongLength(TileLayer, usize),
+}
+
+/// A run of tiles with the same palette index, in row order.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct Run {
+	/// The number of tiles in the run minus one, so that a whole chunk row fits in a byte.
+	length_minus_one: u8,
+	/// The index into the palette plus one, or [UNCHANGED].
+	index: u16,
+}
+
+/// Every tile change in a chunk during a single tick.
+///
+/// The changed tiles are stored as a palette of distinct tiles, and each layer is a run-length encoded list of palette
+/// indices covering the chunk from the bottom row up. Mostly-empty and mostly-uniform chunks both come out small. Air in
+/// a batch removes the tile.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TileUpdateBatch {
+	chunk: ChunkPos,
+	palette: Vec<WorldTile>,
+	layers: Vec<(TileLayer, Vec<Run>)>,
+}
+
+impl TileUpdateBatch {
+	/// Encodes the changes in the chunk. Changes outside of the chunk are ignored, and later changes to the same tile
+	/// replace earlier ones.
+	pub fn new(chunk: ChunkPos, changes: impl IntoIterator<Item = (TileLayer, TilePos, WorldTile)>) -> Self {
+		let origin = chunk.origin();
+		let mut palette: Vec<WorldTile> = vec![];
+		let mut indices: BTreeMap<TileLayer, [u16; CHUNK_AREA]> = BTreeMap::new();
+		
+		for (layer, pos, tile) in changes {
+			if ChunkPos::from(pos) != chunk {
+				continue
+			}
+			
+			let index = match palette.iter().position(|entry| *entry == tile) {
+				Some(index) => index,
+				None => {
+					palette.push(tile);
+					palette.len() - 1
+				},
+			};
+			let offset = ((pos.y - origin.y) * CHUNK_SIZE + (pos.x - origin.x)) as usize;
+			indices.entry(layer).or_insert([UNCHANGED; CHUNK_AREA])[offset] = index as u16 + 1;
+		}
+		
+		let layers = indices
+			.into_iter()
+			.map(|(layer, indices)| (layer, encode_runs(&indices)))
+			.collect();
+		
+		Self {
+			chunk,
+			palette,
+			layers,
+		}
+	}
+	
+	/// Groups the changes by chunk and encodes each chunk, in chunk order.
+	pub fn from_changes(changes: impl IntoIterator<Item = (TileLayer, TilePos, WorldTile)>) -> Vec<Self> {
+		let mut chunks: BTreeMap<ChunkPos, Vec<(TileLayer, TilePos, WorldTile)>> = BTreeMap::new();
+		for (layer, pos, tile) in changes {
+			chunks.entry(ChunkPos::from(pos)).or_default().push((layer, pos, tile));
+		}
+		
+		chunks
+			.into_iter()
+			.map(|(chunk, changes)| Self::new(chunk, changes))
+			.collect()
+	}
+	
+	/// Encodes every tile of a world, one batch per chunk.
+	pub fn from_tiles(tiles: &TileLayers) -> Vec<Self> {
+		Self::from_changes(tiles.iter().map(|(layer, pos, tile)| (layer, *pos, tile.clone())))
+	}
+	
+	/// Decodes the changed tiles, in layer order and then row order.
+	pub fn tiles(&self) -> Result<Vec<(TileLayer, TilePos, WorldTile)>, TileBatchError> {
+		let origin = self.chunk.origin();
+		let mut tiles = vec![];
+		
+		for (layer, runs) in self.layers.iter() {
+			let mut offset = 0;
+			for run in runs {
+				let length = run.length_minus_one as usize + 1;
+				if run.index != UNCHANGED {
+					let tile = self.palette
+						.get(run.index as usize - 1)
+						.ok_or(TileBatchError::InvalidPaletteIndex(run.index, self.palette.len()))?;
+					for i in offset..(offset + length).min(CHUNK_AREA) {
+						let pos = TilePos {
+							x: origin.x + i as i32 % CHUNK_SIZE,
+							y: origin.y + i as i32 / CHUNK_SIZE,
+						};
+						tiles.push((*layer, pos, tile.clone()));
+					}
+				}
+				offset += length;
+			}
+			
+			if offset != CHUNK_AREA {
+				return Err(TileBatchError::WrongLength(*layer, offset))
+			}
+		}
+		
+		Ok(tiles)
+	}
+}
+
+fn encode_runs(indices: &[u16; CHUNK_AREA]) -> Vec<Run> {
+	let mut runs: Vec<Run> = vec![];
+	for &index in indices.iter() {
+		match runs.last_mut() {
+			Some(run) if run.index == index && run.length_minus_one < u8::MAX => run.length_minus_one += 1,
+			_ => runs.push(Run { length_minus_one: 0, index }),
+		}
+	}
+	runs
+}
+
+/// Collects tile changes so that they can be sent as one [TileUpdateBatch] per chunk.
+#[derive(Debug, Default, Clone)]
+pub struct TileChanges(BevyHashMap<ChunkPos, BevyHashMap<(TileLayer, TilePos), WorldTile>>);
+
+impl TileChanges {
+	/// Records a change, replacing any earlier change to the same tile.
+	pub fn push(&mut self, layer: TileLayer, pos: TilePos, tile: WorldTile) {
+		self.0
+			.entry(ChunkPos::from(pos))
+			.or_default()
+			.insert((layer, pos), tile);
+	}
+	
+	/// Returns every changed tile, in no particular order.
+	pub fn iter(&self) -> impl Iterator<Item = (TileLayer, &TilePos, &WorldTile)> {
+		self.0
+			.values()
+			.flat_map(|changes| changes.iter().map(|((layer, pos), tile)| (*layer, pos, tile)))
+	}
+	
+	/// Encodes the changes, in chunk order.
+	pub fn to_batches(&self) -> Vec<TileUpdateBatch> {
+		let mut chunks: Vec<&ChunkPos> = self.0.keys().collect();
+		chunks.sort();
+		chunks
+			.into_iter()
+			.map(|chunk| {
+				let changes = self.0[chunk].iter().map(|((layer, pos), tile)| (*layer, *pos, tile.clone()));
+				TileUpdateBatch::new(*chunk, changes)
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::raw_id::RawId;
+	use crate::tile::TileData;
+	use crate::utils;
+	
+	fn tile(raw_id: i32, data: u8) -> WorldTile {
+		WorldTile(RawId(raw_id), TileData(data))
+	}
+	
+	/// Encodes the changes, sends them through the wire format and decodes them again, in the order [TileUpdateBatch::tiles]
+	/// gives them.
+	fn round_trip(changes: &[(TileLayer, TilePos, WorldTile)]) -> Vec<(TileLayer, TilePos, WorldTile)> {
+		TileUpdateBatch::from_changes(changes.iter().cloned())
+			.iter()
+			.flat_map(|batch| {
+				let bytes = utils::serialize_be(batch).unwrap();
+				let batch: TileUpdateBatch = utils::deserialize_be(&bytes).unwrap();
+				batch.tiles().unwrap()
+			})
+			.collect()
+	}
+	
+	fn sorted(mut changes: Vec<(TileLayer, TilePos, WorldTile)>) -> Vec<(TileLayer, TilePos, WorldTile)> {
+		changes.sort_by_key(|(layer, pos, _)| (ChunkPos::from(*pos), *layer, pos.y, pos.x));
+		changes
+	}
+	
+	/// A chunk's worth of tiles like a generated world has: a few kinds of tile in solid bands.
+	fn terrain(chunk: ChunkPos) -> Vec<(TileLayer, TilePos, WorldTile)> {
+		let origin = chunk.origin();
+		(0..CHUNK_SIZE)
+			.flat_map(|y| (0..CHUNK_SIZE).map(move |x| TilePos { x: origin.x + x, y: origin.y + y }))
+			.flat_map(|pos| {
+				let foreground = (TileLayer::Foreground, pos, tile((pos.y - origin.y) / 6, 0));
+				let background = (TileLayer::Background, pos, tile(10, 0));
+				[foreground, background]
+			})
+			.collect()
+	}
+	
+	#[test]
+	fn empty_chunks() {
+		assert!(TileUpdateBatch::from_changes([]).is_empty());
+		
+		let batch = TileUpdateBatch::new(ChunkPos { x: 3, y: -2 }, []);
+		assert!(batch.tiles().unwrap().is_empty());
+		// changes outside of the chunk are left out
+		let batch = TileUpdateBatch::new(ChunkPos { x: 0, y: 0 }, [(TileLayer::Foreground, TilePos { x: CHUNK_SIZE, y: 0 }, tile(1, 0))]);
+		assert!(batch.tiles().unwrap().is_empty());
+	}
+	
+	#[test]
+	fn single_tile() {
+		for pos in [TilePos { x: 0, y: 0 }, TilePos { x: 7, y: 9 }, TilePos { x: CHUNK_SIZE - 1, y: CHUNK_SIZE - 1 }] {
+			let changes = vec![(TileLayer::Foreground, pos, tile(4, 2))];
+			assert_eq!(round_trip(&changes), changes);
+		}
+		
+		let air = vec![(TileLayer::Overlay, TilePos { x: 3, y: 3 }, WorldTile::air())];
+		assert_eq!(round_trip(&air), air);
+	}
+	
+	#[test]
+	fn later_changes_win() {
+		let pos = TilePos { x: 1, y: 1 };
+		let changes = [(TileLayer::Foreground, pos, tile(1, 0)), (TileLayer::Foreground, pos, tile(2, 0))];
+		assert_eq!(round_trip(&changes), vec![(TileLayer::Foreground, pos, tile(2, 0))]);
+	}
+	
+	#[test]
+	fn big_palettes() {
+		// every tile of two layers different, which is more than a byte can index
+		let changes: Vec<_> = (0..CHUNK_SIZE)
+			.flat_map(|y| (0..CHUNK_SIZE).map(move |x| TilePos { x, y }))
+			.enumerate()
+			.flat_map(|(i, pos)| [
+				(TileLayer::Background, pos, tile(i as i32, 0)),
+				(TileLayer::Foreground, pos, tile(i as i32, 1)),
+			])
+			.collect();
+		
+		let batches = TileUpdateBatch::from_changes(changes.iter().cloned());
+		assert_eq!(batches.len(), 1);
+		assert_eq!(batches[0].palette.len(), 2 * CHUNK_AREA);
+		assert_eq!(sorted(round_trip(&changes)), sorted(changes));
+	}
+	
+	#[test]
+	fn mixed_layers() {
+		let pos = TilePos { x: 5, y: 5 };
+		let changes = vec![
+			(TileLayer::Background, pos, tile(1, 0)),
+			(TileLayer::Foreground, pos, tile(2, 0)),
+			(TileLayer::Overlay, pos, tile(3, 0)),
+			(TileLayer::Foreground, TilePos { x: 6, y: 5 }, tile(1, 0)),
+			(TileLayer::Overlay, TilePos { x: 0, y: 15 }, WorldTile::air()),
+		];
+		assert_eq!(sorted(round_trip(&changes)), sorted(changes));
+	}
+	
+	#[test]
+	fn negative_chunks() {
+		let changes = vec![
+			(TileLayer::Foreground, TilePos { x: -1, y: -1 }, tile(1, 0)),
+			(TileLayer::Foreground, TilePos { x: -CHUNK_SIZE, y: -CHUNK_SIZE }, tile(2, 0)),
+			(TileLayer::Foreground, TilePos { x: -CHUNK_SIZE - 1, y: 0 }, tile(3, 0)),
+			(TileLayer::Background, TilePos { x: 0, y: -1 }, tile(4, 0)),
+		];
+		
+		let batches = TileUpdateBatch::from_changes(changes.iter().cloned());
+		let chunks: Vec<ChunkPos> = batches.iter().map(|batch| batch.chunk).collect();
+		assert_eq!(chunks, vec![ChunkPos { x: -2, y: 0 }, ChunkPos { x: -1, y: -1 }, ChunkPos { x: 0, y: -1 }]);
+		assert_eq!(sorted(round_trip(&changes)), sorted(changes));
+	}
+	
+	#[test]
+	fn whole_world() {
+		let mut tiles = TileLayers::default();
+		for chunk in [ChunkPos { x: -1, y: -1 }, ChunkPos { x: 0, y: -1 }, ChunkPos { x: 0, y: 0 }] {
+			for (layer, pos, tile) in terrain(chunk) {
+				tiles.insert(layer, pos, tile);
+			}
+		}
+		
+		let decoded: Vec<_> = TileUpdateBatch::from_tiles(&tiles)
+			.iter()
+			.flat_map(|batch| batch.tiles().unwrap())
+			.collect();
+		let expected: Vec<_> = tiles.iter().map(|(layer, pos, tile)| (layer, *pos, tile.clone())).collect();
+		assert_eq!(sorted(decoded), sorted(expected));
+	}
+	
+	#[test]
+	fn corrupt_batches_are_rejected() {
+		let mut batch = TileUpdateBatch::new(ChunkPos::default(), [(TileLayer::Foreground, TilePos::default(), tile(1, 0))]);
+		batch.palette.clear();
+		assert!(matches!(batch.tiles(), Err(TileBatchError::InvalidPaletteIndex(1, 0))));
+		
+		let mut batch = TileUpdateBatch::new(ChunkPos::default(), [(TileLayer::Foreground, TilePos::default(), tile(1, 0))]);
+		batch.layers[0].1.pop();
+		assert!(matches!(batch.tiles(), Err(TileBatchError::WrongLength(TileLayer::Foreground, _))));
+	}
+	
+	/// The encoding that batches replaced: every tile sent as a map from position to tile.
+	fn world_tiles_encoding(changes: &[(TileLayer, TilePos, WorldTile)]) -> Vec<u8> {
+		let world_tiles: BevyHashMap<TilePos, WorldTile> = changes
+			.iter()
+			.filter(|(layer, _, _)| *layer == TileLayer::Foreground)
+			.map(|(_, pos, tile)| (*pos, tile.clone()))
+			.collect();
+		utils::serialize_be(&world_tiles).unwrap()
+	}
+	
+	fn batch_encoding(changes: &[(TileLayer, TilePos, WorldTile)]) -> Vec<u8> {
+		utils::serialize_be(&TileUpdateBatch::from_changes(changes.iter().cloned())).unwrap()
+	}
+	
+	#[test]
+	fn smaller_than_world_tiles() {
+		// the old encoding only had a single layer, so compare against just the foreground
+		let chunk: Vec<_> = terrain(ChunkPos::default())
+			.into_iter()
+			.filter(|(layer, _, _)| *layer == TileLayer::Foreground)
+			.collect();
+		assert!(batch_encoding(&chunk).len() * 4 < world_tiles_encoding(&chunk).len());
+		
+		let single = [(TileLayer::Foreground, TilePos { x: 3, y: 4 }, tile(1, 0))];
+		assert!(batch_encoding(&single).len() < 4 * world_tiles_encoding(&single).len());
+	}
+	
+	#[cfg(feature = "bench")]
+	mod benches {
+		extern crate test;
+		
+		use test::Bencher;
+		
+		use super::*;
+		
+		fn world() -> Vec<(TileLayer, TilePos, WorldTile)> {
+			(-4..4)
+				.flat_map(|y| (-4..4).map(move |x| ChunkPos { x, y }))
+				.flat_map(terrain)
+				.filter(|(layer, _, _)| *layer == TileLayer::Foreground)
+				.collect()
+		}
+		
+		#[bench]
+		fn encode_world_batches(b: &mut Bencher) {
+			let world = world();
+			b.iter(|| batch_encoding(&world));
+		}
+		
+		#[bench]
+		fn encode_world_tiles(b: &mut Bencher) {
+			let world = world();
+			b.iter(|| world_tiles_encoding(&world));
+		}
+		
+		#[bench]
+		fn decode_world_batches(b: &mut Bencher) {
+			let bytes = batch_encoding(&world());
+			b.iter(|| {
+				let batches: Vec<TileUpdateBatch> = utils::deserialize_be(&bytes).unwrap();
+				batches.iter().map(|batch| batch.tiles().unwrap().len()).sum::<usize>()
+			});
+		}
+		
+		#[bench]
+		fn decode_world_tiles(b: &mut Bencher) {
+			let bytes = world_tiles_encoding(&world());
+			b.iter(|| utils::deserialize_be::<BevyHashMap<TilePos, WorldTile>>(&bytes).unwrap().len());
+		}
+	}
+}
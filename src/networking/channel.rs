@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use renet::{ChannelConfig, ConnectionConfig, SendType};
+
+use super::envelope::MAX_PACKET_SIZE;
+use super::protocol::{ClientMessage, ClientResponse, Packet, ServerMessage, ServerResponse};
+
+/// The renet channels that packets are sent over. Every message variant is assigned to one of them by [MessageChannel],
+/// so callers never pick a channel themselves.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Channel {
+	/// Everything that changes state on the other side and has to arrive in order: joining, worlds, tiles, inventories.
+	Control,
+	/// Chat messages, so that a burst of chat doesn't hold up world data and the other way around.
+	Chat,
+	/// Pings and their acknowledgements, which are resent quickly and don't wait on each other.
+	Ping,
+	/// Frequently repeated state where only the latest value matters, like positions.
+	State,
+}
+
+impl Channel {
+	pub const ALL: [Channel; 4] = [Channel::Control, Channel::Chat, Channel::Ping, Channel::State];
+	
+	pub fn id(self) -> u8 {
+		match self {
+			Channel::Control => 0,
+			Channel::Chat => 1,
+			Channel::Ping => 2,
+			Channel::State => 3,
+		}
+	}
+	
+	/// Returns whether messages on the channel are resent until they arrive.
+	pub fn is_reliable(self) -> bool {
+		!matches!(self.send_type(), SendType::Unreliable)
+	}
+	
+	fn send_type(self) -> SendType {
+		match self {
+			Channel::Control => SendType::ReliableOrdered { resend_time: Duration::from_millis(300) },
+			Channel::Chat => SendType::ReliableOrdered { resend_time: Duration::from_millis(300) },
+			Channel::Ping => SendType::ReliableUnordered { resend_time: Duration::from_millis(100) },
+			Channel::State => SendType::Unreliable,
+		}
+	}
+	
+	/// The most memory the channel may use for messages that haven't been sent or acknowledged yet.
+	fn max_memory_usage_bytes(self) -> usize {
+		match self {
+			// room for a whole world of tiles being sent at once
+			Channel::Control => 2 * MAX_PACKET_SIZE,
+			Channel::Chat => 1024 * 1024,
+			Channel::Ping => 64 * 1024,
+			Channel::State => 1024 * 1024,
+		}
+	}
+	
+	pub fn config(self) -> ChannelConfig {
+		ChannelConfig {
+			channel_id: self.id(),
+			max_memory_usage_bytes: self.max_memory_usage_bytes(),
+			send_type: self.send_type(),
+		}
+	}
+}
+
+impl From<Channel> for u8 {
+	fn from(value: Channel) -> Self {
+		value.id()
+	}
+}
+
+/// The connection config that both the client and the server use. Both sides have the same channels.
+pub fn connection_config() -> ConnectionConfig {
+	let channels: Vec<ChannelConfig> = Channel::ALL.iter().map(|channel| channel.config()).collect();
+	ConnectionConfig {
+		// twice the default, so that entering a world doesn't take ages
+		available_bytes_per_tick: 120_000,
+		server_channels_config: channels.clone(),
+		client_channels_config: channels,
+	}
+}
+
+/// Decides which [Channel] a message is sent over.
+pub trait MessageChannel {
+	fn channel(&self) -> Channel;
+}
+
+impl MessageChannel for Packet {
+	fn channel(&self) -> Channel {
+		match self {
+			Packet::ServerMessage(message) => message.channel(),
+			Packet::ServerResponse(response) => response.channel(),
+			Packet::ClientMessage(message) => message.channel(),
+			Packet::ClientResponse(response) => response.channel(),
+		}
+	}
+}
+
+impl MessageChannel for ServerMessage {
+	fn channel(&self) -> Channel {
+		match self {
+			ServerMessage::Ping { .. } => Channel::Ping,
+			ServerMessage::ChatMessage(_) => Channel::Chat,
+			ServerMessage::PlayerPosition(..) | ServerMessage::WorldTime(_) | ServerMessage::PlayerPings(_) => Channel::State,
+			ServerMessage::Disconnect(_)
+			| ServerMessage::PlayerJoin(..)
+			| ServerMessage::PlayerLeave(_)
+			| ServerMessage::PlayerNick(..)
+			| ServerMessage::Roster(_)
+			| ServerMessage::RosterJoin(_)
+			| ServerMessage::RosterLeave(_)
+			| ServerMessage::RosterWorld(..)
+			| ServerMessage::RawTileIds(_)
+			| ServerMessage::RawItemIds(_)
+			| ServerMessage::TileUpdateBatch(_)
+			| ServerMessage::TileEntities(..)
+			| ServerMessage::SetTileEntity(..)
+			| ServerMessage::Inventory(_) => Channel::Control,
+		}
+	}
+}
+
+impl MessageChannel for ServerResponse {
+	fn channel(&self) -> Channel {
+		match self {
+			ServerResponse::PingAck { .. } => Channel::Ping,
+			ServerResponse::JoinDeny(_)
+			| ServerResponse::JoinAccept(_)
+			| ServerResponse::EnterWorldDeny(_)
+			| ServerResponse::EnterWorldAccept(_)
+			| ServerResponse::LeaveWorldAccept
+			| ServerResponse::CraftAccept(_)
+			| ServerResponse::CraftDeny(_)
+			| ServerResponse::NickDeny(_) => Channel::Control,
+		}
+	}
+}
+
+impl MessageChannel for ClientMessage {
+	fn channel(&self) -> Channel {
+		match self {
+			ClientMessage::Ping { .. } => Channel::Ping,
+			ClientMessage::ChatMessage(..) => Channel::Chat,
+			ClientMessage::PlayerPosition(_) => Channel::State,
+			ClientMessage::JoinRequest(_)
+			| ClientMessage::EnterWorldRequest(_)
+			| ClientMessage::LeaveWorld
+			| ClientMessage::BreakTile(..)
+			| ClientMessage::PlaceTile(..)
+			| ClientMessage::InventoryAction(_)
+			| ClientMessage::CraftRequest(_)
+			| ClientMessage::NickRequest(_) => Channel::Control,
+		}
+	}
+}
+
+impl MessageChannel for ClientResponse {
+	fn channel(&self) -> Channel {
+		match self {
+			ClientResponse::PingAck { .. } => Channel::Ping,
+		}
+	}
+}
@@ -0,0 +1,308 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use rand::Rng;
+use renet::transport::{ClientAuthentication, ConnectToken, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES, TokenGenerationError};
+use thiserror::Error;
+
+use super::protocol;
+
+/// The environment variable that turns on the network conditioner, e.g.
+/// `BOTANICA_NET_CONDITIONS=latency=100,jitter=20,loss=0.05,duplication=0.01`.
+pub const CONDITIONS_ENV_VAR: &'static str = "BOTANICA_NET_CONDITIONS";
+
+/// How long the proxy sleeps between checking its sockets.
+const PROXY_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How long the connect token made for the proxy is valid, in seconds. The same as for unsecure authentication.
+const CONNECT_TOKEN_EXPIRE_SECONDS: u64 = 300;
+/// How long netcode waits for a packet before timing out, in seconds. The same as for unsecure authentication.
+const CONNECT_TOKEN_TIMEOUT_SECONDS: i32 = 15;
+
+#[derive(Debug, Error)]
+pub enum ConditionsParseError {
+	#[error("setting \"{0}\" has no value")]
+	MissingValue(String),
+	#[error("unknown setting \"{0}\"")]
+	UnknownSetting(String),
+	#[error("invalid value \"{1}\" for setting \"{0}\"")]
+	InvalidValue(String, String),
+}
+
+/// The bad network to simulate. Every setting applies to each direction separately.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct NetworkConditions {
+	/// How long every packet is held back.
+	pub latency: Duration,
+	/// Up to how much longer or shorter than the latency a packet is held back, picked per packet. Packets can
+	/// overtake each other because of this.
+	pub jitter: Duration,
+	/// The chance of a packet being dropped, from 0 to 1.
+	pub loss: f32,
+	/// The chance of a packet arriving twice, from 0 to 1.
+	pub duplication: f32,
+}
+
+impl NetworkConditions {
+	/// Parses comma-separated `setting=value` pairs. Latency and jitter are in milliseconds, loss and duplication are
+	/// chances from 0 to 1. Settings that are left out are perfect.
+	pub fn parse(settings: &str) -> Result<Self, ConditionsParseError> {
+		let mut conditions = Self::default();
+		for setting in settings.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+			let Some((name, value)) = setting.split_once('=') else {
+				return Err(ConditionsParseError::MissingValue(setting.to_string()))
+			};
+			let invalid = || ConditionsParseError::InvalidValue(name.to_string(), value.to_string());
+			
+			match name {
+				"latency" => conditions.latency = Duration::from_millis(value.parse().map_err(|_| invalid())?),
+				"jitter" => conditions.jitter = Duration::from_millis(value.parse().map_err(|_| invalid())?),
+				"loss" => conditions.loss = parse_chance(value).ok_or_else(invalid)?,
+				"duplication" => conditions.duplication = parse_chance(value).ok_or_else(invalid)?,
+				_ => return Err(ConditionsParseError::UnknownSetting(name.to_string())),
+			}
+		}
+		Ok(conditions)
+	}
+	
+	/// Reads the conditions from [CONDITIONS_ENV_VAR], if it is set.
+	pub fn from_env() -> Option<Result<Self, ConditionsParseError>> {
+		std::env::var(CONDITIONS_ENV_VAR).ok().map(|settings| Self::parse(&settings))
+	}
+	
+	/// Picks how long to hold a packet back, or [None] if it should be dropped.
+	fn delay(&self, rng: &mut impl Rng) -> Option<Duration> {
+		if rng.gen::<f32>() < self.loss {
+			return None
+		}
+		
+		if self.jitter.is_zero() {
+			return Some(self.latency)
+		}
+		
+		let jitter = rng.gen_range(0..=self.jitter.as_micros() as u64 * 2);
+		Some((self.latency + Duration::from_micros(jitter)).saturating_sub(self.jitter))
+	}
+}
+
+fn parse_chance(value: &str) -> Option<f32> {
+	value.parse().ok().filter(|chance| (0.0..=1.0).contains(chance))
+}
+
+/// What the conditioner has done so far, in both directions together.
+#[derive(Debug, Default)]
+pub struct ConditionerStats {
+	pub packets: AtomicU64,
+	pub dropped: AtomicU64,
+	pub duplicated: AtomicU64,
+	pub delivered: AtomicU64,
+}
+
+impl ConditionerStats {
+	/// Returns how many packets are being held back right now.
+	pub fn in_flight(&self) -> u64 {
+		(self.packets.load(Ordering::Relaxed) + self.duplicated.load(Ordering::Relaxed))
+			.saturating_sub(self.dropped.load(Ordering::Relaxed) + self.delivered.load(Ordering::Relaxed))
+	}
+}
+
+/// Makes the client's connection behave like a bad network. The conditions can be changed at any time, and apply to
+/// packets sent from then on.
+///
+/// Only exists on the client, when [CONDITIONS_ENV_VAR] is set or in debug builds. Over UDP, the client connects through
+/// a [ConditionerProxy]; over a [MemoryNetwork](super::transport::MemoryNetwork), the client transport holds packets
+/// back itself.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct NetworkConditioner {
+	conditions: Arc<Mutex<NetworkConditions>>,
+	stats: Arc<ConditionerStats>,
+}
+
+impl NetworkConditioner {
+	pub fn new(conditions: NetworkConditions) -> Self {
+		Self {
+			conditions: Arc::new(Mutex::new(conditions)),
+			stats: default(),
+		}
+	}
+	
+	pub fn conditions(&self) -> NetworkConditions {
+		*self.conditions.lock().unwrap()
+	}
+	
+	pub fn set_conditions(&self, conditions: NetworkConditions) {
+		*self.conditions.lock().unwrap() = conditions;
+	}
+	
+	pub fn stats(&self) -> &ConditionerStats {
+		&self.stats
+	}
+}
+
+/// Packets travelling one way through a [NetworkConditioner].
+#[derive(Debug)]
+pub struct ConditionedQueue {
+	conditioner: NetworkConditioner,
+	/// Packets by the time they arrive, then by the order they were sent in.
+	queue: BinaryHeap<Reverse<(Instant, u64, Vec<u8>)>>,
+	next_seq: u64,
+}
+
+impl ConditionedQueue {
+	pub fn new(conditioner: NetworkConditioner) -> Self {
+		Self {
+			conditioner,
+			queue: default(),
+			next_seq: 0,
+		}
+	}
+	
+	/// Sends a packet into the queue. It may be dropped or arrive twice.
+	pub fn push(&mut self, packet: Vec<u8>) {
+		let conditions = self.conditioner.conditions();
+		let stats = self.conditioner.stats();
+		let mut rng = rand::thread_rng();
+		let now = Instant::now();
+		
+		stats.packets.fetch_add(1, Ordering::Relaxed);
+		let Some(delay) = conditions.delay(&mut rng) else {
+			stats.dropped.fetch_add(1, Ordering::Relaxed);
+			return
+		};
+		
+		if rng.gen::<f32>() < conditions.duplication {
+			stats.duplicated.fetch_add(1, Ordering::Relaxed);
+			// the copy gets its own delay, so it may well arrive first
+			let copy_delay = conditions.delay(&mut rng).unwrap_or(delay);
+			self.insert(now + copy_delay, packet.clone());
+		}
+		self.insert(now + delay, packet);
+	}
+	
+	fn insert(&mut self, arrival: Instant, packet: Vec<u8>) {
+		self.queue.push(Reverse((arrival, self.next_seq, packet)));
+		self.next_seq += 1;
+	}
+	
+	/// Takes every packet that has arrived by now.
+	pub fn drain_arrived(&mut self) -> Vec<Vec<u8>> {
+		let now = Instant::now();
+		let mut arrived = Vec::new();
+		while self.queue.peek().is_some_and(|Reverse((arrival, _, _))| *arrival <= now) {
+			let Reverse((_, _, packet)) = self.queue.pop().unwrap();
+			arrived.push(packet);
+		}
+		
+		self.conditioner.stats().delivered.fetch_add(arrived.len() as u64, Ordering::Relaxed);
+		arrived
+	}
+}
+
+/// A UDP relay on a loopback port that passes the client's packets on to the server and back through a
+/// [NetworkConditioner], so that netcode itself doesn't need to know about it. Stops when dropped.
+#[derive(Debug, Resource)]
+pub struct ConditionerProxy {
+	address: SocketAddr,
+	shutdown: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl ConditionerProxy {
+	/// Starts relaying to the server in the background.
+	pub fn start(server_addr: SocketAddr, conditioner: NetworkConditioner) -> io::Result<Self> {
+		let front = UdpSocket::bind("127.0.0.1:0")?;
+		front.set_nonblocking(true)?;
+		let address = front.local_addr()?;
+		
+		let back = UdpSocket::bind(if server_addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+		back.connect(server_addr)?;
+		back.set_nonblocking(true)?;
+		
+		let shutdown = Arc::new(AtomicBool::new(false));
+		let proxy_shutdown = shutdown.clone();
+		
+		let thread = std::thread::Builder::new()
+			.name("network conditioner".to_string())
+			.spawn(move || {
+				let mut to_server = ConditionedQueue::new(conditioner.clone());
+				let mut to_client = ConditionedQueue::new(conditioner);
+				let mut client_addr: Option<SocketAddr> = None;
+				let mut buf = [0u8; 2048];
+				
+				while !proxy_shutdown.load(Ordering::Acquire) {
+					while let Ok((len, from)) = front.recv_from(&mut buf) {
+						client_addr = Some(from);
+						to_server.push(buf[..len].to_vec());
+					}
+					while let Ok(len) = back.recv(&mut buf) {
+						to_client.push(buf[..len].to_vec());
+					}
+					
+					// a real network loses packets it can't deliver too, so errors are ignored
+					for packet in to_server.drain_arrived() {
+						let _ = back.send(&packet);
+					}
+					for packet in to_client.drain_arrived() {
+						if let Some(client_addr) = client_addr {
+							let _ = front.send_to(&packet, client_addr);
+						}
+					}
+					
+					std::thread::sleep(PROXY_POLL_INTERVAL);
+				}
+			})?;
+		
+		println!("Conditioning the connection to {} through {}", server_addr, address);
+		Ok(Self {
+			address,
+			shutdown,
+			thread: Some(thread),
+		})
+	}
+	
+	/// The address to connect to instead of the server's.
+	pub fn address(&self) -> SocketAddr {
+		self.address
+	}
+	
+	/// Returns the authentication to connect to the server through the proxy with.
+	///
+	/// The server only accepts connect tokens that list its own address, while the client sends to the first address
+	/// in the token. So this is an unsecure token like [ClientAuthentication::Unsecure] makes, but with the proxy listed
+	/// before the server.
+	pub fn authentication(
+		&self,
+		current_time: Duration,
+		client_id: u64,
+		server_addr: SocketAddr,
+		user_data: &[u8; NETCODE_USER_DATA_BYTES],
+	) -> Result<ClientAuthentication, TokenGenerationError> {
+		let connect_token = ConnectToken::generate(
+			current_time,
+			protocol::PROTOCOL_ID,
+			CONNECT_TOKEN_EXPIRE_SECONDS,
+			client_id,
+			CONNECT_TOKEN_TIMEOUT_SECONDS,
+			vec![self.address, server_addr],
+			Some(user_data),
+			&[0; NETCODE_KEY_BYTES],
+		)?;
+		Ok(ClientAuthentication::Secure { connect_token })
+	}
+}
+
+impl Drop for ConditionerProxy {
+	fn drop(&mut self) {
+		self.shutdown.store(true, Ordering::Release);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
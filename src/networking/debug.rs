@@ -1,10 +1,16 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
 use bevy::prelude::*;
-use bevy_egui::EguiContexts;
+use bevy_egui::{egui, EguiContexts};
 use renet::{RenetClient, RenetServer, ServerEvent};
 use renet_visualizer::{RenetClientVisualizer, RenetServerVisualizer};
 
 use crate::{env, GameState, is_debug};
 
+use super::conditioner::NetworkConditioner;
+use super::stats::PlayerNetStats;
+
 const VISUALIZER_UPDATE: usize = 200;
 
 pub struct NetworkingDebugPlugin;
@@ -12,18 +18,30 @@ pub struct NetworkingDebugPlugin;
 impl Plugin for NetworkingDebugPlugin {
 	fn build(&self, app: &mut App) {
 		app
+			.init_resource::<RenetServerVisualizer<VISUALIZER_UPDATE>>()
+			.init_resource::<RenetClientVisualizer<VISUALIZER_UPDATE>>()
 			.add_systems(
 				Update,
-				debug_server
-					.run_if(in_state(GameState::InWorld))
+				(debug_server, debug_player_stats)
+					.run_if(in_state(GameState::ServerLoaded))
 					.run_if(is_debug)
 					.run_if(env::is_server)
 			)
 			.add_systems(
 				Update,
 				debug_client
-					.run_if(in_state(GameState::LoadingWorld))
-					.run_if(in_state(GameState::InWorld))
+					.run_if(
+						in_state(GameState::LoadingWorld)
+							.or_else(in_state(GameState::InWorld))
+					)
+					.run_if(resource_exists::<RenetClient>())
+					.run_if(is_debug)
+					.run_if(env::is_client)
+			)
+			.add_systems(
+				Update,
+				debug_conditioner
+					.run_if(resource_exists::<NetworkConditioner>())
 					.run_if(is_debug)
 					.run_if(env::is_client)
 			);
@@ -31,17 +49,19 @@ impl Plugin for NetworkingDebugPlugin {
 }
 
 fn debug_server(
-	mut server: ResMut<RenetServer>,
+	server: Res<RenetServer>,
 	mut visualizer: ResMut<RenetServerVisualizer<VISUALIZER_UPDATE>>,
+	mut ev_server: EventReader<ServerEvent>,
 	contexts: EguiContexts,
 ) {
-	while let Some(event) = server.get_event() {
+	// read through bevy's events, which every reader gets its own copy of, so the server's handler still sees them
+	for event in ev_server.iter() {
 		match event {
 			ServerEvent::ClientConnected { client_id } => {
-				visualizer.add_client(client_id);
+				visualizer.add_client(*client_id);
 			}
 			ServerEvent::ClientDisconnected { client_id, .. } => {
-				visualizer.remove_client(client_id);
+				visualizer.remove_client(*client_id);
 			}
 		}
 	}
@@ -51,6 +71,52 @@ fn debug_server(
 	visualizer.show_window(contexts.ctx());
 }
 
+/// Shows the [PlayerNetStats] of every connected player.
+fn debug_player_stats(
+	player_stats: Res<PlayerNetStats>,
+	contexts: EguiContexts,
+) {
+	egui::Window::new("Player Network Stats").show(contexts.ctx(), |ui| {
+		let mut player_stats: Vec<_> = player_stats.iter().collect();
+		player_stats.sort_by_key(|(client_id, _)| client_id.0);
+		
+		if player_stats.is_empty() {
+			ui.label("No players connected");
+		}
+		
+		for (client_id, stats) in player_stats {
+			egui::CollapsingHeader::new(format!("Client {:X}", client_id.0)).default_open(true).show(ui, |ui| {
+				ui.label(format!("World: {}", stats.world.as_ref().map(|world| world.as_str()).unwrap_or("none")));
+				ui.label(format!("Ping: {} ms (smoothed {} ms, jitter {} ms)", stats.ping, stats.smoothed_rtt.as_millis(), stats.jitter.as_millis()));
+				ui.label(format!("RTT: {:.1} ms", stats.rtt));
+				ui.label(format!("Packet loss: {:.1}%", stats.packet_loss * 100.0));
+				ui.label(format!("Sent: {:.1} KiB/s", stats.bytes_sent_per_second / 1024.0));
+				ui.label(format!("Received: {:.1} KiB/s", stats.bytes_received_per_second / 1024.0));
+				
+				egui::Grid::new(("received messages", client_id.0)).striped(true).show(ui, |ui| {
+					ui.strong("Message");
+					ui.strong("Count");
+					ui.strong("Bytes");
+					ui.end_row();
+					
+					for (name, traffic) in stats.received() {
+						ui.label(name);
+						ui.label(traffic.count.to_string());
+						ui.label(traffic.bytes.to_string());
+						ui.end_row();
+					}
+					
+					let total = stats.received_total();
+					ui.strong("Total");
+					ui.strong(total.count.to_string());
+					ui.strong(total.bytes.to_string());
+					ui.end_row();
+				});
+			});
+		}
+	});
+}
+
 fn debug_client(
 	client: Res<RenetClient>,
 	mut visualizer: ResMut<RenetClientVisualizer<VISUALIZER_UPDATE>>,
@@ -60,3 +126,33 @@ fn debug_client(
 	
 	visualizer.show_window(contexts.ctx());
 }
+
+/// Shows what the network conditioner has done, and lets its conditions be changed.
+fn debug_conditioner(
+	conditioner: Res<NetworkConditioner>,
+	contexts: EguiContexts,
+) {
+	let mut conditions = conditioner.conditions();
+	let mut latency = conditions.latency.as_millis() as u64;
+	let mut jitter = conditions.jitter.as_millis() as u64;
+	let stats = conditioner.stats();
+	
+	egui::Window::new("Network Conditioner").show(contexts.ctx(), |ui| {
+		ui.add(egui::Slider::new(&mut latency, 0..=1000).text("latency (ms)"));
+		ui.add(egui::Slider::new(&mut jitter, 0..=500).text("jitter (ms)"));
+		ui.add(egui::Slider::new(&mut conditions.loss, 0.0..=1.0).text("loss"));
+		ui.add(egui::Slider::new(&mut conditions.duplication, 0.0..=1.0).text("duplication"));
+		ui.separator();
+		ui.label(format!("Packets: {}", stats.packets.load(Ordering::Relaxed)));
+		ui.label(format!("Dropped: {}", stats.dropped.load(Ordering::Relaxed)));
+		ui.label(format!("Duplicated: {}", stats.duplicated.load(Ordering::Relaxed)));
+		ui.label(format!("Delivered: {}", stats.delivered.load(Ordering::Relaxed)));
+		ui.label(format!("In flight: {}", stats.in_flight()));
+	});
+	
+	conditions.latency = Duration::from_millis(latency);
+	conditions.jitter = Duration::from_millis(jitter);
+	if conditions != conditioner.conditions() {
+		conditioner.set_conditions(conditions);
+	}
+}
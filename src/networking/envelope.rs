@@ -0,0 +1,371 @@
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use renet::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+use crate::utils::BevyHashMap;
+
+use super::channel::Channel;
+use super::error::NetworkError;
+use super::handshake::Capabilities;
+use super::protocol::Packet;
+
+/// Packets at least this big (in bytes) are compressed if the peer supports it, unless compressing them doesn't make
+/// them smaller.
+pub const COMPRESSION_THRESHOLD: usize = 256;
+/// The biggest payload (in bytes) that is sent as a single renet message. Anything bigger is split into fragments.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// The biggest packet (in bytes) that will be sent or reassembled, before compression.
+pub const MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
+/// Room left in each fragment for the fields around its data.
+const FRAGMENT_OVERHEAD: usize = 64;
+/// The most data (in bytes) a single fragment carries.
+const FRAGMENT_SIZE: usize = MAX_MESSAGE_SIZE - FRAGMENT_OVERHEAD;
+/// The most fragments a packet of [MAX_PACKET_SIZE] is split into.
+const MAX_FRAGMENTS: usize = (MAX_PACKET_SIZE + FRAGMENT_SIZE - 1) / FRAGMENT_SIZE;
+/// The most partly received packets a [Reassembler] holds on to. Reliable channels deliver a packet's fragments in a
+/// row, so a well-behaved sender never has more than one per channel.
+const MAX_PENDING_PACKETS: usize = 8;
+/// How long a partly received packet is kept without any of its fragments arriving.
+const PENDING_PACKET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What actually goes over the wire: a serialized [Packet], possibly compressed or split up.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+enum Envelope {
+	Plain(Vec<u8>),
+	/// Deflate-compressed.
+	Compressed(Vec<u8>),
+	/// One part of a serialized [Envelope::Plain] or [Envelope::Compressed] that was too big for a single message.
+	Fragment {
+		id: u32,
+		index: u16,
+		count: u16,
+		data: Vec<u8>,
+	},
+}
+
+/// Serializes packets into renet messages. Each sender needs its own encoder, since fragment IDs only have to be unique
+/// per sender.
+#[derive(Debug, Default, Resource)]
+pub struct Encoder {
+	next_fragment_id: u32,
+}
+
+impl Encoder {
+	/// Serializes the packet into one or more renet messages, compressing it if it is big enough and the capabilities
+	/// negotiated with the receiver allow it. Anything sent before the handshake uses [Capabilities::NONE].
+	///
+	/// Only reliable channels can carry packets that have to be split up, since a single lost fragment would lose the
+	/// whole packet.
+	pub fn encode(&mut self, packet: &Packet, channel: Channel, capabilities: Capabilities) -> Result<Vec<Bytes>, NetworkError> {
+		let bytes = utils::serialize_be(packet)?;
+		if bytes.len() > MAX_PACKET_SIZE {
+			return Err(NetworkError::OversizedPayload(bytes.len(), MAX_PACKET_SIZE))
+		}
+		
+		let envelope = if bytes.len() >= COMPRESSION_THRESHOLD && capabilities.contains(Capabilities::COMPRESSION) {
+			let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+			encoder.write_all(&bytes)?;
+			let compressed = encoder.finish()?;
+			if compressed.len() < bytes.len() { Envelope::Compressed(compressed) } else { Envelope::Plain(bytes) }
+		} else {
+			Envelope::Plain(bytes)
+		};
+		
+		let serialized = utils::serialize_be(&envelope)?;
+		if serialized.len() <= MAX_MESSAGE_SIZE {
+			return Ok(vec![serialized.into()])
+		}
+		
+		if !channel.is_reliable() {
+			return Err(NetworkError::OversizedPayload(serialized.len(), MAX_MESSAGE_SIZE))
+		}
+		// the reassembler won't take more than this
+		if serialized.len() > MAX_PACKET_SIZE {
+			return Err(NetworkError::OversizedPayload(serialized.len(), MAX_PACKET_SIZE))
+		}
+		
+		let id = self.next_fragment_id;
+		self.next_fragment_id = self.next_fragment_id.wrapping_add(1);
+		let parts: Vec<&[u8]> = serialized.chunks(FRAGMENT_SIZE).collect();
+		let count = parts.len() as u16;
+		parts
+			.into_iter()
+			.enumerate()
+			.map(|(index, data)| {
+				let fragment = Envelope::Fragment { id, index: index as u16, count, data: data.to_vec() };
+				Ok(utils::serialize_be(&fragment)?.into())
+			})
+			.collect()
+	}
+}
+
+/// Opens a whole (non-fragment) envelope.
+fn open(envelope: Envelope) -> Result<Packet, NetworkError> {
+	match envelope {
+		Envelope::Plain(bytes) => Ok(utils::deserialize_be(&bytes)?),
+		Envelope::Compressed(compressed) => {
+			// read one byte past the limit to notice packets that decompress to something too big
+			let mut bytes = vec![];
+			DeflateDecoder::new(compressed.as_slice())
+				.take(MAX_PACKET_SIZE as u64 + 1)
+				.read_to_end(&mut bytes)
+				.map_err(|err| NetworkError::CorruptPayload(format!("failed to decompress: {}", err)))?;
+			if bytes.len() > MAX_PACKET_SIZE {
+				return Err(NetworkError::OversizedPayload(bytes.len(), MAX_PACKET_SIZE))
+			}
+			Ok(utils::deserialize_be(&bytes)?)
+		},
+		Envelope::Fragment { .. } => Err(NetworkError::CorruptPayload("fragment inside of a fragmented packet".to_string())),
+	}
+}
+
+/// The fragments received so far of a single packet.
+#[derive(Debug)]
+struct PartialPacket {
+	parts: Vec<Option<Vec<u8>>>,
+	received: usize,
+	size: usize,
+	/// When the last new fragment arrived.
+	updated: Instant,
+}
+
+/// Turns received messages back into [Packet]s, holding on to fragments until their packet is complete.
+///
+/// Each sender needs its own reassembler, since fragment IDs are only unique per sender. Packets that stop receiving
+/// fragments are dropped after [PENDING_PACKET_TIMEOUT], and only [MAX_PENDING_PACKETS] are kept at once.
+#[derive(Debug, Default, Resource)]
+pub struct Reassembler(BevyHashMap<u32, PartialPacket>);
+
+impl Reassembler {
+	/// Decodes a received message. Returns [None] if it was a fragment of a packet that isn't complete yet.
+	pub fn decode(&mut self, bytes: &[u8]) -> Result<Option<Packet>, NetworkError> {
+		self.decode_at(bytes, Instant::now())
+	}
+	
+	fn decode_at(&mut self, bytes: &[u8], now: Instant) -> Result<Option<Packet>, NetworkError> {
+		let (id, index, count, data) = match utils::deserialize_be::<Envelope>(bytes)? {
+			Envelope::Fragment { id, index, count, data } => (id, index as usize, count as usize, data),
+			envelope => return open(envelope).map(Some),
+		};
+		
+		if index >= count {
+			self.0.remove(&id);
+			return Err(NetworkError::CorruptPayload(format!("fragment {} of packet {} is out of range (count {})", index, id, count)))
+		}
+		// checked before anything is held on to, so that a bad sender can't make us allocate for a huge packet
+		if count > MAX_FRAGMENTS {
+			self.0.remove(&id);
+			return Err(NetworkError::OversizedPayload(count * FRAGMENT_SIZE, MAX_PACKET_SIZE))
+		}
+		if data.len() > FRAGMENT_SIZE {
+			self.0.remove(&id);
+			return Err(NetworkError::CorruptPayload(format!("fragment {} of packet {} is {} bytes", index, id, data.len())))
+		}
+		
+		self.0.retain(|_, partial| now.saturating_duration_since(partial.updated) < PENDING_PACKET_TIMEOUT);
+		if !self.0.contains_key(&id) && self.0.len() >= MAX_PENDING_PACKETS {
+			let oldest = self.0
+				.iter()
+				.min_by_key(|(_, partial)| partial.updated)
+				.map(|(&id, _)| id);
+			if let Some(oldest) = oldest {
+				self.0.remove(&oldest);
+			}
+		}
+		
+		let partial = self.0
+			.entry(id)
+			.or_insert_with(|| PartialPacket { parts: vec![None; count], received: 0, size: 0, updated: now });
+		if partial.parts.len() != count {
+			self.0.remove(&id);
+			return Err(NetworkError::CorruptPayload(format!("fragments of packet {} disagree on the fragment count", id)))
+		}
+		
+		// a resent fragment doesn't add anything
+		if partial.parts[index].is_some() {
+			return Ok(None)
+		}
+		
+		partial.size += data.len();
+		if partial.size > MAX_PACKET_SIZE {
+			let size = partial.size;
+			self.0.remove(&id);
+			return Err(NetworkError::OversizedPayload(size, MAX_PACKET_SIZE))
+		}
+		
+		partial.parts[index] = Some(data);
+		partial.received += 1;
+		partial.updated = now;
+		if partial.received < count {
+			return Ok(None)
+		}
+		
+		let partial = self.0.remove(&id).expect("partial packet should exist");
+		let bytes: Vec<u8> = partial.parts
+			.into_iter()
+			.flatten()
+			.flatten()
+			.collect();
+		open(utils::deserialize_be::<Envelope>(&bytes)?).map(Some)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand::rngs::StdRng;
+	
+	use crate::networking::protocol::ClientMessage;
+	
+	use super::*;
+	
+	/// A packet that doesn't compress well, and so has to be split up once it's over [MAX_MESSAGE_SIZE].
+	fn noise_packet(len: usize) -> Packet {
+		let mut rng = StdRng::seed_from_u64(7);
+		let noise = (0..len).map(|_| rng.gen_range('!'..='~')).collect();
+		Packet::from(ClientMessage::NickRequest(noise))
+	}
+	
+	fn fragment(id: u32, index: u16, count: u16, data: Vec<u8>) -> Vec<u8> {
+		utils::serialize_be(&Envelope::Fragment { id, index, count, data }).unwrap()
+	}
+	
+	fn fragment_id(bytes: &[u8]) -> u32 {
+		match utils::deserialize_be::<Envelope>(bytes).unwrap() {
+			Envelope::Fragment { id, .. } => id,
+			envelope => panic!("expected a fragment, got {:?}", envelope),
+		}
+	}
+	
+	#[test]
+	fn small_and_compressed_packets() {
+		let mut encoder = Encoder::default();
+		let mut reassembler = Reassembler::default();
+		
+		let small = Packet::from(ClientMessage::LeaveWorld);
+		let big = Packet::from(ClientMessage::NickRequest("a".repeat(100_000)));
+		for packet in [small, big] {
+			let payloads = encoder.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap();
+			assert_eq!(payloads.len(), 1);
+			assert_eq!(reassembler.decode(&payloads[0]).unwrap(), Some(packet));
+		}
+	}
+	
+	#[test]
+	fn compresses_only_when_negotiated() {
+		let mut encoder = Encoder::default();
+		let packet = Packet::from(ClientMessage::NickRequest("a".repeat(1000)));
+		
+		let compressed = encoder.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap();
+		assert!(matches!(utils::deserialize_be::<Envelope>(&compressed[0]).unwrap(), Envelope::Compressed(_)));
+		let plain = encoder.encode(&packet, Channel::Control, Capabilities::NONE).unwrap();
+		assert!(matches!(utils::deserialize_be::<Envelope>(&plain[0]).unwrap(), Envelope::Plain(_)));
+		
+		// decoding doesn't depend on what was negotiated
+		let mut reassembler = Reassembler::default();
+		assert_eq!(reassembler.decode(&plain[0]).unwrap(), Some(packet.clone()));
+		assert_eq!(reassembler.decode(&compressed[0]).unwrap(), Some(packet));
+	}
+	
+	#[test]
+	fn fragments_reassemble_in_any_order() {
+		let mut encoder = Encoder::default();
+		let mut reassembler = Reassembler::default();
+		let packet = noise_packet(300_000);
+		
+		let mut payloads = encoder.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap();
+		assert!(payloads.len() > 1);
+		payloads.reverse();
+		let last = payloads.pop().unwrap();
+		for payload in payloads {
+			assert_eq!(reassembler.decode(&payload).unwrap(), None);
+		}
+		assert_eq!(reassembler.decode(&last).unwrap(), Some(packet));
+		assert!(reassembler.0.is_empty());
+	}
+	
+	#[test]
+	fn unreliable_channels_cant_fragment() {
+		let mut encoder = Encoder::default();
+		assert!(matches!(
+			encoder.encode(&noise_packet(300_000), Channel::State, Capabilities::COMPRESSION),
+			Err(NetworkError::OversizedPayload(..))
+		));
+	}
+	
+	#[test]
+	fn fragment_ids_are_per_encoder() {
+		let packet = noise_packet(100_000);
+		let mut first = Encoder::default();
+		let mut second = Encoder::default();
+		
+		assert_eq!(fragment_id(&first.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap()[0]), 0);
+		assert_eq!(fragment_id(&first.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap()[0]), 1);
+		assert_eq!(fragment_id(&second.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap()[0]), 0);
+	}
+	
+	#[test]
+	fn duplicate_fragments_are_ignored() {
+		let mut encoder = Encoder::default();
+		let mut reassembler = Reassembler::default();
+		let packet = noise_packet(100_000);
+		
+		let payloads = encoder.encode(&packet, Channel::Control, Capabilities::COMPRESSION).unwrap();
+		assert_eq!(payloads.len(), 2);
+		assert_eq!(reassembler.decode(&payloads[0]).unwrap(), None);
+		let size = reassembler.0[&0].size;
+		assert_eq!(reassembler.decode(&payloads[0]).unwrap(), None);
+		assert_eq!(reassembler.0[&0].size, size);
+		assert_eq!(reassembler.0[&0].received, 1);
+		assert_eq!(reassembler.decode(&payloads[1]).unwrap(), Some(packet));
+	}
+	
+	#[test]
+	fn oversized_packets_are_rejected_up_front() {
+		let mut reassembler = Reassembler::default();
+		
+		let too_many = fragment(0, 0, MAX_FRAGMENTS as u16 + 1, vec![0; 16]);
+		assert!(matches!(reassembler.decode(&too_many), Err(NetworkError::OversizedPayload(..))));
+		let too_big = fragment(1, 0, 2, vec![0; FRAGMENT_SIZE + 1]);
+		assert!(reassembler.decode(&too_big).is_err());
+		let out_of_range = fragment(2, 2, 2, vec![0; 16]);
+		assert!(reassembler.decode(&out_of_range).is_err());
+		assert!(reassembler.0.is_empty());
+	}
+	
+	#[test]
+	fn pending_packets_are_capped() {
+		let mut reassembler = Reassembler::default();
+		let start = Instant::now();
+		
+		for id in 0..=MAX_PENDING_PACKETS as u32 {
+			let now = start + Duration::from_millis(id as u64);
+			assert_eq!(reassembler.decode_at(&fragment(id, 0, 2, vec![0; 16]), now).unwrap(), None);
+		}
+		assert_eq!(reassembler.0.len(), MAX_PENDING_PACKETS);
+		// the packet that went the longest without a fragment makes room
+		assert!(!reassembler.0.contains_key(&0));
+	}
+	
+	#[test]
+	fn stale_packets_time_out() {
+		let mut reassembler = Reassembler::default();
+		let start = Instant::now();
+		
+		reassembler.decode_at(&fragment(0, 0, 2, vec![0; 16]), start).unwrap();
+		reassembler.decode_at(&fragment(1, 0, 2, vec![0; 16]), start + PENDING_PACKET_TIMEOUT / 2).unwrap();
+		reassembler.decode_at(&fragment(2, 0, 2, vec![0; 16]), start + PENDING_PACKET_TIMEOUT).unwrap();
+		
+		let mut pending: Vec<u32> = reassembler.0.keys().copied().collect();
+		pending.sort();
+		assert_eq!(pending, vec![1, 2]);
+	}
+}
@@ -14,4 +14,12 @@ pub enum NetworkError {
 	QueryEntityError(#[from] bevy::ecs::query::QueryEntityError),
 	#[error("tile event error: {0}")]
 	TileEventError(#[from] crate::world::TileEventError),
+	#[error("error during compression: {0}")]
+	Compression(#[from] std::io::Error),
+	#[error("payload of {0} bytes is bigger than the limit of {1} bytes")]
+	OversizedPayload(usize, usize),
+	#[error("corrupt payload: {0}")]
+	CorruptPayload(String),
+	#[error("tile batch error: {0}")]
+	TileBatchError(#[from] crate::networking::batch::TileBatchError),
 }
@@ -0,0 +1,262 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::VERSION_STRING;
+
+use super::protocol::{DisconnectReason, PROTOCOL_VER, ProtocolVersion};
+
+/// Optional protocol features, as a set of flags.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+	pub const NONE: Capabilities = Capabilities(0);
+	/// Packets may be deflate-compressed.
+	pub const COMPRESSION: Capabilities = Capabilities(1 << 0);
+	
+	/// Every capability this build knows about, with their names.
+	const NAMED: [(Capabilities, &'static str); 1] = [
+		(Capabilities::COMPRESSION, "compression"),
+	];
+	
+	/// The capabilities this build supports.
+	pub const SUPPORTED: Capabilities = Capabilities::COMPRESSION;
+	/// The capabilities a peer must support to be able to talk to this build at all. Packets are only compressed once
+	/// both sides have agreed on it, so nothing is required.
+	pub const REQUIRED: Capabilities = Capabilities::NONE;
+	
+	pub const fn union(self, other: Capabilities) -> Capabilities {
+		Capabilities(self.0 | other.0)
+	}
+	
+	pub const fn intersection(self, other: Capabilities) -> Capabilities {
+		Capabilities(self.0 & other.0)
+	}
+	
+	/// Returns the capabilities in `self` that aren't in `other`.
+	pub const fn difference(self, other: Capabilities) -> Capabilities {
+		Capabilities(self.0 & !other.0)
+	}
+	
+	pub const fn contains(self, other: Capabilities) -> bool {
+		self.0 & other.0 == other.0
+	}
+	
+	pub const fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+}
+
+impl fmt::Display for Capabilities {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let mut names: Vec<String> = Capabilities::NAMED
+			.iter()
+			.filter(|(capability, _)| self.contains(*capability))
+			.map(|(_, name)| name.to_string())
+			.collect();
+		
+		let unknown = self.difference(Capabilities::NAMED.iter().fold(Capabilities::NONE, |all, (capability, _)| all.union(*capability)));
+		if !unknown.is_empty() {
+			names.push(format!("unknown ({:#X})", unknown.0));
+		}
+		
+		if names.is_empty() {
+			f.write_str("none")
+		} else {
+			f.write_str(&names.join(", "))
+		}
+	}
+}
+
+impl ProtocolVersion {
+	/// Returns whether a peer using this version can talk to a peer using the other one.
+	///
+	/// Only an exact match is supported: a build doesn't know which of its messages an older peer understands, or what
+	/// a newer peer's messages look like.
+	pub fn is_compatible_with(self, other: ProtocolVersion) -> bool {
+		self == other
+	}
+}
+
+/// What each side of a connection tells the other during the handshake.
+///
+/// The client sends its own in [ClientMessage::JoinRequest](super::protocol::ClientMessage::JoinRequest), and the
+/// server answers with the negotiated one in [ServerResponse::JoinAccept](super::protocol::ServerResponse::JoinAccept).
+/// The negotiated handshake is kept around as a component on the player on the server, and as a resource on the client.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Component, Resource)]
+pub struct Handshake {
+	pub protocol_ver: ProtocolVersion,
+	pub version_string: String,
+	pub capabilities: Capabilities,
+}
+
+impl Handshake {
+	/// The handshake of this build.
+	pub fn local() -> Self {
+		Self {
+			protocol_ver: PROTOCOL_VER,
+			version_string: VERSION_STRING.to_string(),
+			capabilities: Capabilities::SUPPORTED,
+		}
+	}
+	
+	/// Agrees on a protocol version and the capabilities to use with a peer, or returns why the peer can't connect.
+	///
+	/// The result is the shared version, this build's version string and the capabilities both sides support.
+	pub fn negotiate(&self, peer: &Handshake) -> Result<Handshake, DisconnectReason> {
+		if !self.protocol_ver.is_compatible_with(peer.protocol_ver) {
+			return Err(DisconnectReason::ProtocolReject {
+				required_protocol_ver: self.protocol_ver,
+				required_version_string: self.version_string.clone(),
+			})
+		}
+		
+		let missing = Capabilities::REQUIRED.difference(peer.capabilities);
+		if !missing.is_empty() {
+			return Err(DisconnectReason::MissingCapabilities(missing))
+		}
+		
+		Ok(Handshake {
+			protocol_ver: self.protocol_ver,
+			version_string: self.version_string.clone(),
+			capabilities: self.capabilities.intersection(peer.capabilities),
+		})
+	}
+	
+	/// Checks that a handshake negotiated by the peer is one this build can actually use.
+	pub fn accepts(&self, negotiated: &Handshake) -> bool {
+		self.protocol_ver.is_compatible_with(negotiated.protocol_ver)
+			&& self.capabilities.contains(negotiated.capabilities)
+			&& negotiated.capabilities.contains(Capabilities::REQUIRED)
+	}
+}
+
+/// The [Packet](super::protocol::Packet) and [ClientMessage](super::protocol::ClientMessage) variant tags that every join
+/// request starts with, followed by the client's protocol version.
+const JOIN_REQUEST_PREFIX: [u8; 8] = [0, 0, 0, 2, 0, 0, 0, 0];
+/// The bytes in front of the packet in an unfragmented, uncompressed envelope: its variant tag and the packet's length.
+const PLAIN_ENVELOPE_HEADER: usize = 12;
+
+/// The protocol version read from the start of a join request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JoinVersion {
+	/// Sent by a client from before envelopes, which only understands packets as they are.
+	Legacy(ProtocolVersion),
+	Enveloped(ProtocolVersion),
+}
+
+impl JoinVersion {
+	pub fn protocol_ver(self) -> ProtocolVersion {
+		match self {
+			JoinVersion::Legacy(protocol_ver) | JoinVersion::Enveloped(protocol_ver) => protocol_ver,
+		}
+	}
+}
+
+/// Reads just the protocol version out of a message that might be a join request, without decoding the rest of it.
+/// Clients with a handshake this build can't decode can then still be told which version they need.
+pub fn peek_join_version(bytes: &[u8]) -> Option<JoinVersion> {
+	let read_version = |bytes: &[u8]| {
+		let version: [u8; 4] = bytes.get(JOIN_REQUEST_PREFIX.len()..JOIN_REQUEST_PREFIX.len() + 4)?.try_into().ok()?;
+		bytes.starts_with(&JOIN_REQUEST_PREFIX).then(|| ProtocolVersion(u32::from_be_bytes(version)))
+	};
+	
+	// the legacy join request held nothing but the version
+	if bytes.len() == JOIN_REQUEST_PREFIX.len() + 4 {
+		return read_version(bytes).map(JoinVersion::Legacy)
+	}
+	if bytes.starts_with(&[0, 0, 0, 0]) {
+		return read_version(bytes.get(PLAIN_ENVELOPE_HEADER..)?).map(JoinVersion::Enveloped)
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::networking::channel::Channel;
+	use crate::networking::envelope::Encoder;
+	use crate::networking::protocol::{ClientMessage, Packet};
+	use crate::utils;
+	
+	use super::*;
+	
+	fn handshake(protocol_ver: u32, capabilities: Capabilities) -> Handshake {
+		Handshake {
+			protocol_ver: ProtocolVersion(protocol_ver),
+			version_string: "peer".to_string(),
+			capabilities,
+		}
+	}
+	
+	#[test]
+	fn compatible_versions() {
+		assert!(PROTOCOL_VER.is_compatible_with(PROTOCOL_VER));
+		assert!(!PROTOCOL_VER.is_compatible_with(ProtocolVersion(PROTOCOL_VER.0 + 1)));
+		assert!(!PROTOCOL_VER.is_compatible_with(ProtocolVersion(PROTOCOL_VER.0 - 1)));
+		assert!(!PROTOCOL_VER.is_compatible_with(ProtocolVersion(0)));
+	}
+	
+	#[test]
+	fn negotiates_shared_capabilities() {
+		let local = Handshake::local();
+		
+		let same = local.negotiate(&handshake(PROTOCOL_VER.0, Capabilities::COMPRESSION)).unwrap();
+		assert_eq!(same.protocol_ver, PROTOCOL_VER);
+		assert_eq!(same.version_string, local.version_string);
+		assert_eq!(same.capabilities, Capabilities::COMPRESSION);
+		
+		let everything = local.negotiate(&handshake(PROTOCOL_VER.0, Capabilities(!0))).unwrap();
+		assert_eq!(everything.capabilities, Capabilities::SUPPORTED);
+		
+		let bare = local.negotiate(&handshake(PROTOCOL_VER.0, Capabilities::NONE)).unwrap();
+		assert_eq!(bare.capabilities, Capabilities::NONE);
+	}
+	
+	#[test]
+	fn rejects_other_versions() {
+		for protocol_ver in [PROTOCOL_VER.0 - 1, PROTOCOL_VER.0 + 1] {
+			let reason = Handshake::local().negotiate(&handshake(protocol_ver, Capabilities::SUPPORTED)).unwrap_err();
+			assert_eq!(reason, DisconnectReason::ProtocolReject {
+				required_protocol_ver: PROTOCOL_VER,
+				required_version_string: VERSION_STRING.to_string(),
+			});
+		}
+	}
+	
+	#[test]
+	fn accepts_only_what_it_can_use() {
+		let local = Handshake::local();
+		let negotiated = local.negotiate(&local).unwrap();
+		assert!(local.accepts(&negotiated));
+		assert!(local.accepts(&handshake(PROTOCOL_VER.0, Capabilities::NONE)));
+		
+		assert!(!local.accepts(&handshake(PROTOCOL_VER.0 + 1, Capabilities::NONE)));
+		assert!(!local.accepts(&handshake(PROTOCOL_VER.0 - 1, Capabilities::NONE)));
+		assert!(!local.accepts(&handshake(PROTOCOL_VER.0, Capabilities(1 << 31))));
+	}
+	
+	#[test]
+	fn capabilities_display() {
+		assert_eq!(Capabilities::NONE.to_string(), "none");
+		assert_eq!(Capabilities::COMPRESSION.to_string(), "compression");
+		assert_eq!(Capabilities(0b11).to_string(), "compression, unknown (0x2)");
+	}
+	
+	#[test]
+	fn peeks_join_versions() {
+		let request = Packet::from(ClientMessage::JoinRequest(Handshake::local()));
+		let payloads = Encoder::default().encode(&request, Channel::Control, Capabilities::NONE).unwrap();
+		assert_eq!(peek_join_version(&payloads[0]), Some(JoinVersion::Enveloped(PROTOCOL_VER)));
+		
+		// what a client from before envelopes sends
+		let legacy = [0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+		assert_eq!(peek_join_version(&legacy), Some(JoinVersion::Legacy(ProtocolVersion(0))));
+		
+		let leave = utils::serialize_be(&Packet::from(ClientMessage::LeaveWorld)).unwrap();
+		assert_eq!(peek_join_version(&leave), None);
+		assert_eq!(peek_join_version(&[]), None);
+	}
+}
@@ -0,0 +1,392 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use renet::RenetClient;
+
+use crate::env::EnvType;
+use crate::world::{ClientGameWorld, WorldId};
+use crate::{client, server, GameState, Headless};
+
+use super::Username;
+use super::channel::MessageChannel;
+use super::envelope::Encoder;
+use super::error::NetworkError;
+use super::handshake::{Capabilities, Handshake};
+use super::protocol::{ClientMessage, Packet};
+use super::transport::MemoryNetwork;
+
+/// A server and any number of clients that talk over a [MemoryNetwork] and are updated one frame at a time, so that the
+/// whole client/server flow can be driven without windows or sockets.
+///
+/// Every app is headless, so the clients never see their menus: [Lockstep::connect] sends them straight to connecting
+/// once their assets have loaded, and [Lockstep::enter_world] does what the world select screen would. Anything else
+/// can be checked or changed through the apps' worlds between steps.
+pub struct Lockstep {
+	pub server: App,
+	pub clients: Vec<App>,
+}
+
+impl Lockstep {
+	/// Builds a server that saves into `save_dir`, and a client for each username. Nothing runs until the apps are
+	/// stepped.
+	pub fn new(save_dir: impl Into<PathBuf>, usernames: &[&str]) -> Self {
+		let network = MemoryNetwork::default();
+		
+		let mut server = crate::build_app(EnvType::Server, Headless(true));
+		server
+			.insert_resource(server::networking::ServerConfig {
+				max_clients: usernames.len().max(1),
+				query_port: None,
+				save_dir: save_dir.into(),
+				..default()
+			})
+			.insert_resource(network.clone())
+			.add_plugins(server::networking::NetworkingPlugin);
+		
+		let clients = usernames
+			.iter()
+			.map(|username| {
+				let mut client = crate::build_app(EnvType::Client, Headless(true));
+				client
+					.insert_resource(Username(username.to_string()))
+					.insert_resource(network.clone())
+					.add_plugins(client::networking::NetworkingPlugin);
+				client
+			})
+			.collect();
+		
+		Self {
+			server,
+			clients,
+		}
+	}
+	
+	/// Updates the server, then every client, once.
+	pub fn step(&mut self) {
+		self.server.update();
+		for client in self.clients.iter_mut() {
+			client.update();
+		}
+	}
+	
+	/// Steps until the condition holds, at most `max_steps` times. Returns whether it held.
+	pub fn step_until(&mut self, max_steps: usize, mut condition: impl FnMut(&Self) -> bool) -> bool {
+		for _ in 0..max_steps {
+			if condition(self) {
+				return true
+			}
+			self.step();
+		}
+		condition(self)
+	}
+	
+	pub fn server_state(&self) -> &GameState {
+		self.server.world.resource::<State<GameState>>().get()
+	}
+	
+	pub fn client_state(&self, client: usize) -> &GameState {
+		self.clients[client].world.resource::<State<GameState>>().get()
+	}
+	
+	/// Steps until every app has loaded, then connects every client and steps until they have all joined. Each of the
+	/// two waits is at most `max_steps` long. Returns whether every client joined.
+	pub fn connect(&mut self, max_steps: usize) -> bool {
+		let loaded = self.step_until(max_steps, |lockstep| {
+			*lockstep.server_state() == GameState::ServerLoaded
+				&& (0..lockstep.clients.len()).all(|client| *lockstep.client_state(client) != GameState::LoadingAssets)
+		});
+		if !loaded {
+			return false
+		}
+		
+		for client in self.clients.iter_mut() {
+			client.world.resource_mut::<NextState<GameState>>().set(GameState::ClientConnecting);
+		}
+		
+		self.step_until(max_steps, |lockstep| {
+			(0..lockstep.clients.len()).all(|client| *lockstep.client_state(client) == GameState::WorldSelect)
+		})
+	}
+	
+	/// Makes the client ask to enter the world, like the world select screen does.
+	pub fn enter_world(&mut self, client: usize, world_name: &str) -> Result<(), NetworkError> {
+		self.clients[client].world.insert_resource(
+			ClientGameWorld {
+				id: WorldId(world_name.to_string()),
+				tiles: default(),
+				tile_entities: default(),
+				tile_sprites: default(),
+				light: default(),
+				time: default(),
+			}
+		);
+		self.send(client, ClientMessage::EnterWorldRequest(world_name.to_string()))
+	}
+	
+	/// Asks the server to take the client out of its world. The client is back at the world select screen once the
+	/// server has answered.
+	pub fn leave_world(&mut self, client: usize) -> Result<(), NetworkError> {
+		self.send(client, ClientMessage::LeaveWorld)
+	}
+	
+	/// Sends a message from the client. The server gets it on the next step.
+	pub fn send(&mut self, client: usize, message: ClientMessage) -> Result<(), NetworkError> {
+		let world = &mut self.clients[client].world;
+		let packet = Packet::from(message);
+		let channel = packet.channel();
+		let capabilities = world
+			.get_resource::<Handshake>()
+			.map_or(Capabilities::NONE, |handshake| handshake.capabilities);
+		let payloads = world.resource_mut::<Encoder>().encode(&packet, channel, capabilities)?;
+		let mut renet_client = world.resource_mut::<RenetClient>();
+		for payload in payloads {
+			renet_client.send_message(channel, payload);
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::Position;
+	use crate::client::networking::{LocalPlayer, Roster};
+	use crate::inventory::{Inventory, ItemStack};
+	use crate::networking::protocol::{ChatMessageContent, ClientId};
+	use crate::player::Target;
+	use crate::raw_id::item::RawItemIds;
+	use crate::server::networking::Players;
+	use crate::server::tile::TILE_REACH;
+	use crate::tile::layer::TileLayer;
+	use crate::world::ServerGameWorlds;
+	
+	use super::*;
+	
+	const MAX_STEPS: usize = 1000;
+	const WORLD: &str = "lockstep";
+	
+	/// A save directory of its own for each test, removed again once the test is done.
+	struct TempSaveDir(PathBuf);
+	
+	impl TempSaveDir {
+		fn new(test: &str) -> Self {
+			let path = std::env::temp_dir().join(format!("botanica-lockstep-{}-{}", test, std::process::id()));
+			let _ = std::fs::remove_dir_all(&path);
+			Self(path)
+		}
+	}
+	
+	impl Drop for TempSaveDir {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.0);
+		}
+	}
+	
+	/// Returns whether the client has every tile of the server's copy of the world, and nothing else.
+	fn tiles_match(lockstep: &Lockstep, client: usize) -> bool {
+		let Some(server_world) = lockstep.server.world.resource::<ServerGameWorlds>().get_world(WORLD) else {
+			return false
+		};
+		let Some(client_world) = lockstep.clients[client].world.get_resource::<ClientGameWorld>() else {
+			return false
+		};
+		
+		// the client doesn't keep air around
+		let server_tiles: Vec<_> = server_world.tiles
+			.iter()
+			.filter(|(_, _, tile)| !tile.is_air())
+			.collect();
+		server_tiles.len() == client_world.tiles.iter().count()
+			&& server_tiles.iter().all(|(layer, pos, tile)| client_world.get_tile(*layer, pos) == Some(*tile))
+	}
+	
+	fn local_client_id(lockstep: &mut Lockstep, client: usize) -> ClientId {
+		let world = &mut lockstep.clients[client].world;
+		*world.query_filtered::<&ClientId, With<LocalPlayer>>().single(world)
+	}
+	
+	/// Makes the client leave its world, and steps until it's back at the world select screen.
+	fn leave(lockstep: &mut Lockstep, client: usize) {
+		lockstep.leave_world(client).unwrap();
+		let left = lockstep.step_until(MAX_STEPS, |lockstep| *lockstep.client_state(client) == GameState::WorldSelect);
+		assert!(left, "the client should be back at the world select screen");
+	}
+	
+	/// Connects every client and puts them all in the same world, with all of its tiles.
+	fn in_world(save_dir: &TempSaveDir, usernames: &[&str]) -> Lockstep {
+		let mut lockstep = Lockstep::new(&save_dir.0, usernames);
+		assert!(lockstep.connect(MAX_STEPS), "every client should join");
+		
+		// one at a time, so that the players who are already in the world see the ones who come after them
+		for client in 0..usernames.len() {
+			lockstep.enter_world(client, WORLD).unwrap();
+			let entered = lockstep.step_until(MAX_STEPS, |lockstep| {
+				matches!(lockstep.client_state(client), GameState::LoadingWorld | GameState::InWorld)
+					&& tiles_match(lockstep, client)
+			});
+			assert!(entered, "every client should enter the world and get its tiles");
+		}
+		lockstep
+	}
+	
+	#[test]
+	fn clients_connect() {
+		let save_dir = TempSaveDir::new("connect");
+		let mut lockstep = Lockstep::new(&save_dir.0, &["alice", "bob"]);
+		assert!(lockstep.connect(MAX_STEPS));
+		
+		for client in 0..2 {
+			let handshake = lockstep.clients[client].world.resource::<Handshake>();
+			assert_eq!(*handshake, Handshake::local().negotiate(&Handshake::local()).unwrap());
+		}
+	}
+	
+	#[test]
+	fn chat_arrives() {
+		let save_dir = TempSaveDir::new("chat");
+		let mut lockstep = in_world(&save_dir, &["alice", "bob"]);
+		
+		lockstep.send(0, ClientMessage::ChatMessage(Target::All, "hello".to_string())).unwrap();
+		let arrived = lockstep.step_until(MAX_STEPS, |lockstep| {
+			let world = &lockstep.clients[0].world;
+			world
+				.iter_entities()
+				.filter_map(|entity| entity.get::<ChatMessageContent>())
+				.any(|content| content.0 == "hello")
+		});
+		assert!(arrived, "the chat message should come back from the server");
+	}
+	
+	#[test]
+	fn broken_tiles_reach_other_clients() {
+		let save_dir = TempSaveDir::new("break");
+		let mut lockstep = in_world(&save_dir, &["alice", "bob"]);
+		
+		// both players are new, so they're at the spawnpoint
+		let (pos, tile) = {
+			let world = lockstep.server.world.resource::<ServerGameWorlds>().get_world(WORLD).unwrap();
+			let spawnpoint = world.spawnpoint;
+			world.tiles
+				.iter()
+				.filter(|(layer, _, tile)| *layer == TileLayer::Foreground && !tile.is_air())
+				.find(|(_, pos, _)| Vec2::new(pos.x as f32 - spawnpoint.x, pos.y as f32 - spawnpoint.y).length() <= TILE_REACH)
+				.map(|(_, pos, tile)| (*pos, tile.clone()))
+				.expect("there should be a tile within reach of the spawnpoint")
+		};
+		assert_eq!(lockstep.clients[1].world.resource::<ClientGameWorld>().get_tile(TileLayer::Foreground, &pos), Some(&tile));
+		
+		lockstep.send(0, ClientMessage::BreakTile(TileLayer::Foreground, pos)).unwrap();
+		let broken = lockstep.step_until(MAX_STEPS, |lockstep| {
+			lockstep.clients[1].world.resource::<ClientGameWorld>().get_tile(TileLayer::Foreground, &pos).is_none()
+		});
+		assert!(broken, "the other client should see the tile broken");
+		
+		let server_world = lockstep.server.world.resource::<ServerGameWorlds>().get_world(WORLD).unwrap();
+		assert!(server_world.tiles.get(TileLayer::Foreground, &pos).map_or(true, |tile| tile.is_air()));
+		assert!(tiles_match(&lockstep, 0));
+		assert!(tiles_match(&lockstep, 1));
+	}
+	
+	#[test]
+	fn tiles_out_of_reach_stay() {
+		let save_dir = TempSaveDir::new("reach");
+		let mut lockstep = in_world(&save_dir, &["alice", "bob"]);
+		
+		let pos = {
+			let world = lockstep.server.world.resource::<ServerGameWorlds>().get_world(WORLD).unwrap();
+			let spawnpoint = world.spawnpoint;
+			world.tiles
+				.iter()
+				.filter(|(layer, _, tile)| *layer == TileLayer::Foreground && !tile.is_air())
+				.find(|(_, pos, _)| Vec2::new(pos.x as f32 - spawnpoint.x, pos.y as f32 - spawnpoint.y).length() > TILE_REACH * 2.0)
+				.map(|(_, pos, _)| *pos)
+				.expect("there should be a tile out of reach of the spawnpoint")
+		};
+		
+		lockstep.send(0, ClientMessage::BreakTile(TileLayer::Foreground, pos)).unwrap();
+		for _ in 0..10 {
+			lockstep.step();
+		}
+		assert!(lockstep.clients[1].world.resource::<ClientGameWorld>().get_tile(TileLayer::Foreground, &pos).is_some());
+		assert!(tiles_match(&lockstep, 0));
+		assert!(tiles_match(&lockstep, 1));
+	}
+	
+	#[test]
+	fn leaving_clears_the_world() {
+		let save_dir = TempSaveDir::new("leave");
+		let mut lockstep = in_world(&save_dir, &["alice", "bob"]);
+		
+		let bob = local_client_id(&mut lockstep, 1);
+		let arrived = lockstep.step_until(MAX_STEPS, |lockstep| lockstep.clients[0].world.resource::<Players>().contains_key(&bob));
+		assert!(arrived, "the other player should be in the world");
+		let tile_sprites: Vec<Entity> = lockstep.clients[0].world.resource::<ClientGameWorld>().tile_sprites.values().copied().collect();
+		let player_entities: Vec<Entity> = lockstep.clients[0].world.resource::<Players>().values().copied().collect();
+		assert!(!tile_sprites.is_empty());
+		
+		leave(&mut lockstep, 0);
+		let world = &lockstep.clients[0].world;
+		assert!(world.get_resource::<ClientGameWorld>().is_none());
+		assert!(world.resource::<Players>().is_empty());
+		assert!(tile_sprites.iter().chain(player_entities.iter()).all(|entity| world.get_entity(*entity).is_none()));
+	}
+	
+	#[test]
+	fn others_see_the_player_leave() {
+		let save_dir = TempSaveDir::new("others");
+		let mut lockstep = in_world(&save_dir, &["alice", "bob"]);
+		
+		let bob = local_client_id(&mut lockstep, 1);
+		let arrived = lockstep.step_until(MAX_STEPS, |lockstep| {
+			let world = &lockstep.clients[0].world;
+			world.resource::<Players>().contains_key(&bob)
+				&& world.resource::<Roster>().get(bob).is_some_and(|entry| entry.world == Some(WorldId(WORLD.to_string())))
+		});
+		assert!(arrived, "the other client should see the player in the world");
+		let player_entity = lockstep.clients[0].world.resource::<Players>()[&bob];
+		
+		leave(&mut lockstep, 1);
+		let gone = lockstep.step_until(MAX_STEPS, |lockstep| {
+			let world = &lockstep.clients[0].world;
+			!world.resource::<Players>().contains_key(&bob)
+				&& world.resource::<Roster>().get(bob).is_some_and(|entry| entry.world.is_none())
+		});
+		assert!(gone, "the other client should see the player leave the world, but not the server");
+		assert!(lockstep.clients[0].world.get_entity(player_entity).is_none());
+	}
+	
+	#[test]
+	fn players_come_back_where_they_left() {
+		let save_dir = TempSaveDir::new("reenter");
+		let mut lockstep = in_world(&save_dir, &["alice"]);
+		
+		let alice = local_client_id(&mut lockstep, 0);
+		let player_entity = lockstep.server.world.resource::<Players>()[&alice];
+		let raw_id = *lockstep.server.world.resource::<RawItemIds>().get_raw_ids().next().expect("there should be an item");
+		let spawnpoint = lockstep.server.world.resource::<ServerGameWorlds>().get_world(WORLD).unwrap().spawnpoint;
+		let position = Position { x: spawnpoint.x + 3.5, y: spawnpoint.y - 2.0 };
+		let inventory = {
+			let mut player = lockstep.server.world.entity_mut(player_entity);
+			*player.get_mut::<Position>().unwrap() = position;
+			let mut inventory = player.get_mut::<Inventory>().unwrap();
+			inventory.set(3, Some(ItemStack::new(raw_id, 5)));
+			inventory.clone()
+		};
+		
+		leave(&mut lockstep, 0);
+		assert!(lockstep.server.world.get::<Position>(player_entity).is_none());
+		assert!(lockstep.server.world.get::<Inventory>(player_entity).is_none());
+		
+		lockstep.enter_world(0, WORLD).unwrap();
+		let entered = lockstep.step_until(MAX_STEPS, |lockstep| {
+			let world = &lockstep.clients[0].world;
+			*lockstep.client_state(0) != GameState::WorldSelect
+				&& world
+					.iter_entities()
+					.filter(|entity| entity.contains::<LocalPlayer>())
+					.any(|entity| entity.get::<Inventory>() == Some(&inventory))
+		});
+		assert!(entered, "the client should get the inventory back");
+		assert_eq!(lockstep.server.world.get::<Position>(player_entity), Some(&position));
+		assert_eq!(lockstep.server.world.get::<Inventory>(player_entity), Some(&inventory));
+	}
+}
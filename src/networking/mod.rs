@@ -10,8 +10,19 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 pub mod protocol;
+pub mod batch;
+pub mod channel;
+pub mod envelope;
+pub mod handshake;
+pub mod nickname;
+pub mod query;
+pub mod transport;
+pub mod conditioner;
+#[cfg(test)]
+pub mod lockstep;
 pub mod debug;
 pub mod error;
+pub(crate) mod stats;
 
 pub const USERNAME_BYTES: usize = 32;
 
@@ -79,41 +90,3 @@ impl Username {
 pub fn time_since_epoch() -> Duration {
 	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap()
 }
-
-pub(crate) mod stats {
-	use crate::utils::BevyHashMap;
-	
-	
-	use bevy::ecs::system::Resource;
-	
-	use crate::networking::protocol::ClientId;
-	
-	/// A player's network statistics
-	#[derive(Debug, Default, Clone)]
-	pub struct PlayerNetStat {
-		/// The player's latency in milliseconds
-		pub ping: u128,
-	}
-	
-	#[derive(Debug, Default, Clone, Resource)]
-	pub struct PlayerNetStats(BevyHashMap<ClientId, PlayerNetStat>);
-	
-	impl PlayerNetStats {
-		/// Retrieves an optional network statistics struct that corresponds to the client ID.
-		pub fn get(&self, client_id: ClientId) -> Option<&PlayerNetStat> {
-			self.0.get(&client_id)
-		}
-		
-		/// Retrieves (or creates if non-existent) a mutable network statistics struct that corresponds to the client ID.
-		pub fn get_mut(&mut self, client_id: ClientId) -> &mut PlayerNetStat {
-			self.0
-				.entry(client_id)
-				.or_default()
-		}
-		
-		/// Removes the network statistics struct from the hash map. Returns Some with the removed network statistics struct if successful, None if non-existent.
-		pub fn remove(&mut self, client_id: ClientId) -> Option<PlayerNetStat> {
-			self.0.remove(&client_id)
-		}
-	}
-}
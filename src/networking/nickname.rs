@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::utils::strip_formatting;
+
+/// The most characters a nickname can have, not counting formatting codes.
+pub const NICKNAME_MAX_LENGTH: usize = 24;
+
+/// The formatting codes nicknames may use: the colors `0` to `9` and `a` to `f`, and `r` to reset the color.
+pub const NICKNAME_FORMATTING_CODES: &'static str = "0123456789abcdefr";
+
+/// The reason a nickname has been denied.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Error)]
+pub enum NickDenyReason {
+	#[error("nicknames can be at most {0} characters long")]
+	TooLong(usize),
+	#[error("the nickname has no visible characters")]
+	Blank,
+	#[error("nicknames can't contain {0:?}")]
+	InvalidCharacter(char),
+	#[error("nicknames can't use the formatting code `{0}")]
+	InvalidFormattingCode(char),
+	#[error("the nickname is already taken")]
+	Taken,
+}
+
+/// Checks that the nickname only has letters, digits, spaces, dashes, underscores and the formatting codes in
+/// [NICKNAME_FORMATTING_CODES], and that it isn't too long or blank. Whether it's taken is up to the server.
+pub fn validate_nickname(nickname: &str) -> Result<(), NickDenyReason> {
+	let mut chars = nickname.chars();
+	let mut length = 0;
+	while let Some(c) = chars.next() {
+		match c {
+			'`' => match chars.next() {
+				Some(code) if NICKNAME_FORMATTING_CODES.contains(code) => {},
+				Some(code) => return Err(NickDenyReason::InvalidFormattingCode(code)),
+				None => return Err(NickDenyReason::InvalidCharacter('`')),
+			},
+			c if c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_' => length += 1,
+			c => return Err(NickDenyReason::InvalidCharacter(c)),
+		}
+	}
+	
+	if length > NICKNAME_MAX_LENGTH {
+		return Err(NickDenyReason::TooLong(NICKNAME_MAX_LENGTH))
+	}
+	if nickname_key(nickname).trim().is_empty() {
+		return Err(NickDenyReason::Blank)
+	}
+	
+	Ok(())
+}
+
+/// Returns what the name looks like for telling names apart: without formatting, and ignoring case.
+pub fn nickname_key(name: &str) -> String {
+	strip_formatting(name).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn valid_nicknames() {
+		assert_eq!(validate_nickname("Steve"), Ok(()));
+		assert_eq!(validate_nickname("a-b_c 123"), Ok(()));
+		assert_eq!(validate_nickname("`cRed`r and `9blue"), Ok(()));
+	}
+	
+	#[test]
+	fn length_ignores_formatting() {
+		let longest = "a".repeat(NICKNAME_MAX_LENGTH);
+		assert_eq!(validate_nickname(&longest), Ok(()));
+		assert_eq!(validate_nickname(&format!("`a{longest}`r")), Ok(()));
+		assert_eq!(validate_nickname(&format!("{longest}a")), Err(NickDenyReason::TooLong(NICKNAME_MAX_LENGTH)));
+	}
+	
+	#[test]
+	fn blank_nicknames() {
+		assert_eq!(validate_nickname(""), Err(NickDenyReason::Blank));
+		assert_eq!(validate_nickname("   "), Err(NickDenyReason::Blank));
+		assert_eq!(validate_nickname("`a`b"), Err(NickDenyReason::Blank));
+		assert_eq!(validate_nickname("`a `r"), Err(NickDenyReason::Blank));
+	}
+	
+	#[test]
+	fn invalid_characters() {
+		assert_eq!(validate_nickname("a.b"), Err(NickDenyReason::InvalidCharacter('.')));
+		assert_eq!(validate_nickname("tab\there"), Err(NickDenyReason::InvalidCharacter('\t')));
+		assert_eq!(validate_nickname("café"), Err(NickDenyReason::InvalidCharacter('é')));
+		// a formatting code has to follow the backtick
+		assert_eq!(validate_nickname("name`"), Err(NickDenyReason::InvalidCharacter('`')));
+	}
+	
+	#[test]
+	fn invalid_formatting_codes() {
+		assert_eq!(validate_nickname("`gname"), Err(NickDenyReason::InvalidFormattingCode('g')));
+		assert_eq!(validate_nickname("`Aname"), Err(NickDenyReason::InvalidFormattingCode('A')));
+		assert_eq!(validate_nickname("``name"), Err(NickDenyReason::InvalidFormattingCode('`')));
+	}
+	
+	#[test]
+	fn keys_ignore_case_and_formatting() {
+		assert_eq!(nickname_key("Steve"), "steve");
+		assert_eq!(nickname_key("`cSTEVE`r"), "steve");
+		assert_eq!(nickname_key("St`4eve"), nickname_key("sTEVE"));
+		assert_ne!(nickname_key("Steve"), nickname_key("Steve2"));
+		assert_ne!(nickname_key("Steve"), nickname_key("Ste ve"));
+	}
+}
@@ -1,21 +1,28 @@
-use crate::utils::BevyHashMap;
 use std::fmt;
 use std::fmt::Formatter;
 
 use bevy::prelude::*;
-use renet::Bytes;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::crafting::{CraftDenyReason, CraftingGrid};
+use crate::identifier::Identifier;
+use crate::inventory::{Inventory, InventoryAction};
+use crate::raw_id::item::RawItemIds;
 use crate::raw_id::tile::RawTileIds;
-use crate::tile::WorldTile;
-use crate::world::{WorldBanUntil, WorldId};
+use crate::tile::entity::{ChunkTileEntities, TileEntity};
+use crate::tile::layer::TileLayer;
+use crate::world::{ChunkPos, WorldBanUntil, WorldId, WorldTime};
 use crate::{TilePos, Username, Position};
-use crate::networking::error::NetworkError;
+use crate::networking::batch::TileUpdateBatch;
+use crate::networking::handshake::{Capabilities, Handshake};
+use crate::networking::nickname::NickDenyReason;
 use crate::player::{Source, Target};
 
 pub const PROTOCOL_ID: u64 = 0x460709E200F3661E;
-pub const PROTOCOL_VER: ProtocolVersion = ProtocolVersion(0);
+/// The protocol version of this build. Peers can only talk to each other if they use exactly the same version, since
+/// messages aren't gated on the version of the connection.
+pub const PROTOCOL_VER: ProtocolVersion = ProtocolVersion(5);
 pub const CLIENT_TIMEOUT: u64 = 5000;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -36,13 +43,67 @@ pub enum Packet {
 	ClientResponse(ClientResponse),
 }
 
-macro_rules! impl_try_into_bytes {
+impl Packet {
+	/// Returns the name of the message type, for statistics and logs.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Packet::ServerMessage(message) => match message {
+				ServerMessage::Ping { .. } => "Ping",
+				ServerMessage::PlayerPings(_) => "PlayerPings",
+				ServerMessage::Disconnect(_) => "Disconnect",
+				ServerMessage::PlayerJoin(..) => "PlayerJoin",
+				ServerMessage::PlayerLeave(_) => "PlayerLeave",
+				ServerMessage::PlayerNick(..) => "PlayerNick",
+				ServerMessage::ChatMessage(_) => "ChatMessage",
+				ServerMessage::PlayerPosition(..) => "PlayerPosition",
+				ServerMessage::Roster(_) => "Roster",
+				ServerMessage::RosterJoin(_) => "RosterJoin",
+				ServerMessage::RosterLeave(_) => "RosterLeave",
+				ServerMessage::RosterWorld(..) => "RosterWorld",
+				ServerMessage::RawTileIds(_) => "RawTileIds",
+				ServerMessage::RawItemIds(_) => "RawItemIds",
+				ServerMessage::TileUpdateBatch(_) => "TileUpdateBatch",
+				ServerMessage::TileEntities(..) => "TileEntities",
+				ServerMessage::SetTileEntity(..) => "SetTileEntity",
+				ServerMessage::Inventory(_) => "Inventory",
+				ServerMessage::WorldTime(_) => "WorldTime",
+			},
+			Packet::ServerResponse(response) => match response {
+				ServerResponse::JoinDeny(_) => "JoinDeny",
+				ServerResponse::JoinAccept(_) => "JoinAccept",
+				ServerResponse::PingAck { .. } => "PingAck",
+				ServerResponse::EnterWorldDeny(_) => "EnterWorldDeny",
+				ServerResponse::EnterWorldAccept(_) => "EnterWorldAccept",
+				ServerResponse::LeaveWorldAccept => "LeaveWorldAccept",
+				ServerResponse::CraftAccept(_) => "CraftAccept",
+				ServerResponse::CraftDeny(_) => "CraftDeny",
+				ServerResponse::NickDeny(_) => "NickDeny",
+			},
+			Packet::ClientMessage(message) => match message {
+				ClientMessage::JoinRequest(_) => "JoinRequest",
+				ClientMessage::Ping { .. } => "Ping",
+				ClientMessage::ChatMessage(..) => "ChatMessage",
+				ClientMessage::EnterWorldRequest(_) => "EnterWorldRequest",
+				ClientMessage::LeaveWorld => "LeaveWorld",
+				ClientMessage::PlayerPosition(_) => "PlayerPosition",
+				ClientMessage::BreakTile(..) => "BreakTile",
+				ClientMessage::PlaceTile(..) => "PlaceTile",
+				ClientMessage::InventoryAction(_) => "InventoryAction",
+				ClientMessage::CraftRequest(_) => "CraftRequest",
+				ClientMessage::NickRequest(_) => "NickRequest",
+			},
+			Packet::ClientResponse(response) => match response {
+				ClientResponse::PingAck { .. } => "PingAck",
+			},
+		}
+	}
+}
+
+macro_rules! impl_into_packet {
     ($t:ident) => {
-		impl TryInto<Bytes> for $t {
-			type Error = NetworkError;
-			
-			fn try_into(self) -> Result<Bytes, Self::Error> {
-				Ok($crate::utils::serialize_be(&Packet::$t(self))?.into())
+		impl From<$t> for Packet {
+			fn from(value: $t) -> Self {
+				Packet::$t(value)
 			}
 		}
 	};
@@ -52,64 +113,101 @@ macro_rules! impl_try_into_bytes {
 /// A message that the server sends to a client (or to all/some clients).
 pub enum ServerMessage {
 	Ping {
-		/// The time the ping was sent.
+		/// The time the ping was sent, on the server's own clock. Only meaningful to the server, so the client just
+		/// echoes it back.
 		timestamp: u128,
 	},
+	/// The smoothed round-trip time of every player, in milliseconds.
+	PlayerPings(Vec<(ClientId, u32)>),
 	/// Signals a disconnection is about to happen and gives the reason.
 	Disconnect(DisconnectReason),
 	PlayerJoin(ClientId, PlayerData, Position),
 	PlayerLeave(ClientId),
-	/// A request from a player to change their display name (nickname).
+	/// A player's nickname has changed, or been cleared if empty.
 	PlayerNick(ClientId, String),
 	ChatMessage(ChatMessageBundle),
 	PlayerPosition(ClientId, Position),
+	/// Every player on the server. Sent once the client has joined; [ServerMessage::RosterJoin],
+	/// [ServerMessage::RosterLeave], [ServerMessage::RosterWorld] and [ServerMessage::PlayerNick] keep it up to date.
+	Roster(Vec<RosterEntry>),
+	/// A player has joined the server.
+	RosterJoin(RosterEntry),
+	/// A player has left the server.
+	RosterLeave(ClientId),
+	/// A player has entered a world, or left one if [None].
+	RosterWorld(ClientId, Option<WorldId>),
 	/// Syncs the server's [RawTileIds] with the client.
 	RawTileIds(RawTileIds),
-	WorldTiles(BevyHashMap<TilePos, WorldTile>),
+	/// Syncs the server's [RawItemIds] with the client.
+	RawItemIds(RawItemIds),
+	/// Every tile change in a chunk since the last tick, or every tile of the chunk when entering a world.
+	/// 
+	/// Changed tiles lose their [TileEntity]; if the new tile has one, a [ServerMessage::SetTileEntity] follows.
+	TileUpdateBatch(TileUpdateBatch),
+	/// Replaces every tile entity in the chunk.
+	TileEntities(ChunkPos, ChunkTileEntities),
+	/// A single tile entity has been set or removed.
+	SetTileEntity(TileLayer, TilePos, Option<TileEntity>),
+	/// Replaces the client's copy of their inventory.
+	Inventory(Inventory),
+	/// Syncs the time of the client's world.
+	WorldTime(WorldTime),
 }
 
-impl_try_into_bytes!(ServerMessage);
+impl_into_packet!(ServerMessage);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 /// A message that the server sends to a client in response to a message from that client.
 pub enum ServerResponse {
 	JoinDeny(DisconnectReason),
-	JoinAccept,
-	// Query { // todo: move to REST API
-	// 	/// The protocol version.
-	// 	protocol_ver: ProtocolVersion,
-	// 	/// The game version string.
-	// 	version: String,
-	// 	/// The message of the day.
-	// 	motd: String,
-	// },
+	/// The negotiated protocol version and capabilities, along with the server's version string.
+	JoinAccept(Handshake),
 	PingAck {
 		/// The time the ping was sent.
 		timestamp: u128,
 	},
 	EnterWorldDeny(WorldDenyReason),
 	EnterWorldAccept(WorldId),
+	/// The player has left their world. Nothing more about the world is sent after this.
+	LeaveWorldAccept,
+	/// The recipe has been crafted; an updated [ServerMessage::Inventory] follows.
+	CraftAccept(Identifier),
+	CraftDeny(CraftDenyReason),
+	/// The nickname hasn't been changed; nothing is broadcast.
+	NickDeny(NickDenyReason),
 }
 
-impl_try_into_bytes!(ServerResponse);
+impl_into_packet!(ServerResponse);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 /// A message that the client sends to the server.
 pub enum ClientMessage {
-	JoinRequest {
-		/// The version of the protocol.
-		protocol_ver: ProtocolVersion,
-	},
+	/// The client's protocol version, version string and capabilities.
+	JoinRequest(Handshake),
 	Ping {
 		/// The time the ping was sent.
 		timestamp: u128,
 	},
 	ChatMessage(Target, String),
+	/// A request to enter the world, leaving the world the player is in (if any) first.
 	EnterWorldRequest(String),
+	/// A request to leave the world the player is in, answered with [ServerResponse::LeaveWorldAccept].
+	LeaveWorld,
 	PlayerPosition(Position),
+	/// A request to break the tile at the given position in the layer.
+	BreakTile(TileLayer, TilePos),
+	/// A request to place the tile of the held item at the given position in the layer. Overlay tiles ignore the layer.
+	PlaceTile(TileLayer, TilePos),
+	/// A request to rearrange the player's inventory.
+	InventoryAction(InventoryAction),
+	/// A request to craft whatever recipe matches the grid.
+	CraftRequest(CraftingGrid),
+	/// A request to change the player's nickname, or to clear it if empty. If accepted, a [ServerMessage::PlayerNick]
+	/// is broadcast to everyone.
+	NickRequest(String),
 }
 
-impl_try_into_bytes!(ClientMessage);
+impl_into_packet!(ClientMessage);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 /// A message that the client sends to the server in response to a message the server sent.
@@ -120,7 +218,7 @@ pub enum ClientResponse {
 	},
 }
 
-impl_try_into_bytes!(ClientResponse);
+impl_into_packet!(ClientResponse);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Bundle)]
 pub struct ClientMessageBundle {
@@ -137,6 +235,8 @@ pub enum DisconnectReason {
 		/// The required version string
 		required_version_string: String,
 	},
+	#[error("Missing required capabilities: {0}")]
+	MissingCapabilities(Capabilities),
 	#[error("The userdata field is empty!")]
 	EmptyUserdata,
 	#[error("The player's data is non-existent!")]
@@ -170,6 +270,15 @@ pub struct PlayerData {
 	pub username: Username,
 }
 
+/// What every client knows about a player on the server, whether or not they're in the same world.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct RosterEntry {
+	pub client_id: ClientId,
+	pub data: PlayerData,
+	pub nickname: Option<String>,
+	pub world: Option<WorldId>,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Component)]
 pub struct ClientId(pub u64);
 
@@ -0,0 +1,253 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::utils;
+
+use super::protocol::ProtocolVersion;
+
+pub const QUERY_ERROR_MESSAGE: &'static str = "An error occurred while answering a status query";
+
+/// The first bytes of every status query datagram, so that stray datagrams are ignored.
+pub const QUERY_MAGIC: [u8; 4] = *b"BTQ1";
+/// The biggest status response, which keeps it within a single unfragmented UDP datagram.
+pub const MAX_RESPONSE_SIZE: usize = 1200;
+/// The size of a status request. Requests are padded to the size of the biggest response, so that a spoofed request
+/// can't make the server send more than it received.
+pub const REQUEST_SIZE: usize = MAX_RESPONSE_SIZE;
+
+#[derive(Debug, Error)]
+pub enum QueryError {
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("error during serialization: {0}")]
+	Serialization(#[from] bincode::Error),
+	#[error("the response isn't a status response")]
+	InvalidResponse,
+	#[error("the server didn't answer in time")]
+	TimedOut,
+}
+
+/// What a server reports about itself to anyone who asks, without needing a connection.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ServerStatus {
+	/// The message of the day.
+	pub motd: String,
+	/// The game version string.
+	pub version_string: String,
+	pub protocol_ver: ProtocolVersion,
+	/// The number of connected players.
+	pub players: u32,
+	pub max_clients: u32,
+	/// Every world on the server, by name. May be cut short to keep the response small.
+	pub worlds: Vec<WorldStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WorldStatus {
+	pub name: String,
+	/// The number of players in the world.
+	pub players: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StatusRequest {
+	magic: [u8; 4],
+	/// Echoed back in the response so that answers to earlier requests can be told apart.
+	token: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StatusResponse {
+	magic: [u8; 4],
+	token: u64,
+	status: ServerStatus,
+}
+
+/// Encodes a status request with the given token, padded to [REQUEST_SIZE].
+pub fn encode_request(token: u64) -> Result<Vec<u8>, QueryError> {
+	let mut bytes = utils::serialize_be(&StatusRequest { magic: QUERY_MAGIC, token })?;
+	bytes.resize(REQUEST_SIZE, 0);
+	Ok(bytes)
+}
+
+/// Returns the token of a status request, or [None] if the datagram isn't a (properly padded) status request.
+pub fn decode_request(bytes: &[u8]) -> Option<u64> {
+	if bytes.len() < REQUEST_SIZE {
+		return None
+	}
+	
+	// the padding is trailing bytes
+	let request: StatusRequest = utils::deserialize_trailing(bytes).ok()?;
+	(request.magic == QUERY_MAGIC).then_some(request.token)
+}
+
+/// Encodes a status response, leaving out worlds from the end of the list until it fits in [MAX_RESPONSE_SIZE]. If
+/// that isn't enough, the MOTD is cut short as well.
+pub fn encode_response(token: u64, mut status: ServerStatus) -> Result<Vec<u8>, QueryError> {
+	loop {
+		let response = StatusResponse { magic: QUERY_MAGIC, token, status };
+		let bytes = utils::serialize_be(&response)?;
+		status = response.status;
+		
+		if bytes.len() <= MAX_RESPONSE_SIZE || (status.worlds.is_empty() && status.motd.is_empty()) {
+			return Ok(bytes)
+		}
+		
+		if status.worlds.pop().is_none() {
+			let mut len = status.motd.len().saturating_sub(bytes.len() - MAX_RESPONSE_SIZE);
+			while !status.motd.is_char_boundary(len) {
+				len -= 1;
+			}
+			status.motd.truncate(len);
+		}
+	}
+}
+
+/// Returns the status in a response to the request with the given token.
+pub fn decode_response(bytes: &[u8], token: u64) -> Result<ServerStatus, QueryError> {
+	let response: StatusResponse = utils::deserialize_be(bytes)?;
+	if response.magic != QUERY_MAGIC || response.token != token {
+		return Err(QueryError::InvalidResponse)
+	}
+	Ok(response.status)
+}
+
+/// A status request that has been sent and is waiting for its answer. Nothing blocks: the answer is checked for with
+/// [StatusQuery::poll], which makes it cheap to query a whole server list at once.
+#[derive(Debug)]
+pub struct StatusQuery {
+	socket: UdpSocket,
+	token: u64,
+	sent: Instant,
+	timeout: Duration,
+}
+
+impl StatusQuery {
+	/// Sends a status request to the server at the address. The server has until the timeout runs out to answer.
+	pub fn send(address: SocketAddr, timeout: Duration) -> Result<Self, QueryError> {
+		let bind_address: SocketAddr = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }
+			.parse()
+			.expect("wildcard address should parse");
+		let socket = UdpSocket::bind(bind_address)?;
+		socket.connect(address)?;
+		socket.set_nonblocking(true)?;
+		
+		let token: u64 = rand::random();
+		socket.send(&encode_request(token)?)?;
+		
+		Ok(Self {
+			socket,
+			token,
+			sent: Instant::now(),
+			timeout,
+		})
+	}
+	
+	/// Checks for an answer without waiting for one. Returns the status and how long the server took to answer, or
+	/// [None] if there's no answer yet and there's still time left.
+	pub fn poll(&self) -> Option<Result<(ServerStatus, Duration), QueryError>> {
+		let mut buf = [0u8; MAX_RESPONSE_SIZE * 2];
+		loop {
+			let len = match self.socket.recv(&mut buf) {
+				Ok(len) => len,
+				Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+				Err(err) => return Some(Err(err.into())),
+			};
+			
+			// ignore anything that isn't the answer to this request
+			if let Ok(status) = decode_response(&buf[..len], self.token) {
+				return Some(Ok((status, self.sent.elapsed())))
+			}
+		}
+		
+		(self.sent.elapsed() >= self.timeout).then_some(Err(QueryError::TimedOut))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn status(worlds: usize) -> ServerStatus {
+		ServerStatus {
+			motd: "Hello".to_string(),
+			version_string: "test".to_string(),
+			protocol_ver: ProtocolVersion(1),
+			players: 1,
+			max_clients: 8,
+			worlds: (0..worlds)
+				.map(|i| WorldStatus { name: format!("world-{}", i), players: 0 })
+				.collect(),
+		}
+	}
+	
+	#[test]
+	fn requests_round_trip() {
+		let request = encode_request(42).unwrap();
+		assert_eq!(request.len(), REQUEST_SIZE);
+		assert_eq!(decode_request(&request), Some(42));
+		assert_eq!(decode_request(&request[..REQUEST_SIZE - 1]), None);
+		assert_eq!(decode_request(&[0; REQUEST_SIZE]), None);
+	}
+	
+	#[test]
+	fn responses_fit_in_a_datagram() {
+		let response = encode_response(7, status(1000)).unwrap();
+		assert!(response.len() <= MAX_RESPONSE_SIZE);
+		let decoded = decode_response(&response, 7).unwrap();
+		assert!(decoded.worlds.len() < 1000);
+		assert_eq!(decoded.worlds[..], status(1000).worlds[..decoded.worlds.len()]);
+		
+		assert!(matches!(decode_response(&response, 8), Err(QueryError::InvalidResponse)));
+	}
+	
+	#[test]
+	fn long_motds_are_cut_short() {
+		let status = ServerStatus {
+			motd: "é".repeat(1000),
+			..status(3)
+		};
+		let response = encode_response(7, status).unwrap();
+		assert!(response.len() <= MAX_RESPONSE_SIZE);
+		assert!(response.len() <= REQUEST_SIZE);
+		let decoded = decode_response(&response, 7).unwrap();
+		assert!(decoded.worlds.is_empty());
+		assert!(!decoded.motd.is_empty());
+		assert!("é".repeat(1000).starts_with(&decoded.motd));
+	}
+	
+	#[test]
+	fn queries_without_blocking() {
+		let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let query = StatusQuery::send(server.local_addr().unwrap(), Duration::from_secs(10)).unwrap();
+		// the server hasn't answered yet
+		assert!(query.poll().is_none());
+		
+		let mut buf = [0u8; REQUEST_SIZE * 2];
+		let (len, sender) = server.recv_from(&mut buf).unwrap();
+		let token = decode_request(&buf[..len]).unwrap();
+		// an answer to some other request first, which is ignored
+		server.send_to(&encode_response(token.wrapping_add(1), status(0)).unwrap(), sender).unwrap();
+		server.send_to(&encode_response(token, status(2)).unwrap(), sender).unwrap();
+		
+		let started = Instant::now();
+		let answer = loop {
+			if let Some(answer) = query.poll() {
+				break answer
+			}
+			assert!(started.elapsed() < Duration::from_secs(5), "the answer should arrive");
+		};
+		assert_eq!(answer.unwrap().0, status(2));
+	}
+	
+	#[test]
+	fn queries_time_out() {
+		let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let query = StatusQuery::send(server.local_addr().unwrap(), Duration::ZERO).unwrap();
+		assert!(matches!(query.poll(), Some(Err(QueryError::TimedOut))));
+	}
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use bevy::ecs::system::Resource;
+
+use crate::networking::protocol::{ClientId, Packet};
+use crate::utils::BevyHashMap;
+use crate::world::WorldId;
+
+/// How many messages of a type were received, and how big they were altogether.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MessageTraffic {
+	pub count: u64,
+	/// Bytes on the wire, i.e. compressed and including every fragment.
+	pub bytes: u64,
+}
+
+/// A player's network statistics
+#[derive(Debug, Default, Clone)]
+pub struct PlayerNetStat {
+	/// The player's latency in milliseconds
+	pub ping: u128,
+	/// The round-trip time of pings, smoothed over time. Zero until the first ping comes back.
+	pub smoothed_rtt: Duration,
+	/// How much the round-trip time of pings varies, smoothed over time.
+	pub jitter: Duration,
+	/// The round-trip time measured by renet, in milliseconds.
+	pub rtt: f64,
+	/// The fraction of packets renet had to resend, from 0 to 1.
+	pub packet_loss: f64,
+	pub bytes_sent_per_second: f64,
+	pub bytes_received_per_second: f64,
+	/// The world the player is in, if any.
+	pub world: Option<WorldId>,
+	/// The messages received from the player, by message type.
+	received: BTreeMap<&'static str, MessageTraffic>,
+	/// The bytes of fragments received since the last whole message.
+	pending_bytes: u64,
+}
+
+impl PlayerNetStat {
+	/// Records the round-trip time of a ping, smoothing it like TCP does (RFC 6298).
+	pub fn record_ping(&mut self, rtt: Duration) {
+		self.ping = rtt.as_millis();
+		if self.smoothed_rtt.is_zero() {
+			self.smoothed_rtt = rtt;
+			self.jitter = rtt / 2;
+		} else {
+			let deviation = if rtt > self.smoothed_rtt { rtt - self.smoothed_rtt } else { self.smoothed_rtt - rtt };
+			self.jitter = (self.jitter * 3 + deviation) / 4;
+			self.smoothed_rtt = (self.smoothed_rtt * 7 + rtt) / 8;
+		}
+	}
+	
+	/// Records a payload received from the player. `packet` is the message the payload completed, or [None] if it was
+	/// a fragment of a message that isn't complete yet.
+	pub fn record_received(&mut self, bytes: usize, packet: Option<&Packet>) {
+		self.pending_bytes += bytes as u64;
+		if let Some(packet) = packet {
+			let traffic = self.received.entry(packet.name()).or_default();
+			traffic.count += 1;
+			traffic.bytes += self.pending_bytes;
+			self.pending_bytes = 0;
+		}
+	}
+	
+	/// Returns the messages received from the player, by message type, in alphabetical order.
+	pub fn received(&self) -> impl Iterator<Item = (&'static str, MessageTraffic)> + '_ {
+		self.received.iter().map(|(name, traffic)| (*name, *traffic))
+	}
+	
+	/// Returns the messages received from the player, of every type together.
+	pub fn received_total(&self) -> MessageTraffic {
+		self.received.values().fold(MessageTraffic::default(), |total, traffic| MessageTraffic {
+			count: total.count + traffic.count,
+			bytes: total.bytes + traffic.bytes,
+		})
+	}
+}
+
+#[derive(Debug, Default, Clone, Resource)]
+pub struct PlayerNetStats(BevyHashMap<ClientId, PlayerNetStat>);
+
+impl PlayerNetStats {
+	/// Retrieves an optional network statistics struct that corresponds to the client ID.
+	pub fn get(&self, client_id: ClientId) -> Option<&PlayerNetStat> {
+		self.0.get(&client_id)
+	}
+	
+	/// Retrieves (or creates if non-existent) a mutable network statistics struct that corresponds to the client ID.
+	pub fn get_mut(&mut self, client_id: ClientId) -> &mut PlayerNetStat {
+		self.0
+			.entry(client_id)
+			.or_default()
+	}
+	
+	/// Removes the network statistics struct from the hash map. Returns Some with the removed network statistics struct if successful, None if non-existent.
+	pub fn remove(&mut self, client_id: ClientId) -> Option<PlayerNetStat> {
+		self.0.remove(&client_id)
+	}
+	
+	pub fn iter(&self) -> impl Iterator<Item = (&ClientId, &PlayerNetStat)> {
+		self.0.iter()
+	}
+}
@@ -0,0 +1,389 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy_renet::{RenetClientPlugin, RenetServerPlugin};
+use renet::{RenetClient, RenetServer};
+use renet::transport::{NETCODE_USER_DATA_BYTES, NetcodeClientTransport, NetcodeDisconnectReason, NetcodeServerTransport, NetcodeTransportError};
+
+use crate::utils::{BevyHashMap, nonfatal_error_systems};
+
+use super::conditioner::{ConditionedQueue, NetworkConditioner};
+
+pub const TRANSPORT_ERROR_MESSAGE: &'static str = "A transport error has occurred";
+
+type UserData = [u8; NETCODE_USER_DATA_BYTES];
+
+type PacketQueue = Mutex<VecDeque<Vec<u8>>>;
+
+/// Both directions of a single in-memory connection.
+#[derive(Debug, Default)]
+struct MemoryConnection {
+	to_server: PacketQueue,
+	to_client: PacketQueue,
+	/// Set by the server once it has accepted the connection.
+	accepted: AtomicBool,
+	/// Set by whichever side ends the connection.
+	closed: AtomicBool,
+}
+
+impl MemoryConnection {
+	fn close(&self) {
+		self.closed.store(true, Ordering::Release);
+	}
+	
+	fn is_closed(&self) -> bool {
+		self.closed.load(Ordering::Acquire)
+	}
+}
+
+/// A network that lives entirely in memory, which a server and any number of clients can talk over instead of UDP
+/// sockets.
+///
+/// Insert it as a resource into the server app and every client app before they start connecting, and they will use it
+/// instead of binding sockets. Packets are delivered whenever the receiving side next updates its transport, so apps
+/// that are stepped in lockstep see each other's packets a frame later. Nothing is ever lost or reordered.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct MemoryNetwork {
+	/// Connections the server hasn't seen yet.
+	pending: Arc<Mutex<Vec<(u64, UserData, Arc<MemoryConnection>)>>>,
+}
+
+impl MemoryNetwork {
+	/// Starts connecting a client to the server of this network. The server accepts the connection the next time it
+	/// updates.
+	pub fn connect(&self, client_id: u64, user_data: UserData) -> MemoryClientTransport {
+		let connection = Arc::new(MemoryConnection::default());
+		self.pending.lock().unwrap().push((client_id, user_data, connection.clone()));
+		MemoryClientTransport {
+			connection,
+			conditioned: None,
+			disconnect_reason: None,
+		}
+	}
+}
+
+/// The server side of a [MemoryNetwork].
+#[derive(Debug)]
+pub struct MemoryServerTransport {
+	network: MemoryNetwork,
+	max_clients: usize,
+	clients: BevyHashMap<u64, (UserData, Arc<MemoryConnection>)>,
+}
+
+impl MemoryServerTransport {
+	pub fn new(network: MemoryNetwork, max_clients: usize) -> Self {
+		Self {
+			network,
+			max_clients,
+			clients: default(),
+		}
+	}
+	
+	fn update(&mut self, server: &mut RenetServer) {
+		let pending = std::mem::take(&mut *self.network.pending.lock().unwrap());
+		for (client_id, user_data, connection) in pending {
+			if self.clients.len() >= self.max_clients || self.clients.contains_key(&client_id) {
+				connection.close();
+				continue
+			}
+			
+			connection.accepted.store(true, Ordering::Release);
+			server.add_connection(client_id);
+			self.clients.insert(client_id, (user_data, connection));
+		}
+		
+		self.clients.retain(|client_id, (_, connection)| {
+			if connection.is_closed() {
+				server.remove_connection(*client_id);
+			}
+			!connection.is_closed()
+		});
+		
+		for (client_id, (_, connection)) in self.clients.iter() {
+			for packet in connection.to_server.lock().unwrap().drain(..) {
+				// every client in the map has a connection in the server, so this can't fail
+				let _ = server.process_packet_from(&packet, *client_id);
+			}
+		}
+		
+		for client_id in server.disconnections_id() {
+			if let Some((_, connection)) = self.clients.remove(&client_id) {
+				connection.close();
+			}
+			server.remove_connection(client_id);
+		}
+	}
+	
+	fn send_packets(&mut self, server: &mut RenetServer) {
+		for (client_id, (_, connection)) in self.clients.iter() {
+			if let Ok(packets) = server.get_packets_to_send(*client_id) {
+				connection.to_client.lock().unwrap().extend(packets);
+			}
+		}
+	}
+	
+	fn user_data(&self, client_id: u64) -> Option<UserData> {
+		self.clients.get(&client_id).map(|(user_data, _)| *user_data)
+	}
+	
+	fn disconnect_all(&mut self, server: &mut RenetServer) {
+		for (client_id, (_, connection)) in self.clients.drain() {
+			connection.close();
+			server.remove_connection(client_id);
+		}
+	}
+}
+
+/// The client side of a [MemoryNetwork]. Created with [MemoryNetwork::connect].
+#[derive(Debug)]
+pub struct MemoryClientTransport {
+	connection: Arc<MemoryConnection>,
+	/// The packets to the server and to the client that are being held back, if the connection is conditioned.
+	conditioned: Option<(ConditionedQueue, ConditionedQueue)>,
+	disconnect_reason: Option<NetcodeDisconnectReason>,
+}
+
+impl MemoryClientTransport {
+	/// Makes packets in both directions go through the conditioner.
+	pub fn conditioned(mut self, conditioner: NetworkConditioner) -> Self {
+		self.conditioned = Some((ConditionedQueue::new(conditioner.clone()), ConditionedQueue::new(conditioner)));
+		self
+	}
+	
+	fn update(&mut self, client: &mut RenetClient) {
+		if self.disconnect_reason.is_some() {
+			return
+		}
+		
+		if client.is_disconnected() {
+			self.disconnect();
+			return
+		}
+		
+		if self.connection.is_closed() {
+			self.disconnect_reason = Some(NetcodeDisconnectReason::DisconnectedByServer);
+			client.disconnect_due_to_transport();
+			return
+		}
+		
+		if !self.is_connected() {
+			return
+		}
+		
+		client.set_connected();
+		let packets: Vec<Vec<u8>> = self.connection.to_client.lock().unwrap().drain(..).collect();
+		let packets = match self.conditioned.as_mut() {
+			Some((_, to_client)) => {
+				for packet in packets {
+					to_client.push(packet);
+				}
+				to_client.drain_arrived()
+			},
+			None => packets,
+		};
+		for packet in packets {
+			client.process_packet(&packet);
+		}
+	}
+	
+	fn send_packets(&mut self, client: &mut RenetClient) {
+		if !self.is_connected() {
+			return
+		}
+		
+		let packets = match self.conditioned.as_mut() {
+			Some((to_server, _)) => {
+				for packet in client.get_packets_to_send() {
+					to_server.push(packet);
+				}
+				to_server.drain_arrived()
+			},
+			None => client.get_packets_to_send(),
+		};
+		self.connection.to_server.lock().unwrap().extend(packets);
+	}
+	
+	fn is_connected(&self) -> bool {
+		self.disconnect_reason.is_none() && self.connection.accepted.load(Ordering::Acquire)
+	}
+	
+	fn disconnect(&mut self) {
+		if self.disconnect_reason.is_none() {
+			self.connection.close();
+			self.disconnect_reason = Some(NetcodeDisconnectReason::DisconnectedByClient);
+		}
+	}
+}
+
+/// What the server sends its packets over: UDP sockets through netcode, or a [MemoryNetwork].
+#[derive(Resource)]
+pub enum ServerTransport {
+	Netcode(NetcodeServerTransport),
+	Memory(MemoryServerTransport),
+}
+
+impl ServerTransport {
+	pub fn update(&mut self, duration: Duration, server: &mut RenetServer) -> Result<(), NetcodeTransportError> {
+		match self {
+			ServerTransport::Netcode(transport) => transport.update(duration, server),
+			ServerTransport::Memory(transport) => Ok(transport.update(server)),
+		}
+	}
+	
+	pub fn send_packets(&mut self, server: &mut RenetServer) {
+		match self {
+			ServerTransport::Netcode(transport) => transport.send_packets(server),
+			ServerTransport::Memory(transport) => transport.send_packets(server),
+		}
+	}
+	
+	/// Returns the user data the client connected with.
+	pub fn user_data(&self, client_id: u64) -> Option<UserData> {
+		match self {
+			ServerTransport::Netcode(transport) => transport.user_data(client_id),
+			ServerTransport::Memory(transport) => transport.user_data(client_id),
+		}
+	}
+	
+	pub fn disconnect_all(&mut self, server: &mut RenetServer) {
+		match self {
+			ServerTransport::Netcode(transport) => transport.disconnect_all(server),
+			ServerTransport::Memory(transport) => transport.disconnect_all(server),
+		}
+	}
+}
+
+/// What the client sends its packets over: a UDP socket through netcode, or a [MemoryNetwork].
+#[derive(Resource)]
+pub enum ClientTransport {
+	Netcode(NetcodeClientTransport),
+	Memory(MemoryClientTransport),
+}
+
+impl ClientTransport {
+	pub fn update(&mut self, duration: Duration, client: &mut RenetClient) -> Result<(), NetcodeTransportError> {
+		match self {
+			ClientTransport::Netcode(transport) => transport.update(duration, client),
+			ClientTransport::Memory(transport) => Ok(transport.update(client)),
+		}
+	}
+	
+	pub fn send_packets(&mut self, client: &mut RenetClient) -> Result<(), NetcodeTransportError> {
+		match self {
+			ClientTransport::Netcode(transport) => transport.send_packets(client),
+			ClientTransport::Memory(transport) => Ok(transport.send_packets(client)),
+		}
+	}
+	
+	pub fn is_connected(&self) -> bool {
+		match self {
+			ClientTransport::Netcode(transport) => transport.is_connected(),
+			ClientTransport::Memory(transport) => transport.is_connected(),
+		}
+	}
+	
+	pub fn disconnect_reason(&self) -> Option<NetcodeDisconnectReason> {
+		match self {
+			ClientTransport::Netcode(transport) => transport.disconnect_reason(),
+			ClientTransport::Memory(transport) => transport.disconnect_reason,
+		}
+	}
+	
+	pub fn disconnect(&mut self) {
+		match self {
+			ClientTransport::Netcode(transport) => transport.disconnect(),
+			ClientTransport::Memory(transport) => transport.disconnect(),
+		}
+	}
+}
+
+/// Drives the [ServerTransport], in place of bevy_renet's netcode plugin.
+pub struct ServerTransportPlugin;
+
+impl Plugin for ServerTransportPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(
+				PreUpdate,
+				nonfatal_error_systems!(TRANSPORT_ERROR_MESSAGE, NetcodeTransportError, update_server_transport)
+					.after(RenetServerPlugin::update_system)
+					.run_if(resource_exists::<ServerTransport>())
+					.run_if(resource_exists::<RenetServer>())
+			)
+			.add_systems(
+				PostUpdate,
+				send_server_packets
+					.run_if(resource_exists::<ServerTransport>())
+					.run_if(resource_exists::<RenetServer>())
+			);
+	}
+}
+
+/// Drives the [ClientTransport], in place of bevy_renet's netcode plugin.
+pub struct ClientTransportPlugin;
+
+impl Plugin for ClientTransportPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(
+				PreUpdate,
+				nonfatal_error_systems!(TRANSPORT_ERROR_MESSAGE, NetcodeTransportError, update_client_transport)
+					.after(RenetClientPlugin::update_system)
+					.run_if(resource_exists::<ClientTransport>())
+					.run_if(resource_exists::<RenetClient>())
+			)
+			.add_systems(
+				PostUpdate,
+				(
+					nonfatal_error_systems!(TRANSPORT_ERROR_MESSAGE, NetcodeTransportError, send_client_packets),
+					disconnect_client_on_exit,
+				)
+					.run_if(resource_exists::<ClientTransport>())
+					.run_if(resource_exists::<RenetClient>())
+			);
+	}
+}
+
+fn update_server_transport(
+	time: Res<Time>,
+	mut server: ResMut<RenetServer>,
+	mut transport: ResMut<ServerTransport>,
+) -> Result<(), NetcodeTransportError> {
+	transport.update(time.delta(), &mut server)
+}
+
+fn send_server_packets(
+	mut server: ResMut<RenetServer>,
+	mut transport: ResMut<ServerTransport>,
+) {
+	transport.send_packets(&mut server);
+}
+
+fn update_client_transport(
+	time: Res<Time>,
+	mut client: ResMut<RenetClient>,
+	mut transport: ResMut<ClientTransport>,
+) -> Result<(), NetcodeTransportError> {
+	transport.update(time.delta(), &mut client)
+}
+
+fn send_client_packets(
+	mut client: ResMut<RenetClient>,
+	mut transport: ResMut<ClientTransport>,
+) -> Result<(), NetcodeTransportError> {
+	transport.send_packets(&mut client)
+}
+
+/// Tells the server we're leaving, so that it doesn't have to wait for the connection to time out.
+fn disconnect_client_on_exit(
+	mut transport: ResMut<ClientTransport>,
+	ev_app_exit: EventReader<AppExit>,
+) {
+	if !ev_app_exit.is_empty() {
+		transport.disconnect();
+	}
+}
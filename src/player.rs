@@ -20,6 +20,23 @@ pub enum Source {
 	Unknown,
 }
 
+impl Source {
+	/// Renders the source like [Display](fmt::Display) does, but with a player's name instead of their ID. `player_name`
+	/// returns the name to show for a player, or [None] to fall back to their ID.
+	pub fn render(&self, player_name: impl Fn(ClientId) -> Option<String>) -> String {
+		match self {
+			Self::Player(player_id, world_id) => {
+				let name = player_name(*player_id).unwrap_or_else(|| format!("%p{{{}}}", player_id.0));
+				match world_id {
+					Some(world_id) => format!("{} [in %w{{{}}}]", name, world_id.0),
+					None => name,
+				}
+			},
+			_ => self.to_string(),
+		}
+	}
+}
+
 // mainly for chat message sources
 impl fmt::Display for Source {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -64,6 +64,16 @@ pub mod tile {
 	pub struct RawTileIds(pub RawIds);
 }
 
+pub mod item {
+	use bevy::prelude::*;
+	use serde::{Serialize, Deserialize};
+	
+	use super::RawIds;
+	
+	#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Deref, DerefMut, Resource)]
+	pub struct RawItemIds(pub RawIds);
+}
+
 #[derive(Default)]
 pub struct RawIdsLoader;
 
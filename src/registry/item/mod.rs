@@ -0,0 +1,50 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::asset::item::ItemDef;
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::raw_id::item::RawItemIds;
+use crate::Registry;
+
+use self::settings::DEFAULT_MAX_STACK;
+
+pub mod settings;
+
+#[derive(Deref, DerefMut, Resource)]
+pub struct ItemRegistry(Registry<ItemDef>);
+
+impl Default for ItemRegistry {
+	fn default() -> Self {
+		Self(Registry::new())
+	}
+}
+
+/// Looks up [ItemDef]s by their [RawId] or [Identifier].
+#[derive(SystemParam)]
+pub struct ItemDefs<'w> {
+	registry: Res<'w, ItemRegistry>,
+	assets: Res<'w, Assets<ItemDef>>,
+	raw_item_ids: Res<'w, RawItemIds>,
+}
+
+impl<'w> ItemDefs<'w> {
+	pub fn get(&self, id: &Identifier) -> Option<&ItemDef> {
+		self.assets.get(&self.registry.get(id)?)
+	}
+	
+	pub fn get_raw(&self, raw_id: RawId) -> Option<&ItemDef> {
+		self.get(self.raw_item_ids.get_id(raw_id)?)
+	}
+	
+	/// Returns the maximum stack size of the item, falling back to [DEFAULT_MAX_STACK] if the item is unknown.
+	pub fn max_stack(&self, raw_id: RawId) -> u16 {
+		self.get_raw(raw_id)
+			.map(|def| def.settings().max_stack())
+			.unwrap_or(DEFAULT_MAX_STACK)
+	}
+	
+	pub fn raw_item_ids(&self) -> &RawItemIds {
+		&self.raw_item_ids
+	}
+}
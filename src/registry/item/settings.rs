@@ -0,0 +1,29 @@
+use serde::{Serialize, Deserialize};
+
+/// The default maximum number of items in a single stack.
+pub const DEFAULT_MAX_STACK: u16 = 99;
+
+fn max_stack_default() -> u16 {
+	DEFAULT_MAX_STACK
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct ItemSettings {
+	/// The maximum number of items that fit in a single inventory slot.
+	#[serde(default = "max_stack_default")]
+	max_stack: u16,
+}
+
+impl Default for ItemSettings {
+	fn default() -> Self {
+		Self {
+			max_stack: DEFAULT_MAX_STACK,
+		}
+	}
+}
+
+impl ItemSettings {
+	pub fn max_stack(&self) -> u16 {
+		self.max_stack
+	}
+}
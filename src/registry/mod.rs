@@ -5,6 +5,8 @@ use bevy::{asset::{Handle, Asset}, reflect::{TypeUuid, TypePath}};
 use crate::identifier::Identifier;
 
 pub mod tile;
+pub mod item;
+pub mod recipe;
 pub mod def;
 
 /// Maps identifiers to handles of a specific type.
@@ -32,4 +34,9 @@ impl<T> Registry<T>
 	pub fn get(&self, id: &Identifier) -> Option<Handle<T>> {
 		Some(self.handles.get(id)?.clone())
 	}
+	
+	/// Iterates over every registered `Identifier` and its `Handle<T>`.
+	pub fn iter(&self) -> impl Iterator<Item = (&Identifier, &Handle<T>)> {
+		self.handles.iter()
+	}
 }
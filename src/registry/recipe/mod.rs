@@ -0,0 +1,38 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::asset::recipe::RecipeDef;
+use crate::identifier::Identifier;
+use crate::Registry;
+
+#[derive(Deref, DerefMut, Resource)]
+pub struct RecipeRegistry(Registry<RecipeDef>);
+
+impl Default for RecipeRegistry {
+	fn default() -> Self {
+		Self(Registry::new())
+	}
+}
+
+/// Looks up [RecipeDef]s by their [Identifier] or by the items in a crafting grid.
+#[derive(SystemParam)]
+pub struct RecipeDefs<'w> {
+	registry: Res<'w, RecipeRegistry>,
+	assets: Res<'w, Assets<RecipeDef>>,
+}
+
+impl<'w> RecipeDefs<'w> {
+	pub fn get(&self, id: &Identifier) -> Option<&RecipeDef> {
+		self.assets.get(&self.registry.get(id)?)
+	}
+	
+	/// Returns the recipe that matches the grid (given row by row, top to bottom). When several do, the one that takes
+	/// [precedence](RecipeDef::cmp_precedence) is returned, so that it doesn't depend on the order of the registry.
+	pub fn find(&self, width: usize, grid: &[Option<Identifier>]) -> Option<&RecipeDef> {
+		self.registry
+			.iter()
+			.filter_map(|(_, handle)| self.assets.get(handle))
+			.filter(|def| def.matches(width, grid))
+			.min_by(|a, b| a.cmp_precedence(b))
+	}
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+use crate::identifier::Identifier;
+
+fn stages_default() -> u8 {
+	1
+}
+
+fn chance_default() -> f32 {
+	0.25
+}
+
+fn radius_default() -> u8 {
+	1
+}
+
+fn true_default() -> bool {
+	true
+}
+
+/// How a tile grows and spreads when it is randomly ticked.
+///
+/// The current stage is stored in the tile's [TileData](crate::tile::TileData), starting at `0`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileGrowth {
+	/// The number of growth stages. The tile is mature once it reaches the last one.
+	#[serde(default = "stages_default")]
+	stages: u8,
+	/// The chance that a random tick advances the stage.
+	#[serde(default = "chance_default")]
+	chance: f32,
+	/// What the tile needs in order to grow or spread.
+	#[serde(default)]
+	conditions: GrowthConditions,
+	#[serde(default)]
+	spread: Option<TileSpread>,
+}
+
+impl TileGrowth {
+	pub fn stages(&self) -> u8 {
+		self.stages
+	}
+	
+	pub fn chance(&self) -> f32 {
+		self.chance
+	}
+	
+	pub fn conditions(&self) -> &GrowthConditions {
+		&self.conditions
+	}
+	
+	pub fn spread(&self) -> Option<&TileSpread> {
+		self.spread.as_ref()
+	}
+	
+	/// Returns the stage after `stage`, or [None] if `stage` is the last one.
+	pub fn next_stage(&self, stage: u8) -> Option<u8> {
+		let next = stage.checked_add(1)?;
+		if next < self.stages { Some(next) } else { None }
+	}
+	
+	pub fn is_mature(&self, stage: u8) -> bool {
+		stage.saturating_add(1) >= self.stages
+	}
+	
+	/// Gives identifiers without a namespace the namespace of the tile definition.
+	pub(crate) fn fill_namespaces(&mut self, namespace: &str) {
+		self.conditions.fill_namespaces(namespace);
+		if let Some(spread) = self.spread.as_mut() {
+			for id in spread.onto.iter_mut() {
+				fill_namespace(id, namespace);
+			}
+		}
+	}
+}
+
+/// The requirements for a tile to grow or spread. Every condition must hold.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GrowthConditions {
+	/// The minimum light level at the tile.
+	#[serde(default)]
+	min_light: u8,
+	/// The tiles that this tile may grow on top of. Any tile will do if this is empty.
+	#[serde(default)]
+	soil: Vec<Identifier>,
+	/// The liquids that count as water. If this isn't empty, one of them must be within `water_radius`.
+	#[serde(default)]
+	water: Vec<Identifier>,
+	#[serde(default)]
+	water_radius: u8,
+}
+
+impl GrowthConditions {
+	pub fn min_light(&self) -> u8 {
+		self.min_light
+	}
+	
+	pub fn soil(&self) -> &[Identifier] {
+		&self.soil
+	}
+	
+	pub fn water(&self) -> &[Identifier] {
+		&self.water
+	}
+	
+	pub fn water_radius(&self) -> u8 {
+		self.water_radius
+	}
+	
+	pub(crate) fn fill_namespaces(&mut self, namespace: &str) {
+		for id in self.soil.iter_mut().chain(self.water.iter_mut()) {
+			fill_namespace(id, namespace);
+		}
+	}
+}
+
+/// How a mature tile spreads onto nearby tiles, e.g. grass onto dirt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileSpread {
+	/// The tiles that may be replaced by this tile.
+	onto: Vec<Identifier>,
+	/// The chance that a random tick spreads the tile.
+	#[serde(default = "chance_default")]
+	chance: f32,
+	/// How far (in tiles, along each axis) the tile may spread.
+	#[serde(default = "radius_default")]
+	radius: u8,
+	/// Whether the tile has to be mature before it spreads.
+	#[serde(default = "true_default")]
+	requires_mature: bool,
+}
+
+impl TileSpread {
+	pub fn onto(&self) -> &[Identifier] {
+		&self.onto
+	}
+	
+	pub fn chance(&self) -> f32 {
+		self.chance
+	}
+	
+	pub fn radius(&self) -> u8 {
+		self.radius
+	}
+	
+	pub fn requires_mature(&self) -> bool {
+		self.requires_mature
+	}
+}
+
+fn fill_namespace(id: &mut Identifier, namespace: &str) {
+	if id.namespace() == "null" {
+		*id = Identifier::new(namespace.to_string(), id.path().to_string());
+	}
+}
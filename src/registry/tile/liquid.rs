@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// The level of a full liquid tile.
+pub const MAX_LIQUID_LEVEL: u8 = 8;
+
+fn viscosity_default() -> u32 {
+	1
+}
+
+/// Makes a tile a liquid, which flows down and sideways through empty space.
+///
+/// How full the tile is (from `1` to [MAX_LIQUID_LEVEL]) is stored in its [TileData](crate::tile::TileData). Liquids live in
+/// the foreground layer, and different liquids never mix.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileLiquid {
+	/// How many liquid ticks pass between each time the liquid flows. Higher is slower.
+	#[serde(default = "viscosity_default")]
+	viscosity: u32,
+}
+
+impl TileLiquid {
+	pub fn viscosity(&self) -> u32 {
+		self.viscosity.max(1)
+	}
+}
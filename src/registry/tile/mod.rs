@@ -1,8 +1,16 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use crate::asset::tile::TileDef;
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::raw_id::tile::RawTileIds;
 use crate::Registry;
 
+use self::settings::TileSettings;
+
 pub mod settings;
+pub mod growth;
+pub mod liquid;
 
 #[derive(Deref, DerefMut, Resource)]
 pub struct TileRegistry(Registry<TileDef>);
@@ -12,3 +20,29 @@ impl Default for TileRegistry {
 		Self(Registry::new())
 	}
 }
+
+/// Looks up [TileDef]s by their [RawId] or [Identifier].
+#[derive(SystemParam)]
+pub struct TileDefs<'w> {
+	registry: Res<'w, TileRegistry>,
+	assets: Res<'w, Assets<TileDef>>,
+	raw_tile_ids: Res<'w, RawTileIds>,
+}
+
+impl<'w> TileDefs<'w> {
+	pub fn get(&self, id: &Identifier) -> Option<&TileDef> {
+		self.assets.get(&self.registry.get(id)?)
+	}
+	
+	pub fn get_raw(&self, raw_id: RawId) -> Option<&TileDef> {
+		self.get(self.raw_tile_ids.get_id(raw_id)?)
+	}
+	
+	pub fn settings(&self, raw_id: RawId) -> Option<TileSettings> {
+		self.get_raw(raw_id).map(|def| *def.settings())
+	}
+	
+	pub fn raw_tile_ids(&self) -> &RawTileIds {
+		&self.raw_tile_ids
+	}
+}
@@ -1,11 +1,21 @@
 use serde::{Serialize, Deserialize, de::Visitor};
 
+use crate::tile::layer::TileLayer;
+use crate::tile::light::MAX_LIGHT;
+
 #[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TileSettings {
 	#[serde(default)]
 	hardness: TileHardness,
 	#[serde(default)]
 	salience: TileSalience,
+	/// The block light level the tile gives off.
+	#[serde(default)]
+	light_emission: u8,
+	/// How many extra light levels are lost when light passes through the tile. Defaults to fully opaque for foreground
+	/// tiles and fully transparent for everything else.
+	#[serde(default)]
+	opacity: Option<u8>,
 }
 
 impl TileSettings {
@@ -16,6 +26,18 @@ impl TileSettings {
 	pub fn salience(&self) -> TileSalience {
 		self.salience
 	}
+	
+	pub fn light_emission(&self) -> u8 {
+		self.light_emission.min(MAX_LIGHT)
+	}
+	
+	pub fn opacity(&self) -> u8 {
+		match self.opacity {
+			Some(opacity) => opacity.min(MAX_LIGHT),
+			None if self.salience == TileSalience::Foreground => MAX_LIGHT,
+			None => 0,
+		}
+	}
 }
 
 /// The hardness of a tile.
@@ -81,4 +103,23 @@ impl TileSalience {
 	pub const fn into_z(&self) -> f32 {
 		(*self as i32) as f32
 	}
+	
+	/// Returns the layer that tiles with this salience are stored in. Invisible tiles live in the foreground.
+	#[inline]
+	pub const fn layer(&self) -> TileLayer {
+		match self {
+			TileSalience::Background => TileLayer::Background,
+			TileSalience::Invisible | TileSalience::Foreground => TileLayer::Foreground,
+			TileSalience::Overlay => TileLayer::Overlay,
+		}
+	}
+	
+	#[inline]
+	pub const fn from_layer(layer: TileLayer) -> Self {
+		match layer {
+			TileLayer::Background => TileSalience::Background,
+			TileLayer::Foreground => TileSalience::Foreground,
+			TileLayer::Overlay => TileSalience::Overlay,
+		}
+	}
 }
@@ -8,6 +8,10 @@ pub enum SaveError {
     Io(#[from] std::io::Error),
 	#[error("bincode error: {0}")]
 	BincodeError(#[from] bincode::Error),
+	#[error("RON serialization error: {0}")]
+	RonError(#[from] ron::Error),
+	#[error("RON deserialization error: {0}")]
+	RonDeserializationError(#[from] ron::error::SpannedError),
     #[error("magic bytes 0x{0:X} are missing!")]
     MagicMissing(u32),
 	#[error("invalid save version: 0x{0:X}; expected version 0x{1:X}")]
@@ -18,4 +22,6 @@ pub enum SaveError {
 	RawIdNotFoundInSave(String, RawId),
 	#[error("the {0} ID {1} does not exist")]
 	IdNonexistent(String, Identifier),
+	#[error("invalid tile layer {0}")]
+	InvalidTileLayer(u8),
 }
@@ -4,24 +4,34 @@ use bincode::Options;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::inventory::SavedInventory;
 use crate::networking::Username;
 use crate::raw_id::RawId;
 use crate::raw_id::tile::RawTileIds;
 use crate::tile::TileData;
 use crate::tile::WorldTile;
+use crate::tile::entity::{TileEntities, TileEntity};
+use crate::tile::layer::{TileLayer, TileLayers};
+use crate::world::ChunkPos;
 use crate::TilePos;
 use crate::utils;
-use crate::world::WorldBan;
+use crate::world::{SavedPlayerRecord, WorldBan, WorldTime};
 
 use super::error::SaveError;
 
 pub const MAGIC: [u8; 4] = [0xB0, 0x7A, 0x21, 0xCA];
-pub const SAVE_VERSION: u32 = 0x0;
+pub const SAVE_VERSION: u32 = 0x5;
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct WorldSave {
-	pub tiles: BevyHashMap<TilePos, WorldTile>,
+	pub tiles: TileLayers,
+	/// Stored separately from the tiles so that the tile records keep a fixed size.
+	pub tile_entities: TileEntities,
 	pub bans: BevyHashMap<Username, WorldBan>,
+	/// What this world remembers about each player who has been in it.
+	pub players: BevyHashMap<Username, SavedPlayerRecord>,
+	/// The time in the world when it was saved.
+	pub time: WorldTime,
 }
 
 #[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
@@ -55,41 +65,76 @@ impl Into<usize> for FileOffset {
     }
 }
 
+/// The offset table used by save version `0x0`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OffsetTableV0 {
+	raw_tile_ids_offset: FileOffset,
+	tiles_offset: FileOffset,
+	bans_offset: FileOffset,
+}
+
+/// The offset table used by save version `0x1`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OffsetTableV1 {
+	raw_tile_ids_offset: FileOffset,
+	tiles_offset: FileOffset,
+	bans_offset: FileOffset,
+	inventories_offset: FileOffset,
+}
+
+/// The offset table used by save versions `0x2` to `0x4`, which didn't save the time. Save versions `0x2` and `0x3` had
+/// player inventories where the player records are now.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OffsetTableV4 {
+	raw_tile_ids_offset: FileOffset,
+	tiles_offset: FileOffset,
+	tile_entities_offset: FileOffset,
+	bans_offset: FileOffset,
+	players_offset: FileOffset,
+}
+
+/// The offset table.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct OffsetTable {
 	raw_tile_ids_offset: FileOffset,
 	tiles_offset: FileOffset,
+	tile_entities_offset: FileOffset,
 	bans_offset: FileOffset,
+	players_offset: FileOffset,
+	time_offset: FileOffset,
 }
 
+/// The size of a single tile record in bytes (x, y, raw ID, data and layer).
+const TILE_RECORD_SIZE: usize = 14;
+/// The size of a single tile record in save versions `0x0` to `0x2`, which had no layers.
+const TILE_RECORD_SIZE_V2: usize = 13;
+
+/// The tile entities of save version `0x2`, which had no layers.
+type TileEntitiesV2 = BevyHashMap<ChunkPos, BevyHashMap<TilePos, TileEntity>>;
+
 impl WorldSave {
 	pub fn serialize(&self, raw_tile_ids: &RawTileIds) -> Result<Vec<u8>, SaveError> {
 		let saved_raw_tile_ids: Vec<u8> = utils::serialize(raw_tile_ids)?;
-		
-		let tiles: Vec<u8> = self.tiles
-			.iter()
-			.map(
-				|(pos, tile)| {
-					let mut vec: Vec<u8> = vec![];
-					vec.extend_from_slice(&pos.x.to_le_bytes());
-					vec.extend_from_slice(&pos.y.to_le_bytes());
-					vec.extend_from_slice(&tile.0.0.to_le_bytes());
-					vec.push(tile.1.0);
-					vec
-				}
-			)
-			.flatten()
-			.collect();
-		
+		let tiles = serialize_tiles(&self.tiles);
+		let tile_entities: Vec<u8> = utils::serialize(&self.tile_entities)?;
 		let bans: Vec<u8> = utils::serialize(&self.bans)?;
+		let players: Vec<u8> = utils::serialize(&self.players)?;
+		let time: Vec<u8> = utils::serialize(&self.time)?;
 		
-		let raw_tile_ids_offset: FileOffset = MAGIC.len().into();
+		let header_size = MAGIC.len() + std::mem::size_of::<u32>() + utils::OPTIONS_LE.serialized_size::<OffsetTable>(&default())? as usize;
+		let raw_tile_ids_offset: FileOffset = header_size.into();
 		let tiles_offset: FileOffset = raw_tile_ids_offset + saved_raw_tile_ids.len();
-		let bans_offset = tiles_offset + tiles.len();
+		let tile_entities_offset = tiles_offset + tiles.len();
+		let bans_offset = tile_entities_offset + tile_entities.len();
+		let players_offset = bans_offset + bans.len();
+		let time_offset = players_offset + players.len();
 		let offset_table = OffsetTable {
 			raw_tile_ids_offset,
 			tiles_offset,
+			tile_entities_offset,
 			bans_offset,
+			players_offset,
+			time_offset,
 		};
 		
 		let mut vec: Vec<u8> = vec![];
@@ -98,7 +143,10 @@ impl WorldSave {
 		vec.extend(utils::serialize(&offset_table)?);
 		vec.extend(saved_raw_tile_ids);
 		vec.extend(tiles);
+		vec.extend(tile_entities);
 		vec.extend(bans);
+		vec.extend(players);
+		vec.extend(time);
 		Ok(vec)
 	}
 	
@@ -120,45 +168,93 @@ impl WorldSave {
 		
 		match version {
 			0 => {
-				let offset_table_size = utils::OPTIONS_LE.serialized_size::<OffsetTable>(&default())? as usize;
-				let offset_table: OffsetTable = utils::deserialize(&vec[8..offset_table_size])?;
+				let offset_table_size = utils::OPTIONS_LE.serialized_size::<OffsetTableV0>(&default())? as usize;
+				let offset_table: OffsetTableV0 = utils::deserialize(&vec[8..8 + offset_table_size])?;
 				
 				let saved_raw_tile_ids: RawTileIds = utils::deserialize(&vec[offset_table.raw_tile_ids_offset.into()..offset_table.tiles_offset.into()])?;
+				let tiles = deserialize_tiles(&vec[offset_table.tiles_offset.into()..offset_table.bans_offset.into()], TILE_RECORD_SIZE_V2, &saved_raw_tile_ids, raw_tile_ids)?;
+				let bans: BevyHashMap<Username, WorldBan> = utils::deserialize(&vec[offset_table.bans_offset.into()..])?;
 				
-				let tiles = vec[offset_table.tiles_offset.into()..offset_table.bans_offset.into()]
-					.to_vec()
-					.into_iter()
-					.collect::<Vec<u8>>()
-					.chunks_exact(13)
-					.map(
-						|chunk| {
-							let mut x: [u8; 4] = Default::default();
-							x.copy_from_slice(&chunk[0..4]);
-							let mut y: [u8; 4] = Default::default();
-							y.copy_from_slice(&chunk[4..8]);
-							let mut raw_id: [u8; 4] = Default::default();
-							raw_id.copy_from_slice(&chunk[8..12]);
-							let raw_id = RawId(i32::from_le_bytes(raw_id));
-							let id = match saved_raw_tile_ids.get_id(raw_id) {
-								Some(id) => id,
-								None => return Err(SaveError::RawIdNotFoundInSave("tile".to_string(), raw_id)),
-							};
-							let raw_id = match raw_tile_ids.get_raw_id(id) {
-								Some(raw_id) => raw_id,
-								None => return Err(SaveError::IdNonexistent("tile".to_string(), id.clone())),
-							};
-							
-							Ok((TilePos { x: i32::from_le_bytes(x), y: i32::from_le_bytes(y) }, WorldTile(raw_id, TileData(chunk[13]))))
-						}
-					)
-					.collect::<Result<BevyHashMap<_, _>, _>>()?;
+				Ok(
+					Self {
+						tiles,
+						bans,
+						..default()
+					}
+				)
+			},
+			1 => {
+				let offset_table_size = utils::OPTIONS_LE.serialized_size::<OffsetTableV1>(&default())? as usize;
+				let offset_table: OffsetTableV1 = utils::deserialize(&vec[8..8 + offset_table_size])?;
 				
-				let bans: BevyHashMap<Username, WorldBan> = utils::deserialize(&vec[offset_table.bans_offset.into()..])?;
+				let saved_raw_tile_ids: RawTileIds = utils::deserialize(&vec[offset_table.raw_tile_ids_offset.into()..offset_table.tiles_offset.into()])?;
+				let tiles = deserialize_tiles(&vec[offset_table.tiles_offset.into()..offset_table.bans_offset.into()], TILE_RECORD_SIZE_V2, &saved_raw_tile_ids, raw_tile_ids)?;
+				let bans: BevyHashMap<Username, WorldBan> = utils::deserialize(&vec[offset_table.bans_offset.into()..offset_table.inventories_offset.into()])?;
+				let inventories: BevyHashMap<Username, SavedInventory> = utils::deserialize(&vec[offset_table.inventories_offset.into()..])?;
+				
+				Ok(
+					Self {
+						tiles,
+						bans,
+						players: records_from_inventories(inventories),
+						..default()
+					}
+				)
+			},
+			2..=5 => {
+				let offset_table = if version < 5 {
+					let offset_table_size = utils::OPTIONS_LE.serialized_size::<OffsetTableV4>(&default())? as usize;
+					let offset_table: OffsetTableV4 = utils::deserialize(&vec[8..8 + offset_table_size])?;
+					// the player records went on to the end
+					OffsetTable {
+						raw_tile_ids_offset: offset_table.raw_tile_ids_offset,
+						tiles_offset: offset_table.tiles_offset,
+						tile_entities_offset: offset_table.tile_entities_offset,
+						bans_offset: offset_table.bans_offset,
+						players_offset: offset_table.players_offset,
+						time_offset: vec.len().into(),
+					}
+				} else {
+					let offset_table_size = utils::OPTIONS_LE.serialized_size::<OffsetTable>(&default())? as usize;
+					utils::deserialize(&vec[8..8 + offset_table_size])?
+				};
+				
+				let (record_size, tile_entities) = if version == 2 {
+					// everything was in the foreground before layers existed
+					let old_tile_entities: TileEntitiesV2 = utils::deserialize(&vec[offset_table.tile_entities_offset.into()..offset_table.bans_offset.into()])?;
+					let mut tile_entities = TileEntities::default();
+					for (pos, tile_entity) in old_tile_entities.into_values().flatten() {
+						tile_entities.insert(TileLayer::Foreground, pos, tile_entity);
+					}
+					(TILE_RECORD_SIZE_V2, tile_entities)
+				} else {
+					(TILE_RECORD_SIZE, utils::deserialize(&vec[offset_table.tile_entities_offset.into()..offset_table.bans_offset.into()])?)
+				};
+				
+				let saved_raw_tile_ids: RawTileIds = utils::deserialize(&vec[offset_table.raw_tile_ids_offset.into()..offset_table.tiles_offset.into()])?;
+				let tiles = deserialize_tiles(&vec[offset_table.tiles_offset.into()..offset_table.tile_entities_offset.into()], record_size, &saved_raw_tile_ids, raw_tile_ids)?;
+				let bans: BevyHashMap<Username, WorldBan> = utils::deserialize(&vec[offset_table.bans_offset.into()..offset_table.players_offset.into()])?;
+				let players_bytes = &vec[offset_table.players_offset.into()..offset_table.time_offset.into()];
+				let players = if version < 4 {
+					let inventories: BevyHashMap<Username, SavedInventory> = utils::deserialize(players_bytes)?;
+					records_from_inventories(inventories)
+				} else {
+					utils::deserialize(players_bytes)?
+				};
+				// older worlds start in the morning, like new ones
+				let time = if version < 5 {
+					default()
+				} else {
+					utils::deserialize(&vec[offset_table.time_offset.into()..])?
+				};
 				
 				Ok(
 					Self {
 						tiles,
+						tile_entities,
 						bans,
+						players,
+						time,
 					}
 				)
 			},
@@ -168,3 +264,194 @@ impl WorldSave {
 		}
 	}
 }
+
+/// Turns the inventories of save versions `0x1` to `0x3` into player records. Nothing else about the players was saved,
+/// so they start at the spawnpoint and their first join time is unknown.
+fn records_from_inventories(inventories: BevyHashMap<Username, SavedInventory>) -> BevyHashMap<Username, SavedPlayerRecord> {
+	inventories
+		.into_iter()
+		.map(|(username, inventory)| (username, SavedPlayerRecord {
+			inventory,
+			..default()
+		}))
+		.collect()
+}
+
+/// Writes a record for each tile.
+fn serialize_tiles(tiles: &TileLayers) -> Vec<u8> {
+	tiles
+		.iter()
+		.map(
+			|(layer, pos, tile)| {
+				let mut vec: Vec<u8> = vec![];
+				vec.extend_from_slice(&pos.x.to_le_bytes());
+				vec.extend_from_slice(&pos.y.to_le_bytes());
+				vec.extend_from_slice(&tile.0.0.to_le_bytes());
+				vec.push(tile.1.0);
+				vec.push(layer as u8);
+				vec
+			}
+		)
+		.flatten()
+		.collect()
+}
+
+/// Reads the tile records, translating the raw IDs in the save into the current raw IDs.
+/// 
+/// Records without a layer byte (i.e., from before layers existed) are put in the foreground.
+fn deserialize_tiles(bytes: &[u8], record_size: usize, saved_raw_tile_ids: &RawTileIds, raw_tile_ids: &RawTileIds) -> Result<TileLayers, SaveError> {
+	let mut tiles = TileLayers::default();
+	for chunk in bytes.chunks_exact(record_size) {
+		let mut x: [u8; 4] = Default::default();
+		x.copy_from_slice(&chunk[0..4]);
+		let mut y: [u8; 4] = Default::default();
+		y.copy_from_slice(&chunk[4..8]);
+		let mut raw_id: [u8; 4] = Default::default();
+		raw_id.copy_from_slice(&chunk[8..12]);
+		let raw_id = RawId(i32::from_le_bytes(raw_id));
+		let id = match saved_raw_tile_ids.get_id(raw_id) {
+			Some(id) => id,
+			None => return Err(SaveError::RawIdNotFoundInSave("tile".to_string(), raw_id)),
+		};
+		let raw_id = match raw_tile_ids.get_raw_id(id) {
+			Some(raw_id) => raw_id,
+			None => return Err(SaveError::IdNonexistent("tile".to_string(), id.clone())),
+		};
+		let layer = match chunk.get(13) {
+			Some(&layer) => TileLayer::try_from(layer).map_err(SaveError::InvalidTileLayer)?,
+			None => TileLayer::Foreground,
+		};
+		
+		tiles.insert(layer, TilePos { x: i32::from_le_bytes(x), y: i32::from_le_bytes(y) }, WorldTile(raw_id, TileData(chunk[12])));
+	}
+	Ok(tiles)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, SystemTime};
+	
+	use crate::identifier::Identifier;
+	use crate::Position;
+	use crate::tile::entity::TileValue;
+	use crate::world::PlayerStats;
+	
+	use super::*;
+	
+	fn raw_tile_ids() -> RawTileIds {
+		let mut raw_tile_ids = RawTileIds::default();
+		raw_tile_ids.register(Identifier::from_str("test", "dirt"), RawId(0));
+		raw_tile_ids.register(Identifier::from_str("test", "stone"), RawId(1));
+		raw_tile_ids
+	}
+	
+	fn inventory() -> SavedInventory {
+		SavedInventory(vec![Some((Identifier::from_str("test", "dirt"), 12)), None, Some((Identifier::from_str("test", "stone"), 1))])
+	}
+	
+	fn save() -> WorldSave {
+		let mut tiles = TileLayers::default();
+		tiles.insert(TileLayer::Foreground, TilePos { x: -3, y: 7 }, WorldTile(RawId(0), TileData(2)));
+		tiles.insert(TileLayer::Background, TilePos { x: 4, y: -1 }, WorldTile(RawId(1), TileData(0)));
+		
+		let mut tile_entity = TileEntity::default();
+		tile_entity.set("items", TileValue::Items(vec![Some((Identifier::from_str("test", "dirt"), 3)), None]));
+		tile_entity.set("locked", TileValue::Bool(true));
+		tile_entity.set("label", TileValue::String("loot".to_string()));
+		let mut tile_entities = TileEntities::default();
+		tile_entities.insert(TileLayer::Foreground, TilePos { x: -3, y: 7 }, tile_entity.clone());
+		tile_entities.insert(TileLayer::Background, TilePos { x: 4, y: -1 }, tile_entity);
+		
+		let mut players = BevyHashMap::default();
+		players.insert(Username("steve".to_string()), SavedPlayerRecord {
+			position: Some(Position { x: 12.5, y: -4.0 }),
+			inventory: inventory(),
+			stats: PlayerStats {
+				play_time: Duration::from_secs(3600),
+				tiles_broken: 40,
+				tiles_placed: 2,
+			},
+			first_joined: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+		});
+		
+		WorldSave {
+			tiles,
+			tile_entities,
+			players,
+			time: WorldTime(12345.5),
+			..default()
+		}
+	}
+	
+	#[test]
+	fn saves_round_trip() {
+		let raw_tile_ids = raw_tile_ids();
+		let save = save();
+		let loaded = WorldSave::deserialize(save.serialize(&raw_tile_ids).unwrap(), &raw_tile_ids).unwrap();
+		assert_eq!(loaded.tiles, save.tiles);
+		assert_eq!(loaded.tile_entities, save.tile_entities);
+		assert_eq!(loaded.players, save.players);
+		assert_eq!(loaded.time, save.time);
+		assert!(loaded.bans.is_empty());
+	}
+	
+	#[test]
+	fn inventories_become_player_records() {
+		let raw_tile_ids = raw_tile_ids();
+		let save = WorldSave {
+			players: default(),
+			..save()
+		};
+		
+		let saved_raw_tile_ids = utils::serialize(&raw_tile_ids).unwrap();
+		let tiles = serialize_tiles(&save.tiles);
+		let tile_entities = utils::serialize(&save.tile_entities).unwrap();
+		let bans = utils::serialize(&save.bans).unwrap();
+		let mut inventories = BevyHashMap::default();
+		inventories.insert(Username("steve".to_string()), inventory());
+		let inventories = utils::serialize(&inventories).unwrap();
+		
+		let header_size = 8 + utils::OPTIONS_LE.serialized_size::<OffsetTableV4>(&default()).unwrap() as usize;
+		let raw_tile_ids_offset: FileOffset = header_size.into();
+		let tiles_offset = raw_tile_ids_offset + saved_raw_tile_ids.len();
+		let tile_entities_offset = tiles_offset + tiles.len();
+		let bans_offset = tile_entities_offset + tile_entities.len();
+		let players_offset = bans_offset + bans.len();
+		let offset_table = OffsetTableV4 {
+			raw_tile_ids_offset,
+			tiles_offset,
+			tile_entities_offset,
+			bans_offset,
+			players_offset,
+		};
+		
+		let mut bytes = vec![];
+		bytes.extend_from_slice(&MAGIC);
+		bytes.extend_from_slice(&3u32.to_le_bytes());
+		bytes.extend(utils::serialize(&offset_table).unwrap());
+		bytes.extend(saved_raw_tile_ids);
+		bytes.extend(tiles);
+		bytes.extend(tile_entities);
+		bytes.extend(bans);
+		bytes.extend(inventories);
+		
+		let loaded = WorldSave::deserialize(bytes, &raw_tile_ids).unwrap();
+		assert_eq!(loaded.tiles, save.tiles);
+		assert_eq!(loaded.tile_entities, save.tile_entities);
+		assert_eq!(loaded.players.len(), 1);
+		assert_eq!(loaded.players[&Username("steve".to_string())], SavedPlayerRecord {
+			inventory: inventory(),
+			..default()
+		});
+		assert_eq!(loaded.time, WorldTime::default());
+	}
+	
+	#[test]
+	fn rejects_unknown_versions() {
+		let raw_tile_ids = raw_tile_ids();
+		let mut bytes = save().serialize(&raw_tile_ids).unwrap();
+		bytes[4..8].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+		assert!(matches!(WorldSave::deserialize(bytes, &raw_tile_ids), Err(SaveError::InvalidVersion(..))));
+		assert!(matches!(WorldSave::deserialize(vec![0; 8], &raw_tile_ids), Err(SaveError::MagicMissing(_))));
+	}
+}
@@ -1,21 +1,52 @@
-use crate::utils::BevyHashMap;
+use std::path::{Path, PathBuf};
 
 use bevy::utils::default;
 
-use crate::{save::format::WorldSave, tile::WorldTile, TilePos, raw_id::tile::RawTileIds};
+use crate::{save::format::WorldSave, tile::{WorldTile, layer::{TileLayer, TileLayers}}, TilePos, raw_id::tile::RawTileIds};
 
 use self::error::SaveError;
 
 pub mod format;
 pub mod error;
 
-pub const SAVE_DIR: &'static str = "/saves/worlds";
+/// The save directory of dedicated servers, relative to the working directory.
+pub const SAVE_DIR: &'static str = "saves/worlds";
+/// The save directory of singleplayer worlds, relative to the working directory.
+pub const SINGLEPLAYER_SAVE_DIR: &'static str = "saves/singleplayer";
 
+/// Returns the path of the world's save file in the save directory.
+/// 
 /// ## Warning
 /// You **must** ensure that the name is sanitized!
-pub fn open_world(name: &str, raw_tile_ids: &RawTileIds) -> Result<WorldSave, SaveError> {
+pub fn world_path(dir: &Path, name: &str) -> PathBuf {
 	let mut path = std::env::current_dir().unwrap();
-	path.push(format!("{}/{}.dat", SAVE_DIR, name));
+	path.push(dir);
+	path.push(format!("{}.dat", name));
+	path
+}
+
+/// Returns the names of every world in the save directory, in no particular order. Returns nothing if the save directory
+/// can't be read.
+pub fn world_names(dir: &Path) -> Vec<String> {
+	let mut path = std::env::current_dir().unwrap();
+	path.push(dir);
+	
+	let Ok(entries) = std::fs::read_dir(path) else {
+		return vec![]
+	};
+	
+	entries
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.path())
+		.filter(|path| path.extension().is_some_and(|extension| extension == "dat"))
+		.filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+		.collect()
+}
+
+/// ## Warning
+/// You **must** ensure that the name is sanitized!
+pub fn open_world(dir: &Path, name: &str, raw_tile_ids: &RawTileIds) -> Result<WorldSave, SaveError> {
+	let path = world_path(dir, name);
 	
 	if !path.exists() {
 		return Err(SaveError::WorldNonexistent)
@@ -26,15 +57,15 @@ pub fn open_world(name: &str, raw_tile_ids: &RawTileIds) -> Result<WorldSave, Sa
 
 /// ## Warning
 /// You **must** ensure that the name is sanitized!
-pub fn open_or_gen_world(name: &str, raw_tile_ids: &RawTileIds) -> Result<WorldSave, SaveError> {
-	let world = open_world(name, raw_tile_ids);
+pub fn open_or_gen_world(dir: &Path, name: &str, raw_tile_ids: &RawTileIds) -> Result<WorldSave, SaveError> {
+	let world = open_world(dir, name, raw_tile_ids);
 	if let Err(err) = world {
 		match err {
 			SaveError::WorldNonexistent => {
-				let mut tiles = BevyHashMap::default();
+				let mut tiles = TileLayers::default();
 				let grass = WorldTile::new(&crate::id("grass"), raw_tile_ids).unwrap();
 				for x in -16..=16 {
-					tiles.insert(TilePos { x, y: 0 }, grass.clone());
+					tiles.insert(TileLayer::Foreground, TilePos { x, y: 0 }, grass.clone());
 				}
 				
 				Ok(WorldSave {
@@ -47,14 +78,15 @@ pub fn open_or_gen_world(name: &str, raw_tile_ids: &RawTileIds) -> Result<WorldS
 	} else { world }
 }
 
+/// Writes the world to disk, creating the save file if it doesn't exist yet.
+/// 
 /// ## Warning
 /// You **must** ensure that the name is sanitized!
-pub fn save_world(name: &str, save: &WorldSave, raw_tile_ids: &RawTileIds) -> Result<(), SaveError> {
-	let mut path = std::env::current_dir().unwrap();
-	path.push(format!("{}/{}.dat", SAVE_DIR, name));
+pub fn save_world(dir: &Path, name: &str, save: &WorldSave, raw_tile_ids: &RawTileIds) -> Result<(), SaveError> {
+	let path = world_path(dir, name);
 	
-	if !path.exists() {
-		return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("save not found at {}", path.as_os_str().to_string_lossy().to_string())).into())
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
 	}
 	
 	std::fs::write(path, save.serialize(raw_tile_ids)?)?;
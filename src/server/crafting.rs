@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+
+use crate::crafting::{CRAFTING_GRID_SIZE, CraftDenyReason};
+use crate::creature::player::Player;
+use crate::identifier::Identifier;
+use crate::inventory::{Inventory, ItemStack};
+use crate::networking::error::NetworkError;
+use crate::networking::protocol::{ClientId, ClientMessage, Packet, ServerMessage, ServerResponse};
+use crate::raw_id::tile::RawTileIds;
+use crate::registry::item::ItemDefs;
+use crate::registry::recipe::RecipeDefs;
+use crate::tile::layer::TileLayer;
+use crate::world::{ServerGameWorld, ServerGameWorlds, WorldId};
+use crate::{Position, TilePos};
+
+use super::networking::{send_message, NetServer, Players};
+use super::tile::TILE_REACH;
+
+/// Handles [ClientMessage::CraftRequest]s.
+///
+/// The grid is matched against every recipe, and the ingredients are taken from the player's inventory only if the
+/// player has all of them, is near a required station (if any), and has room for the output.
+pub fn craft(
+	message_query: Query<(&ClientId, &Packet)>,
+	mut server: NetServer,
+	worlds: Res<ServerGameWorlds>,
+	players: Res<Players>,
+	mut player_query: Query<(&WorldId, Option<&Position>, &mut Inventory), With<Player>>,
+	raw_tile_ids: Res<RawTileIds>,
+	item_defs: ItemDefs,
+	recipe_defs: RecipeDefs,
+) -> Result<(), NetworkError> {
+	for (client_id, packet) in message_query.iter() {
+		let Packet::ClientMessage(ClientMessage::CraftRequest(grid)) = packet else {
+			continue
+		};
+		
+		let Some(&player_entity) = players.get(client_id) else {
+			continue
+		};
+		
+		let Ok((world_id, position, mut inventory)) = player_query.get_mut(player_entity) else {
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::NotInWorld));
+			continue
+		};
+		
+		let Some(world) = worlds.get_world(world_id.as_str()) else {
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::NotInWorld));
+			continue
+		};
+		
+		let recipe = grid
+			.is_valid()
+			.then(|| grid.to_identifiers(item_defs.raw_item_ids()))
+			.flatten()
+			.and_then(|grid| recipe_defs.find(CRAFTING_GRID_SIZE, &grid));
+		let Some(recipe) = recipe else {
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::NoMatchingRecipe));
+			continue
+		};
+		
+		if !recipe.stations().is_empty() && !near_station(world, position, recipe.stations(), &raw_tile_ids) {
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::MissingStation(recipe.stations().to_vec())));
+			continue
+		}
+		
+		let Some(output) = item_defs.raw_item_ids().get_raw_id(recipe.output().item()) else {
+			warn!("Recipe {} outputs nonexistent item {}", recipe.identifier(), recipe.output().item());
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::NoMatchingRecipe));
+			continue
+		};
+		
+		// craft on a copy so that nothing is lost if any step fails
+		let mut crafted = inventory.clone();
+		let mut has_ingredients = true;
+		for (item, count) in recipe.ingredients() {
+			let removed = item_defs
+				.raw_item_ids()
+				.get_raw_id(&item)
+				.is_some_and(|raw_id| crafted.remove(raw_id, count));
+			if !removed {
+				has_ingredients = false;
+				break
+			}
+		}
+		
+		if !has_ingredients {
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::MissingIngredients));
+			continue
+		}
+		
+		if crafted.insert(ItemStack::new(output, recipe.output().count()), item_defs.max_stack(output)).is_some() {
+			send_message!(server, client_id, ServerResponse::CraftDeny(CraftDenyReason::InventoryFull));
+			continue
+		}
+		
+		*inventory = crafted;
+		send_message!(server, client_id, ServerResponse::CraftAccept(recipe.identifier().clone()));
+		send_message!(server, client_id, ServerMessage::Inventory(inventory.clone()));
+	}
+	
+	Ok(())
+}
+
+/// Returns whether any of the station tiles are within [TILE_REACH] of the position.
+fn near_station(world: &ServerGameWorld, position: Option<&Position>, stations: &[Identifier], raw_tile_ids: &RawTileIds) -> bool {
+	let Some(position) = position else {
+		return false
+	};
+	
+	let reach = TILE_REACH.ceil() as i32;
+	let center = TilePos {
+		x: position.x.round() as i32,
+		y: position.y.round() as i32,
+	};
+	
+	for y in (center.y - reach)..=(center.y + reach) {
+		for x in (center.x - reach)..=(center.x + reach) {
+			let distance = Vec2::new(x as f32 - position.x, y as f32 - position.y).length();
+			if distance > TILE_REACH {
+				continue
+			}
+			
+			let pos = TilePos { x, y };
+			let is_station = TileLayer::ALL
+				.iter()
+				.filter_map(|&layer| world.tiles.get(layer, &pos))
+				.any(|tile| raw_tile_ids.get_id(tile.0).is_some_and(|id| stations.contains(id)));
+			if is_station {
+				return true
+			}
+		}
+	}
+	
+	false
+}
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::identifier::Identifier;
+use crate::registry::tile::TileDefs;
+use crate::registry::tile::growth::GrowthConditions;
+use crate::tile::{TileData, WorldTile};
+use crate::tile::layer::TileLayer;
+use crate::world::{CHUNK_SIZE, ServerGameWorld, ServerGameWorlds};
+use crate::TilePos;
+
+use super::liquid::liquid_within;
+use super::tile::PendingTileUpdates;
+
+/// How many times per second random ticks happen.
+pub const RANDOM_TICK_RATE: f64 = 20.0;
+/// How many random tiles in each chunk are ticked per random tick.
+pub const RANDOM_TICKS_PER_CHUNK: u32 = 3;
+
+/// Drives random ticks, which let tiles grow and spread.
+///
+/// All randomness comes from the contained RNG, so a seeded instance always produces the same updates for the same worlds.
+#[derive(Resource)]
+pub struct RandomTicks {
+	timer: Timer,
+	rng: StdRng,
+	per_chunk: u32,
+}
+
+impl Default for RandomTicks {
+	fn default() -> Self {
+		Self::new(StdRng::from_entropy())
+	}
+}
+
+impl RandomTicks {
+	pub fn new(rng: StdRng) -> Self {
+		Self {
+			timer: Timer::new(Duration::from_secs_f64(1.0 / RANDOM_TICK_RATE), TimerMode::Repeating),
+			rng,
+			per_chunk: RANDOM_TICKS_PER_CHUNK,
+		}
+	}
+	
+	pub fn seeded(seed: u64) -> Self {
+		Self::new(StdRng::seed_from_u64(seed))
+	}
+}
+
+/// Randomly ticks tiles in every loaded chunk and queues the tiles that changed to be sent.
+pub fn random_tick(
+	time: Res<Time>,
+	mut random_ticks: ResMut<RandomTicks>,
+	mut worlds: ResMut<ServerGameWorlds>,
+	mut pending: ResMut<PendingTileUpdates>,
+	tile_defs: TileDefs,
+) {
+	let RandomTicks { timer, rng, per_chunk } = &mut *random_ticks;
+	timer.tick(time.delta());
+	
+	for _ in 0..timer.times_finished_this_tick() {
+		// worlds are ticked in order so that the RNG is consumed the same way every time
+		let mut sorted_worlds: Vec<(&String, &mut ServerGameWorld)> = worlds.iter_mut().collect();
+		sorted_worlds.sort_by(|(a, _), (b, _)| a.cmp(b));
+		
+		for (_, world) in sorted_worlds {
+			let changes = random_tick_world(world, rng, *per_chunk, &tile_defs);
+			for (layer, pos, tile) in changes {
+				pending.push(&world.id, layer, pos, tile);
+			}
+		}
+	}
+}
+
+/// Ticks `ticks_per_chunk` random positions in each loaded chunk of the world, returning the tiles that changed.
+/// 
+/// Every layer at a ticked position is ticked.
+pub fn random_tick_world(world: &mut ServerGameWorld, rng: &mut impl Rng, ticks_per_chunk: u32, tile_defs: &TileDefs) -> Vec<(TileLayer, TilePos, WorldTile)> {
+	let mut changes = vec![];
+	for chunk in world.loaded_chunks() {
+		let origin = chunk.origin();
+		for _ in 0..ticks_per_chunk {
+			let pos = TilePos {
+				x: origin.x + rng.gen_range(0..CHUNK_SIZE),
+				y: origin.y + rng.gen_range(0..CHUNK_SIZE),
+			};
+			for layer in TileLayer::ALL {
+				changes.extend(tick_tile(world, layer, pos, rng, tile_defs));
+			}
+		}
+	}
+	world.update_light(changes.iter().map(|(_, pos, _)| *pos), tile_defs);
+	changes
+}
+
+/// Grows and spreads the tile at the position according to its [TileGrowth](crate::registry::tile::growth::TileGrowth).
+/// 
+/// Tiles only spread within their own layer.
+fn tick_tile(world: &mut ServerGameWorld, layer: TileLayer, pos: TilePos, rng: &mut impl Rng, tile_defs: &TileDefs) -> Vec<(TileLayer, TilePos, WorldTile)> {
+	let mut changes = vec![];
+	
+	let Some(tile) = world.tiles.get(layer, &pos).cloned() else {
+		return changes
+	};
+	
+	let Some(def) = tile_defs.get_raw(tile.0) else {
+		return changes
+	};
+	
+	let Some(growth) = def.growth() else {
+		return changes
+	};
+	
+	if !conditions_met(world, layer, pos, growth.conditions(), tile_defs) {
+		return changes
+	}
+	
+	let stage = tile.1.0;
+	if let Some(next) = growth.next_stage(stage) {
+		if rng.gen::<f32>() < growth.chance() {
+			let grown = WorldTile(tile.0, TileData(next));
+			world.tiles.insert(layer, pos, grown.clone());
+			changes.push((layer, pos, grown));
+		}
+	}
+	
+	if let Some(spread) = growth.spread() {
+		if (!spread.requires_mature() || growth.is_mature(stage)) && rng.gen::<f32>() < spread.chance() {
+			let radius = spread.radius() as i32;
+			let target = TilePos {
+				x: pos.x + rng.gen_range(-radius..=radius),
+				y: pos.y + rng.gen_range(-radius..=radius),
+			};
+			
+			let replaceable = world.tiles
+				.get(layer, &target)
+				.and_then(|tile| tile_defs.raw_tile_ids().get_id(tile.0))
+				.is_some_and(|id| spread.onto().contains(id));
+			if target != pos && replaceable && conditions_met(world, layer, target, growth.conditions(), tile_defs) {
+				let sprout = WorldTile(tile.0, TileData(0));
+				world.set_tile(layer, target, sprout.clone(), def);
+				changes.push((layer, target, sprout));
+			}
+		}
+	}
+	
+	changes
+}
+
+/// Returns whether a tile in the layer at the position would have everything it needs to grow.
+/// 
+/// Soil has to be in the same layer, while water is looked up in the liquid simulation.
+pub fn conditions_met(world: &ServerGameWorld, layer: TileLayer, pos: TilePos, conditions: &GrowthConditions, tile_defs: &TileDefs) -> bool {
+	let id_at = |layer: TileLayer, pos: TilePos| -> Option<&Identifier> {
+		tile_defs.raw_tile_ids().get_id(world.tiles.get(layer, &pos)?.0)
+	};
+	
+	if world.light.surface(&pos).with_daylight(world.time.daylight()) < conditions.min_light() {
+		return false
+	}
+	
+	if !conditions.soil().is_empty() {
+		let below = TilePos { x: pos.x, y: pos.y - 1 };
+		if !id_at(layer, below).is_some_and(|id| conditions.soil().contains(id)) {
+			return false
+		}
+	}
+	
+	if !conditions.water().is_empty() && !liquid_within(world, &pos, conditions.water_radius(), conditions.water(), tile_defs) {
+		return false
+	}
+	
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::registry::tile::liquid::MAX_LIQUID_LEVEL;
+	use crate::server::testing::{test_world, TestTiles};
+	use crate::world::WorldTime;
+	
+	fn test_tiles() -> TestTiles {
+		TestTiles::new(&[
+			("dirt", "(settings: (salience: fg))"),
+			("stone", "(settings: (salience: fg))"),
+			("water", "#![enable(implicit_some)] (settings: (salience: fg), liquid: (viscosity: 1))"),
+			("grass", "#![enable(implicit_some)] (settings: (salience: fg), growth: (spread: (onto: [\"test:dirt\"], chance: 1.0, requires_mature: false)))"),
+			("sprout", "#![enable(implicit_some)] (settings: (salience: fg), growth: (stages: 3, chance: 1.0))"),
+			("crop", "#![enable(implicit_some)] (settings: (salience: fg), growth: (conditions: (min_light: 8, soil: [\"test:dirt\"], water: [\"test:water\"], water_radius: 2)))"),
+		])
+	}
+	
+	fn pos(x: i32, y: i32) -> TilePos {
+		TilePos { x, y }
+	}
+	
+	#[test]
+	fn same_seed_same_changes() {
+		let mut tiles = test_tiles();
+		let (dirt, grass) = (tiles.tile("dirt", 0), tiles.tile("grass", 0));
+		let tile_defs = tiles.defs();
+		
+		// stripes of grass and dirt filling two chunks
+		let mut world = test_world(
+			(0..CHUNK_SIZE)
+				.flat_map(|y| (0..2 * CHUNK_SIZE).map(move |x| pos(x, y)))
+				.map(|pos| (TileLayer::Foreground, pos, if pos.x % 2 == 0 { grass.clone() } else { dirt.clone() }))
+		);
+		let mut other_world = world.clone();
+		
+		let mut rng = StdRng::seed_from_u64(7);
+		let mut other_rng = StdRng::seed_from_u64(7);
+		let mut changed = false;
+		for _ in 0..100 {
+			let changes = random_tick_world(&mut world, &mut rng, RANDOM_TICKS_PER_CHUNK, &tile_defs);
+			let other_changes = random_tick_world(&mut other_world, &mut other_rng, RANDOM_TICKS_PER_CHUNK, &tile_defs);
+			assert_eq!(changes, other_changes);
+			changed |= !changes.is_empty();
+		}
+		
+		assert!(changed);
+		assert!(world.tiles == other_world.tiles);
+	}
+	
+	#[test]
+	fn stages_advance_until_mature() {
+		let mut tiles = test_tiles();
+		let sprout = tiles.tile("sprout", 0);
+		let tile_defs = tiles.defs();
+		let mut world = test_world([(TileLayer::Foreground, pos(0, 0), sprout.clone())]);
+		let mut rng = StdRng::seed_from_u64(0);
+		
+		for stage in 1..3 {
+			let changes = tick_tile(&mut world, TileLayer::Foreground, pos(0, 0), &mut rng, &tile_defs);
+			let grown = WorldTile(sprout.0, TileData(stage));
+			assert_eq!(changes, vec![(TileLayer::Foreground, pos(0, 0), grown.clone())]);
+			assert_eq!(world.tiles.get(TileLayer::Foreground, &pos(0, 0)), Some(&grown));
+		}
+		
+		// the last stage is mature, so there's nothing left to grow into
+		assert!(tick_tile(&mut world, TileLayer::Foreground, pos(0, 0), &mut rng, &tile_defs).is_empty());
+		assert_eq!(world.tiles.get(TileLayer::Foreground, &pos(0, 0)), Some(&WorldTile(sprout.0, TileData(2))));
+	}
+	
+	#[test]
+	fn conditions_need_soil_light_and_water() {
+		let mut tiles = test_tiles();
+		let (dirt, stone, water, crop) = (tiles.tile("dirt", 0), tiles.tile("stone", 0), tiles.tile("water", MAX_LIQUID_LEVEL), tiles.tile("crop", 0));
+		let tile_defs = tiles.defs();
+		let conditions = tile_defs.get_raw(crop.0).unwrap().growth().unwrap().conditions().clone();
+		let met = |world: &ServerGameWorld| conditions_met(world, TileLayer::Foreground, pos(0, 0), &conditions, &tile_defs);
+		
+		let mut world = test_world([
+			(TileLayer::Foreground, pos(0, 0), crop),
+			(TileLayer::Foreground, pos(0, -1), dirt.clone()),
+			(TileLayer::Foreground, pos(2, 0), water.clone()),
+		]);
+		assert!(met(&world));
+		
+		// the soil has to be right below, and in the same layer
+		world.tiles.insert(TileLayer::Foreground, pos(0, -1), stone);
+		assert!(!met(&world));
+		world.tiles.remove(TileLayer::Foreground, &pos(0, -1));
+		world.tiles.insert(TileLayer::Background, pos(0, -1), dirt.clone());
+		assert!(!met(&world));
+		world.tiles.remove(TileLayer::Background, &pos(0, -1));
+		world.tiles.insert(TileLayer::Foreground, pos(0, -1), dirt);
+		assert!(met(&world));
+		
+		// not enough light at midnight
+		world.time = WorldTime(0.0);
+		assert!(!met(&world));
+		world.time = test_world([]).time;
+		assert!(met(&world));
+		
+		// the water has to be within the radius
+		world.tiles.remove(TileLayer::Foreground, &pos(2, 0));
+		world.tiles.insert(TileLayer::Foreground, pos(3, 0), water);
+		assert!(!met(&world));
+	}
+	
+	#[test]
+	fn spreads_onto_allowed_tiles_only() {
+		let mut tiles = test_tiles();
+		let (dirt, stone, grass) = (tiles.tile("dirt", 0), tiles.tile("stone", 0), tiles.tile("grass", 0));
+		let tile_defs = tiles.defs();
+		
+		// grass boxed in by stone, except for a single dirt tile
+		let mut world = test_world(
+			(-1..=1)
+				.flat_map(|y| (-1..=1).map(move |x| pos(x, y)))
+				.map(|pos| (TileLayer::Foreground, pos, stone.clone()))
+		);
+		world.tiles.insert(TileLayer::Foreground, pos(0, 0), grass.clone());
+		world.tiles.insert(TileLayer::Foreground, pos(1, 0), dirt.clone());
+		// nor does it spread into other layers
+		world.tiles.insert(TileLayer::Background, pos(-1, 0), dirt.clone());
+		let mut rng = StdRng::seed_from_u64(0);
+		
+		let mut changes = vec![];
+		for _ in 0..200 {
+			changes.extend(tick_tile(&mut world, TileLayer::Foreground, pos(0, 0), &mut rng, &tile_defs));
+		}
+		
+		assert_eq!(changes, vec![(TileLayer::Foreground, pos(1, 0), grass.clone())]);
+		for (layer, tile_pos, tile) in world.tiles.iter() {
+			let expected = match layer {
+				TileLayer::Foreground if *tile_pos == pos(0, 0) || *tile_pos == pos(1, 0) => &grass,
+				TileLayer::Foreground => &stone,
+				_ => &dirt,
+			};
+			assert_eq!(tile, expected);
+		}
+	}
+}
@@ -0,0 +1,173 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use renet::RenetServer;
+
+use crate::env::EnvType;
+use crate::networking::transport::ServerTransport;
+use crate::raw_id::item::RawItemIds;
+use crate::raw_id::tile::RawTileIds;
+use crate::save;
+use crate::world::ServerGameWorlds;
+use crate::{GameState, Headless};
+
+use super::networking::{save_server_world, NetworkingPlugin, PlayerRecordQuery, ServerAddress, ServerConfig, ServerPort, ServerSocket};
+
+/// What the client and its integrated server tell each other across threads.
+#[derive(Debug, Default)]
+struct Signals {
+	/// Set by the server once it accepts connections.
+	ready: AtomicBool,
+	/// Set by the client to make the server save everything and stop.
+	shutdown: AtomicBool,
+}
+
+/// A server running on a background thread of the client, for singleplayer.
+///
+/// It listens on a loopback port, so nothing outside of the machine can connect to it. It uses unsecure authentication
+/// like dedicated servers do, doesn't answer status queries and saves into [save::SINGLEPLAYER_SAVE_DIR].
+#[derive(Debug, Resource)]
+pub struct IntegratedServer {
+	address: SocketAddr,
+	signals: Arc<Signals>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl IntegratedServer {
+	/// Starts the server in the background. It isn't ready to be connected to until [IntegratedServer::is_ready].
+	pub fn start() -> io::Result<Self> {
+		// bind here, so that the address is known right away
+		let socket = UdpSocket::bind("127.0.0.1:0")?;
+		let address = socket.local_addr()?;
+		
+		let signals = Arc::new(Signals::default());
+		let server_signals = signals.clone();
+		
+		let thread = std::thread::Builder::new()
+			.name("integrated server".to_string())
+			.spawn(move || {
+				let server_config = ServerConfig {
+					address: ServerAddress(address.ip().to_string()),
+					port: ServerPort(address.port()),
+					max_clients: 1,
+					motd: String::new(),
+					query_port: None,
+					save_dir: PathBuf::from(save::SINGLEPLAYER_SAVE_DIR),
+				};
+				
+				let mut app = crate::build_app(EnvType::Server, Headless(true));
+				app
+					.insert_resource(server_config)
+					.insert_resource(ServerSocket(socket))
+					.insert_resource(ServerSignals(server_signals))
+					.add_plugins(NetworkingPlugin)
+					.add_systems(OnEnter(GameState::ServerLoaded), mark_ready)
+					.add_systems(Last, shut_down);
+				app.run();
+			})?;
+		
+		println!("Starting integrated server @ {}", address);
+		Ok(Self {
+			address,
+			signals,
+			thread: Some(thread),
+		})
+	}
+	
+	pub fn address(&self) -> SocketAddr {
+		self.address
+	}
+	
+	/// Returns whether the server has finished loading and accepts connections.
+	pub fn is_ready(&self) -> bool {
+		self.signals.ready.load(Ordering::Acquire)
+	}
+	
+	/// Returns whether the server thread has stopped, whether it was asked to or not. A server that stops before it's
+	/// ready has failed to start, for example because its assets or saves couldn't be loaded.
+	pub fn has_stopped(&self) -> bool {
+		self.thread.as_ref().map_or(true, |thread| thread.is_finished())
+	}
+	
+	/// Makes the server save every loaded world and stop, and waits until it has.
+	pub fn stop(&mut self) {
+		self.signals.shutdown.store(true, Ordering::Release);
+		if let Some(thread) = self.thread.take() {
+			if thread.join().is_err() {
+				eprintln!("The integrated server panicked");
+			}
+		}
+	}
+}
+
+/// The integrated server's side of the [Signals].
+#[derive(Debug, Resource)]
+struct ServerSignals(Arc<Signals>);
+
+fn mark_ready(signals: Res<ServerSignals>) {
+	signals.0.ready.store(true, Ordering::Release);
+}
+
+/// Saves every loaded world and exits once the client asks the server to stop.
+fn shut_down(
+	signals: Res<ServerSignals>,
+	server_config: Res<ServerConfig>,
+	worlds: Res<ServerGameWorlds>,
+	player_record_query: PlayerRecordQuery,
+	raw_tile_ids: Option<Res<RawTileIds>>,
+	raw_item_ids: Option<Res<RawItemIds>>,
+	server: Option<ResMut<RenetServer>>,
+	transport: Option<ResMut<ServerTransport>>,
+	mut ev_app_exit: EventWriter<AppExit>,
+) {
+	if !signals.0.shutdown.load(Ordering::Acquire) {
+		return
+	}
+	
+	// nothing can have changed if the ids haven't even been loaded yet
+	if let (Some(raw_tile_ids), Some(raw_item_ids)) = (raw_tile_ids, raw_item_ids) {
+		for (name, world) in worlds.iter() {
+			if let Err(err) = save_server_world(&server_config.save_dir, world, &player_record_query, &raw_tile_ids, &raw_item_ids) {
+				eprintln!("Failed to save world {}: {}", name, err);
+			}
+		}
+	}
+	
+	if let (Some(mut server), Some(mut transport)) = (server, transport) {
+		transport.disconnect_all(&mut server);
+	}
+	
+	println!("Stopped integrated server");
+	ev_app_exit.send(AppExit);
+}
+
+/// Stops the integrated server, if there is one, once the player is back at the title screen.
+pub fn stop_integrated_server(
+	integrated_server: Option<ResMut<IntegratedServer>>,
+	mut commands: Commands,
+) {
+	if let Some(mut integrated_server) = integrated_server {
+		integrated_server.stop();
+		commands.remove_resource::<IntegratedServer>();
+	}
+}
+
+/// Stops the integrated server, if there is one, before the client exits, so that the worlds get saved.
+pub fn stop_integrated_server_on_exit(
+	integrated_server: Option<ResMut<IntegratedServer>>,
+	ev_app_exit: EventReader<AppExit>,
+) {
+	if ev_app_exit.is_empty() {
+		return
+	}
+	
+	if let Some(mut integrated_server) = integrated_server {
+		integrated_server.stop();
+	}
+}
@@ -0,0 +1,60 @@
+use bevy::prelude::*;
+
+use crate::creature::player::Player;
+use crate::inventory::{HOTBAR_SIZE, Inventory, InventoryAction, SelectedSlot};
+use crate::networking::error::NetworkError;
+use crate::networking::protocol::{ClientId, ClientMessage, Packet, ServerMessage};
+use crate::registry::item::ItemDefs;
+
+use super::networking::{send_message, NetServer, Players};
+
+/// Validates and applies [ClientMessage::InventoryAction] requests.
+///
+/// The resulting inventory is always sent back so that the client's copy stays in sync, even if the action was rejected.
+pub fn inventory_action(
+	message_query: Query<(&ClientId, &Packet)>,
+	mut server: NetServer,
+	players: Res<Players>,
+	mut player_query: Query<(&mut Inventory, &mut SelectedSlot), With<Player>>,
+	item_defs: ItemDefs,
+) -> Result<(), NetworkError> {
+	for (client_id, packet) in message_query.iter() {
+		let Packet::ClientMessage(ClientMessage::InventoryAction(action)) = packet else {
+			continue
+		};
+		
+		let Some(&player_entity) = players.get(client_id) else {
+			continue
+		};
+		
+		// players that aren't in a world don't have an inventory
+		let Ok((mut inventory, mut selected_slot)) = player_query.get_mut(player_entity) else {
+			continue
+		};
+		
+		let valid = match *action {
+			InventoryAction::Move { from, to } => {
+				let max_stack = inventory
+					.get(from)
+					.map(|stack| item_defs.max_stack(stack.item))
+					.unwrap_or_default();
+				inventory.move_stack(from, to, max_stack)
+			},
+			InventoryAction::Split { from, to } => inventory.split_stack(from, to),
+			InventoryAction::SelectHotbar(slot) => {
+				if slot < HOTBAR_SIZE {
+					selected_slot.0 = slot;
+				}
+				continue
+			},
+		};
+		
+		if !valid {
+			println!("Rejected inventory action from client {:X}: {:?}", client_id.0, action);
+		}
+		
+		send_message!(server, client_id, ServerMessage::Inventory(inventory.clone()));
+	}
+	
+	Ok(())
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::creature::player::Player;
+use crate::networking::error::NetworkError;
+use crate::networking::protocol::{ClientId, ServerMessage};
+use crate::registry::tile::TileDefs;
+use crate::world::ServerGameWorlds;
+
+use super::networking::{broadcast_world_message, NetServer};
+
+/// How often (in seconds) the world time is sent to the players in each world.
+pub const TIME_SYNC_INTERVAL: f64 = 5.0;
+
+/// Decides when the world time is next sent to players.
+#[derive(Resource, Deref, DerefMut)]
+pub struct TimeSyncTimer(Timer);
+
+impl Default for TimeSyncTimer {
+	fn default() -> Self {
+		Self(Timer::new(Duration::from_secs_f64(TIME_SYNC_INTERVAL), TimerMode::Repeating))
+	}
+}
+
+/// Computes the light of worlds that have just been loaded.
+pub fn light_loaded_worlds(
+	mut worlds: ResMut<ServerGameWorlds>,
+	tile_defs: TileDefs,
+) {
+	for (_, world) in worlds.iter_mut() {
+		if !world.light.is_computed() {
+			world.light.compute(&world.tiles, &|raw_id| tile_defs.settings(raw_id));
+		}
+	}
+}
+
+/// Advances the time of every world and periodically syncs it with the players in it.
+pub fn advance_world_time(
+	time: Res<Time>,
+	mut sync_timer: ResMut<TimeSyncTimer>,
+	mut server: NetServer,
+	mut worlds: ResMut<ServerGameWorlds>,
+	player_client_id_query: Query<&ClientId, With<Player>>,
+) -> Result<(), NetworkError> {
+	sync_timer.tick(time.delta());
+	
+	for (_, world) in worlds.iter_mut() {
+		world.time.advance(time.delta());
+		if sync_timer.just_finished() {
+			broadcast_world_message!(server, world, player_client_id_query, ServerMessage::WorldTime(world.time));
+		}
+	}
+	
+	Ok(())
+}
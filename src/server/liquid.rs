@@ -0,0 +1,400 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::registry::tile::TileDefs;
+use crate::registry::tile::liquid::MAX_LIQUID_LEVEL;
+use crate::tile::{TileData, WorldTile};
+use crate::tile::layer::TileLayer;
+use crate::utils::BevyHashMap;
+use crate::world::{ChunkPos, ServerGameWorld, ServerGameWorlds, WorldId};
+use crate::TilePos;
+
+use super::tile::PendingTileUpdates;
+
+/// How many times per second liquids may flow.
+pub const LIQUID_TICK_RATE: f64 = 5.0;
+
+/// Runs the liquid simulation of every loaded world.
+///
+/// The simulation doesn't touch the network; the changes it makes are sent out as [LiquidChangeEvent]s.
+pub struct LiquidPlugin;
+
+impl Plugin for LiquidPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<LiquidTicks>()
+			.add_event::<LiquidChangeEvent>()
+			.add_systems(
+				Update,
+				flow_liquids
+					.run_if(in_state(GameState::ServerLoaded))
+			);
+	}
+}
+
+/// Drives liquid ticks.
+#[derive(Resource)]
+pub struct LiquidTicks {
+	timer: Timer,
+	/// The number of liquid ticks so far, which decides which liquids flow based on their viscosity.
+	tick: u64,
+}
+
+impl Default for LiquidTicks {
+	fn default() -> Self {
+		Self {
+			timer: Timer::new(Duration::from_secs_f64(1.0 / LIQUID_TICK_RATE), TimerMode::Repeating),
+			tick: 0,
+		}
+	}
+}
+
+impl LiquidTicks {
+	pub fn tick(&self) -> u64 {
+		self.tick
+	}
+}
+
+/// Sent once per liquid tick for each world in which liquid moved.
+#[derive(Event, Debug, Clone)]
+pub struct LiquidChangeEvent {
+	pub world: WorldId,
+	/// The final state of every tile that changed, ordered from the bottom row up.
+	pub changes: Vec<(TileLayer, TilePos, WorldTile)>,
+}
+
+/// Flows the liquids of every loaded world.
+pub fn flow_liquids(
+	time: Res<Time>,
+	mut liquid_ticks: ResMut<LiquidTicks>,
+	mut worlds: ResMut<ServerGameWorlds>,
+	mut ev_liquid_change: EventWriter<LiquidChangeEvent>,
+	tile_defs: TileDefs,
+) {
+	liquid_ticks.timer.tick(time.delta());
+	
+	for _ in 0..liquid_ticks.timer.times_finished_this_tick() {
+		liquid_ticks.tick += 1;
+		for (_, world) in worlds.iter_mut() {
+			let changes = step_liquids(world, liquid_ticks.tick, &tile_defs);
+			if !changes.is_empty() {
+				world.update_light(changes.iter().map(|(_, pos, _)| *pos), &tile_defs);
+				ev_liquid_change.send(LiquidChangeEvent { world: world.id.clone(), changes });
+			}
+		}
+	}
+}
+
+/// Queues the changes made by liquid ticks to be sent to the players in each world.
+pub fn queue_liquid_changes(
+	mut ev_liquid_change: EventReader<LiquidChangeEvent>,
+	mut pending: ResMut<PendingTileUpdates>,
+) {
+	for event in ev_liquid_change.iter() {
+		for (layer, pos, tile) in event.changes.iter() {
+			pending.push(&event.world, *layer, *pos, tile.clone());
+		}
+	}
+}
+
+/// Returns the liquid at the position and its level, if there is one.
+pub fn liquid_at(world: &ServerGameWorld, pos: &TilePos, tile_defs: &TileDefs) -> Option<(RawId, u8)> {
+	let tile = world.tiles.get(TileLayer::Foreground, pos)?;
+	tile_defs.get_raw(tile.0)?.liquid()?;
+	Some((tile.0, tile.1.0))
+}
+
+/// Returns whether one of the liquids is within `radius` tiles (along each axis) of the position. This is what plants use
+/// to check whether they are watered.
+pub fn liquid_within(world: &ServerGameWorld, pos: &TilePos, radius: u8, liquids: &[Identifier], tile_defs: &TileDefs) -> bool {
+	let radius = radius as i32;
+	(-radius..=radius)
+		.flat_map(|dy| (-radius..=radius).map(move |dx| TilePos { x: pos.x + dx, y: pos.y + dy }))
+		.filter_map(|pos| liquid_at(world, &pos, tile_defs))
+		.any(|(raw_id, level)| level > 0 && tile_defs.raw_tile_ids().get_id(raw_id).is_some_and(|id| liquids.contains(id)))
+}
+
+/// What a liquid finds at a position it could flow into.
+enum FlowTarget {
+	/// Nothing is there, so the liquid can move in.
+	Empty,
+	/// The same liquid is there, with the given level.
+	Liquid(u8),
+	/// A solid tile, a different liquid or the edge of the loaded world.
+	Blocked,
+}
+
+/// Runs one step of the liquid cellular automaton and returns the final state of every tile that changed.
+///
+/// Liquids are processed from the bottom row up and from left to right within a row, so the result only depends on the
+/// tiles of the world. Each liquid first falls into the tile below it, and whatever doesn't fit is then shared evenly with
+/// the tiles to its left and right. Liquid only flows into chunks that are already loaded, so it can't fall out of the
+/// world forever.
+pub fn step_liquids(world: &mut ServerGameWorld, tick: u64, tile_defs: &TileDefs) -> Vec<(TileLayer, TilePos, WorldTile)> {
+	let loaded_chunks: BTreeSet<ChunkPos> = world.loaded_chunks();
+	
+	let mut liquids: Vec<TilePos> = world.tiles
+		.layer(TileLayer::Foreground)
+		.into_iter()
+		.flatten()
+		.filter(|(_, tile)| {
+			tile_defs
+				.get_raw(tile.0)
+				.and_then(|def| def.liquid())
+				.is_some_and(|liquid| tick % liquid.viscosity() as u64 == 0)
+		})
+		.map(|(pos, _)| *pos)
+		.collect();
+	liquids.sort_by_key(|pos| (pos.y, pos.x));
+	
+	let mut changed: BevyHashMap<TilePos, WorldTile> = BevyHashMap::default();
+	
+	for pos in liquids {
+		// an earlier liquid may have changed this tile already
+		let Some((raw_id, level)) = liquid_at(world, &pos, tile_defs) else {
+			continue
+		};
+		if level == 0 {
+			continue
+		}
+		
+		let target = |world: &ServerGameWorld, target: TilePos| -> FlowTarget {
+			if !loaded_chunks.contains(&ChunkPos::from(target)) {
+				return FlowTarget::Blocked
+			}
+			match world.tiles.get(TileLayer::Foreground, &target) {
+				None => FlowTarget::Empty,
+				Some(tile) if tile.is_air() => FlowTarget::Empty,
+				Some(tile) if tile.0 == raw_id => FlowTarget::Liquid(tile.1.0),
+				Some(_) => FlowTarget::Blocked,
+			}
+		};
+		
+		let mut set_level = |world: &mut ServerGameWorld, pos: TilePos, level: u8| {
+			let tile = if level == 0 { WorldTile::air() } else { WorldTile(raw_id, TileData(level)) };
+			if level == 0 {
+				world.remove_tile(TileLayer::Foreground, &pos);
+			} else {
+				world.tiles.insert(TileLayer::Foreground, pos, tile.clone());
+			}
+			changed.insert(pos, tile);
+		};
+		
+		let mut remaining = level;
+		
+		// fall
+		let below = TilePos { x: pos.x, y: pos.y - 1 };
+		let below_level = match target(world, below) {
+			FlowTarget::Empty => Some(0),
+			FlowTarget::Liquid(below_level) => Some(below_level),
+			FlowTarget::Blocked => None,
+		};
+		if let Some(below_level) = below_level {
+			let moved = remaining.min(MAX_LIQUID_LEVEL.saturating_sub(below_level));
+			if moved > 0 {
+				remaining -= moved;
+				set_level(world, below, below_level + moved);
+			}
+		}
+		
+		// spread out evenly with the neighbors that have less
+		let sides: Vec<(TilePos, u8)> = [TilePos { x: pos.x - 1, y: pos.y }, TilePos { x: pos.x + 1, y: pos.y }]
+			.into_iter()
+			.filter_map(|side| match target(world, side) {
+				FlowTarget::Empty => Some((side, 0)),
+				FlowTarget::Liquid(side_level) if side_level + 1 < remaining => Some((side, side_level)),
+				_ => None,
+			})
+			.collect();
+		if !sides.is_empty() && remaining > 1 {
+			let total = remaining as u32 + sides.iter().map(|(_, level)| *level as u32).sum::<u32>();
+			let share = (total / (sides.len() as u32 + 1)) as u8;
+			for (side, side_level) in sides.iter() {
+				if share != *side_level {
+					set_level(world, *side, share);
+				}
+			}
+			remaining = (total - share as u32 * sides.len() as u32) as u8;
+		}
+		
+		if remaining != level {
+			set_level(world, pos, remaining);
+		}
+	}
+	
+	let mut changes: Vec<(TileLayer, TilePos, WorldTile)> = changed
+		.into_iter()
+		.map(|(pos, tile)| (TileLayer::Foreground, pos, tile))
+		.collect();
+	changes.sort_by_key(|(_, pos, _)| (pos.y, pos.x));
+	changes
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::server::testing::{test_world, TestTiles};
+	use crate::world::CHUNK_SIZE;
+	
+	fn test_tiles() -> TestTiles {
+		TestTiles::new(&[
+			("stone", "(settings: (salience: fg))"),
+			("water", "#![enable(implicit_some)] (settings: (salience: fg), liquid: (viscosity: 1))"),
+			("honey", "#![enable(implicit_some)] (settings: (salience: fg), liquid: (viscosity: 3))"),
+		])
+	}
+	
+	fn pos(x: i32, y: i32) -> TilePos {
+		TilePos { x, y }
+	}
+	
+	/// Returns a world with a stone floor along the bottom row of its only chunk, and the tiles on top.
+	fn floored_world(tiles: &TestTiles, on_top: impl IntoIterator<Item = (TilePos, WorldTile)>) -> ServerGameWorld {
+		let stone = tiles.tile("stone", 0);
+		let mut world = test_world((0..CHUNK_SIZE).map(|x| (TileLayer::Foreground, pos(x, 0), stone.clone())));
+		for (pos, tile) in on_top {
+			world.tiles.insert(TileLayer::Foreground, pos, tile);
+		}
+		world
+	}
+	
+	fn level_at(world: &ServerGameWorld, pos: TilePos) -> Option<u8> {
+		world.tiles.get(TileLayer::Foreground, &pos).map(|tile| tile.1.0)
+	}
+	
+	#[test]
+	fn falls_into_the_tile_below() {
+		let mut tiles = test_tiles();
+		let (full, topped, topping) = (tiles.tile("water", MAX_LIQUID_LEVEL), tiles.tile("water", 6), tiles.tile("water", 5));
+		let mut world = floored_world(&tiles, [(pos(5, 3), full), (pos(8, 2), topping), (pos(8, 1), topped)]);
+		let tile_defs = tiles.defs();
+		
+		let changes = step_liquids(&mut world, 1, &tile_defs);
+		
+		// a full tile falls all the way into an empty one
+		assert_eq!(level_at(&world, pos(5, 3)), None);
+		assert_eq!(level_at(&world, pos(5, 2)), Some(MAX_LIQUID_LEVEL));
+		assert!(changes.contains(&(TileLayer::Foreground, pos(5, 3), WorldTile::air())));
+		// but only fills up one that already has some, leaving the rest behind
+		assert_eq!(level_at(&world, pos(8, 1)), Some(MAX_LIQUID_LEVEL));
+		assert_eq!(level_at(&world, pos(8, 2)), Some(1));
+	}
+	
+	#[test]
+	fn shares_sideways_when_it_cant_fall() {
+		let mut tiles = test_tiles();
+		let (water, drop) = (tiles.tile("water", 6), tiles.tile("water", 1));
+		let mut world = floored_world(&tiles, [(pos(5, 1), water), (pos(12, 1), drop)]);
+		let tile_defs = tiles.defs();
+		
+		step_liquids(&mut world, 1, &tile_defs);
+		
+		assert_eq!(level_at(&world, pos(4, 1)), Some(2));
+		assert_eq!(level_at(&world, pos(5, 1)), Some(2));
+		assert_eq!(level_at(&world, pos(6, 1)), Some(2));
+		// a single level can't be split up
+		assert_eq!(level_at(&world, pos(11, 1)), None);
+		assert_eq!(level_at(&world, pos(12, 1)), Some(1));
+		assert_eq!(level_at(&world, pos(13, 1)), None);
+	}
+	
+	#[test]
+	fn blocked_by_unloaded_chunks() {
+		let mut tiles = test_tiles();
+		let water = tiles.tile("water", MAX_LIQUID_LEVEL);
+		// in the corner of the only loaded chunk, so only the tile to the right is loaded
+		let mut world = test_world([(TileLayer::Foreground, pos(0, 0), water)]);
+		let tile_defs = tiles.defs();
+		
+		step_liquids(&mut world, 1, &tile_defs);
+		
+		assert_eq!(level_at(&world, pos(0, -1)), None);
+		assert_eq!(level_at(&world, pos(-1, 0)), None);
+		assert_eq!(level_at(&world, pos(0, 0)), Some(MAX_LIQUID_LEVEL / 2));
+		assert_eq!(level_at(&world, pos(1, 0)), Some(MAX_LIQUID_LEVEL / 2));
+	}
+	
+	#[test]
+	fn viscosity_skips_ticks() {
+		let mut tiles = test_tiles();
+		let honey = tiles.tile("honey", MAX_LIQUID_LEVEL);
+		let mut world = floored_world(&tiles, [(pos(5, 3), honey)]);
+		let tile_defs = tiles.defs();
+		
+		assert!(step_liquids(&mut world, 1, &tile_defs).is_empty());
+		assert!(step_liquids(&mut world, 2, &tile_defs).is_empty());
+		assert!(!step_liquids(&mut world, 3, &tile_defs).is_empty());
+		assert_eq!(level_at(&world, pos(5, 2)), Some(MAX_LIQUID_LEVEL));
+	}
+	
+	#[test]
+	fn finds_liquids_within_radius() {
+		let mut tiles = test_tiles();
+		let (water, honey) = (tiles.tile("water", 1), tiles.tile("honey", MAX_LIQUID_LEVEL));
+		let world = floored_world(&tiles, [(pos(5, 3), water), (pos(10, 3), honey)]);
+		let tile_defs = tiles.defs();
+		let water_ids = [TestTiles::id("water")];
+		
+		assert!(liquid_within(&world, &pos(5, 3), 0, &water_ids, &tile_defs));
+		assert!(liquid_within(&world, &pos(7, 5), 2, &water_ids, &tile_defs));
+		assert!(!liquid_within(&world, &pos(8, 3), 2, &water_ids, &tile_defs));
+		// other liquids don't count, and neither do solid tiles
+		assert!(!liquid_within(&world, &pos(10, 3), 1, &water_ids, &tile_defs));
+		assert!(!liquid_within(&world, &pos(5, 0), 1, &[TestTiles::id("stone")], &tile_defs));
+	}
+	
+	#[test]
+	fn changes_are_deterministic_and_ordered() {
+		let mut tiles = test_tiles();
+		let water = tiles.tile("water", MAX_LIQUID_LEVEL);
+		let mut world = floored_world(&tiles, (1..CHUNK_SIZE - 1).step_by(3).map(|x| (pos(x, 1 + x % 4), water.clone())));
+		let mut other_world = world.clone();
+		let tile_defs = tiles.defs();
+		
+		for tick in 1..20 {
+			let changes = step_liquids(&mut world, tick, &tile_defs);
+			assert_eq!(changes, step_liquids(&mut other_world, tick, &tile_defs));
+			assert!(changes.windows(2).all(|pair| (pair[0].1.y, pair[0].1.x) < (pair[1].1.y, pair[1].1.x)));
+		}
+		assert!(world.tiles == other_world.tiles);
+	}
+	
+	#[test]
+	fn flows_on_a_headless_app() {
+		let tiles = test_tiles();
+		let water = tiles.tile("water", MAX_LIQUID_LEVEL);
+		let mut worlds = ServerGameWorlds::default();
+		worlds.add_world("test".to_string(), floored_world(&tiles, [(pos(5, 3), water.clone())]));
+		
+		let mut app = App::new();
+		tiles.add_to(&mut app);
+		app
+			.init_resource::<Time>()
+			.init_resource::<LiquidTicks>()
+			.insert_resource(worlds)
+			.add_event::<LiquidChangeEvent>()
+			.add_systems(Update, flow_liquids);
+		
+		// nothing flows until a whole liquid tick has passed
+		app.update();
+		assert_eq!(app.world.resource::<LiquidTicks>().tick(), 0);
+		
+		app.world.resource_mut::<Time>().advance_by(Duration::from_secs_f64(1.0 / LIQUID_TICK_RATE));
+		app.update();
+		assert_eq!(app.world.resource::<LiquidTicks>().tick(), 1);
+		
+		let world = app.world.resource::<ServerGameWorlds>().get_world("test").unwrap();
+		assert_eq!(level_at(world, pos(5, 2)), Some(MAX_LIQUID_LEVEL));
+		let events: Vec<&LiquidChangeEvent> = app.world.resource::<Events<LiquidChangeEvent>>().iter_current_update_events().collect();
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].changes, vec![
+			(TileLayer::Foreground, pos(5, 2), WorldTile(water.0, TileData(MAX_LIQUID_LEVEL))),
+			(TileLayer::Foreground, pos(5, 3), WorldTile::air()),
+		]);
+	}
+}
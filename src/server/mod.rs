@@ -1 +1,14 @@
 pub mod networking;
+pub mod tile;
+pub mod inventory;
+pub mod crafting;
+pub mod growth;
+pub mod light;
+pub mod liquid;
+pub mod integrated;
+pub mod query;
+pub mod ping;
+pub mod nickname;
+
+#[cfg(test)]
+mod testing;
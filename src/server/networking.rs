@@ -1,23 +1,43 @@
 use crate::utils::BevyHashMap;
 use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
 
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy_renet::RenetServerPlugin;
-use bevy_renet::transport::NetcodeServerPlugin;
-use renet::{Bytes, ConnectionConfig, DefaultChannel, RenetServer, ServerEvent};
+use renet::{Bytes, RenetServer, ServerEvent};
 use renet::transport::{NetcodeServerTransport, ServerAuthentication};
 use serde::{Deserialize, Serialize};
 
 use crate::creature::player::{Player, PlayerBundle};
+use crate::inventory::{Inventory, SelectedSlot};
+use crate::raw_id::item::RawItemIds;
 use crate::raw_id::tile::RawTileIds;
-use crate::{env, GameState, Username, utils, VERSION_STRING};
+use crate::registry::tile::TileDefs;
+use crate::save::error::SaveError;
+use crate::server::crafting::craft;
+use crate::server::growth::{random_tick, RandomTicks};
+use crate::server::inventory::inventory_action;
+use crate::server::light::{advance_world_time, light_loaded_worlds, TimeSyncTimer};
+use crate::server::nickname::{change_nickname, setup_nicknames, Nicknames};
+use crate::server::liquid::{queue_liquid_changes, LiquidPlugin};
+use crate::server::ping::{ping_players, PingTimer};
+use crate::server::query::{answer_queries, setup_query};
+use crate::server::tile::{break_tile, place_tile, send_tile_updates, PendingTileUpdates};
+use crate::{env, GameState, Position, save, Username, utils, VERSION_STRING};
 use crate::networking::{protocol, time_since_epoch};
+use crate::networking::batch::TileUpdateBatch;
+use crate::networking::channel::{self, Channel};
+use crate::networking::envelope::{Encoder, Reassembler};
+use crate::networking::handshake::{self, Capabilities, Handshake, JoinVersion};
+use crate::networking::query::{QUERY_ERROR_MESSAGE, QueryError};
+use crate::networking::transport::{MemoryNetwork, MemoryServerTransport, ServerTransport, ServerTransportPlugin};
 use crate::networking::error::{NETWORK_ERROR_MESSAGE, NetworkError};
-use crate::networking::protocol::{ChatMessageBundle, ChatMessageContent, ClientId, ClientMessage, ClientMessageBundle, ClientResponse, PlayerData, PROTOCOL_VER, Packet, WorldDenyReason, ServerMessage, ServerResponse};
+use crate::networking::protocol::{PROTOCOL_VER, ChatMessageBundle, ChatMessageContent, ClientId, ClientMessage, ClientMessageBundle, ClientResponse, PlayerData, Packet, RosterEntry, WorldDenyReason, ServerMessage, ServerResponse};
 use crate::networking::stats::PlayerNetStats;
 use crate::player::{Source, Target};
 use crate::utils::{nonfatal_error_systems, strip_formatting};
-use crate::world::{ServerGameWorlds, WorldId};
+use crate::world::{FirstJoined, PlayerRecord, PlayerStats, ServerGameWorld, ServerGameWorlds, WorldId};
 
 pub struct NetworkingPlugin;
 
@@ -25,30 +45,53 @@ impl Plugin for NetworkingPlugin {
 	fn build(&self, app: &mut App) {
 		app
 			.add_plugins(RenetServerPlugin)
-			.add_plugins(NetcodeServerPlugin)
+			.add_plugins(ServerTransportPlugin)
+			.add_plugins(LiquidPlugin)
 			.init_resource::<Players>()
 			.init_resource::<ServerGameWorlds>()
 			.init_resource::<ServerConfig>()
+			.init_resource::<RandomTicks>()
+			.init_resource::<TimeSyncTimer>()
+			.init_resource::<PingTimer>()
+			.init_resource::<PendingTileUpdates>()
+			.init_resource::<Reassemblers>()
+			.init_resource::<Encoder>()
+			.init_resource::<ClientCapabilities>()
 			.add_systems(
 				OnEnter(GameState::ServerLoading),
-				setup
+				(setup, setup_query, setup_nicknames)
 					.run_if(env::is_server)
 			)
 			.add_systems(
 				Update,
 				(
-					nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, server, receive_message),
+					nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, server, receive_message, break_tile, place_tile, inventory_action, craft, change_nickname, advance_world_time, ping_players),
+					nonfatal_error_systems!(QUERY_ERROR_MESSAGE, QueryError, answer_queries),
+					light_loaded_worlds,
+					random_tick,
+					update_player_net_stats,
+					track_play_time,
+					queue_liquid_changes,
 				)
 					.run_if(in_state(GameState::ServerLoaded))
 					.run_if(env::is_server)
+			)
+			.add_systems(
+				PostUpdate,
+				// after everything that changes tiles
+				nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, send_tile_updates)
+					.run_if(in_state(GameState::ServerLoaded))
+					.run_if(env::is_server)
 			);
 	}
 }
 
 macro_rules! send_message {
-    ($server:expr, $client_id:expr, $channel_id:expr, $message:expr) => {
+    ($server:expr, $client_id:expr, $message:expr) => {
 		{
-			$server.send_message($client_id.into(), $channel_id, TryInto::<renet::Bytes>::try_into($message)?);
+			let packet = $crate::networking::protocol::Packet::from($message);
+			let channel = $crate::networking::channel::MessageChannel::channel(&packet);
+			$server.send_packet(&packet, channel, [$client_id.into()])?;
 		}
 	};
 }
@@ -56,30 +99,73 @@ macro_rules! send_message {
 pub(super) use send_message;
 
 macro_rules! broadcast_message {
-    ($server:expr, $channel_id:expr, $message:expr) => {
+    ($server:expr, $message:expr) => {
 		{
-			$server.broadcast_message($channel_id, TryInto::<Bytes>::try_into($message)?);
+			let packet = $crate::networking::protocol::Packet::from($message);
+			let channel = $crate::networking::channel::MessageChannel::channel(&packet);
+			let client_ids = $server.clients_id();
+			$server.send_packet(&packet, channel, client_ids)?;
 		}
 	};
 }
 
 pub(super) use broadcast_message;
 
+/// Sends the message to every player in the world.
 macro_rules! broadcast_world_message {
-    ($server:expr, $world:expr, $player_client_id_query:expr, $channel_id:expr, $message:expr) => {
+    ($server:expr, $world:expr, $player_client_id_query:expr, $message:expr) => {
 		{
-			for player in $world.players.iter() {
-				let client_id = $player_client_id_query.get(*player);
-				if let Ok(&client_id) = client_id {
-					$server.send_message(client_id.into(), $channel_id, TryInto::<Bytes>::try_into($message)?);
-				}
-			}
+			let packet = $crate::networking::protocol::Packet::from($message);
+			let channel = $crate::networking::channel::MessageChannel::channel(&packet);
+			let client_ids: Vec<u64> = $world.players
+				.iter()
+				.filter_map(|player| $player_client_id_query.get(*player).ok())
+				.map(|client_id| client_id.0)
+				.collect();
+			$server.send_packet(&packet, channel, client_ids)?;
 		}
 	};
 }
 
 pub(super) use broadcast_world_message;
 
+/// The [RenetServer], along with the [Encoder] that everything sent to clients goes through.
+#[derive(SystemParam, Deref, DerefMut)]
+pub struct NetServer<'w> {
+	#[deref]
+	server: ResMut<'w, RenetServer>,
+	encoder: ResMut<'w, Encoder>,
+	capabilities: ResMut<'w, ClientCapabilities>,
+}
+
+impl<'w> NetServer<'w> {
+	/// Sends the packet to each client, encoded with the capabilities negotiated with them. The packet is only encoded
+	/// once for each set of capabilities.
+	pub fn send_packet(&mut self, packet: &Packet, channel: Channel, client_ids: impl IntoIterator<Item = u64>) -> Result<(), NetworkError> {
+		let mut encoded: Vec<(Capabilities, Vec<Bytes>)> = vec![];
+		for client_id in client_ids {
+			let capabilities = self.capabilities.0.get(&client_id).copied().unwrap_or(Capabilities::NONE);
+			let payloads = match encoded.iter().find(|(encoded_with, _)| *encoded_with == capabilities) {
+				Some((_, payloads)) => payloads.clone(),
+				None => {
+					let payloads = self.encoder.encode(packet, channel, capabilities)?;
+					encoded.push((capabilities, payloads.clone()));
+					payloads
+				},
+			};
+			for payload in payloads {
+				self.server.send_message(client_id, channel, payload);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// The capabilities negotiated with each client that has finished the handshake. The same as the capabilities in their
+/// player's [Handshake], but kept apart so that sending doesn't need to query players.
+#[derive(Debug, Default, Resource)]
+pub struct ClientCapabilities(BevyHashMap<u64, Capabilities>);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerAddress(pub String);
 
@@ -98,11 +184,24 @@ impl Default for ServerPort {
 	}
 }
 
+impl ServerPort {
+	/// The default port for status queries, right after the default game port.
+	pub fn default_query() -> Self {
+		Self(Self::default().0 + 1)
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Resource)]
 pub struct ServerConfig {
 	pub address: ServerAddress,
 	pub port: ServerPort,
 	pub max_clients: usize,
+	/// The message of the day, shown in server browsers.
+	pub motd: String,
+	/// The UDP port that status queries are answered on, or [None] to not answer them.
+	pub query_port: Option<ServerPort>,
+	/// Where worlds are saved, relative to the working directory.
+	pub save_dir: PathBuf,
 }
 
 impl Default for ServerConfig {
@@ -111,6 +210,9 @@ impl Default for ServerConfig {
 			address: Default::default(),
 			port: Default::default(),
 			max_clients: 16,
+			motd: "A Botanica server".to_string(),
+			query_port: Some(ServerPort::default_query()),
+			save_dir: PathBuf::from(save::SAVE_DIR),
 		}
 	}
 }
@@ -118,46 +220,97 @@ impl Default for ServerConfig {
 #[derive(Debug, Deref, Default, Clone, Resource)]
 pub struct Players(pub BevyHashMap<ClientId, Entity>);
 
+/// Everything about a player in a world that their [PlayerRecord] is made from.
+pub type PlayerRecordQuery<'w, 's> = Query<'w, 's, (&'static PlayerData, &'static Position, &'static Inventory, &'static PlayerStats, &'static FirstJoined), With<Player>>;
+
+/// Returns the player's username and what the world they're in should remember about them, or [None] if they aren't in
+/// a world.
+pub fn player_record(player_record_query: &PlayerRecordQuery, player_entity: Entity) -> Option<(Username, PlayerRecord)> {
+	let (player_data, position, inventory, stats, first_joined) = player_record_query.get(player_entity).ok()?;
+	Some((player_data.username.clone(), PlayerRecord {
+		position: Some(*position),
+		inventory: inventory.clone(),
+		stats: *stats,
+		first_joined: first_joined.0,
+	}))
+}
+
+/// A socket that was bound before the server started, which the server uses instead of binding its own. The integrated
+/// server uses this so that the client knows the port before the server has finished loading.
+#[derive(Debug, Resource)]
+pub struct ServerSocket(pub UdpSocket);
+
 fn setup(
 	server_config: Res<ServerConfig>,
+	server_socket: Option<Res<ServerSocket>>,
+	memory_network: Option<Res<MemoryNetwork>>,
 	mut commands: Commands,
 	mut next_state: ResMut<NextState<GameState>>,
 ) {
-	let connection_config = ConnectionConfig::default();
+	let connection_config = channel::connection_config();
+	let server = RenetServer::new(connection_config);
 	
-	let address = format!("{}:{}", server_config.address.0, server_config.port.0);
+	if let Some(memory_network) = memory_network {
+		let transport = MemoryServerTransport::new(memory_network.clone(), server_config.max_clients);
+		
+		commands.insert_resource(server);
+		commands.insert_resource(ServerTransport::Memory(transport));
+		println!("Loaded server! Listening in memory");
+		next_state.set(GameState::ServerLoaded);
+		return
+	}
+	
+	let socket = match server_socket {
+		Some(server_socket) => {
+			commands.remove_resource::<ServerSocket>();
+			server_socket.0.try_clone().expect("Failed to take the server socket")
+		},
+		None => {
+			let address = format!("{}:{}", server_config.address.0, server_config.port.0);
+			UdpSocket::bind(&address).expect(&format!("Failed to bind to address \"{}\"", address))
+		},
+	};
+	let address: SocketAddr = socket.local_addr().expect("Failed to get the address of the server socket");
 	
 	let authentication = ServerAuthentication::Unsecure;
 	
 	let server_config = renet::transport::ServerConfig {
 		max_clients: server_config.max_clients,
 		protocol_id: protocol::PROTOCOL_ID,
-		public_addr: address.parse::<SocketAddr>().expect(&format!("Failed to parse address \"{}\"", address)),
+		public_addr: address,
 		authentication,
 	};
 	
-	let socket = UdpSocket::bind(&address).expect(&format!("Failed to bind to address \"{}\"", address));
 	let current_time = time_since_epoch();
 	
-	let server = RenetServer::new(connection_config);
 	let transport = NetcodeServerTransport::new(current_time, server_config, socket).expect("Failed to create transport");
 	
 	commands.insert_resource(server);
-	commands.insert_resource(transport);
+	commands.insert_resource(ServerTransport::Netcode(transport));
 	println!("Loaded server! Listening @ {}", address);
 	next_state.set(GameState::ServerLoaded);
 }
 
+/// The [Reassembler] of each connected client.
+#[derive(Debug, Default, Resource)]
+pub struct Reassemblers(BevyHashMap<u64, Reassembler>);
+
 fn server(
-	mut server: ResMut<RenetServer>,
-	transport: Res<NetcodeServerTransport>,
+	server_config: Res<ServerConfig>,
+	mut server: NetServer,
+	mut reassemblers: ResMut<Reassemblers>,
+	transport: Res<ServerTransport>,
 	mut commands: Commands,
 	mut worlds: ResMut<ServerGameWorlds>,
 	mut players: ResMut<Players>,
 	player_data_query: Query<&PlayerData, With<Player>>,
 	player_world_id_query: Query<&WorldId, With<Player>>,
+	player_record_query: PlayerRecordQuery,
+	player_client_id_query: Query<&ClientId, With<Player>>,
 	mut ev_server: EventReader<ServerEvent>,
 	raw_tile_ids: Res<RawTileIds>,
+	raw_item_ids: Res<RawItemIds>,
+	mut player_stats: ResMut<PlayerNetStats>,
 ) -> Result<(), NetworkError> {
 	for event in ev_server.iter() {
 		match event {
@@ -171,39 +324,52 @@ fn server(
 					};
 					players.0.insert(ClientId(*id), commands.spawn(player_bundle).id());
 					println!("Player {} (ID {:X}) connected", username, id);
-					send_message!(server, *id, DefaultChannel::ReliableOrdered, ServerMessage::RawTileIds(raw_tile_ids.clone()));
+					send_message!(server, *id, ServerMessage::RawTileIds(raw_tile_ids.clone()));
+					send_message!(server, *id, ServerMessage::RawItemIds(raw_item_ids.clone()));
 				} else {
 					println!("Player (ID {:X}) attempted to join, but no user data was sent!", id);
-					send_message!(server, *id, DefaultChannel::ReliableOrdered, ServerMessage::Disconnect(protocol::DisconnectReason::EmptyUserdata));
+					send_message!(server, *id, ServerMessage::Disconnect(protocol::DisconnectReason::EmptyUserdata));
 					server.disconnect(*id);
 				}
 			},
 			ServerEvent::ClientDisconnected { client_id: id, reason } => {
+				reassemblers.0.remove(id);
+				server.capabilities.0.remove(id);
+				player_stats.remove(ClientId(*id));
 				let player = players.0.remove(&ClientId(*id));
 				if let Some(player_entity) = player {
-					let player_data = player_data_query.get(player_entity)?;
-					println!("Player {} (ID {:X}) disconnected: {}", player_data.username, id, reason);
-					let world = worlds.get_world_mut(player_world_id_query.get(player_entity)?.as_str());
+					if let Ok(player_data) = player_data_query.get(player_entity) {
+						println!("Player {} (ID {:X}) disconnected: {}", player_data.username, id, reason);
+					}
+					let world = player_world_id_query
+						.get(player_entity)
+						.ok()
+						.and_then(|world_id| worlds.get_world_mut(world_id.as_str()));
 					if let Some(world) = world {
-						let index = world.players.iter().position(|x| *x == player_entity);
-						if let Some(index) = index {
-							world.players.remove(index);
+						// the player still has to go, even if their world couldn't be saved
+						if let Err(err) = remove_from_world(&mut server, &server_config, world, ClientId(*id), player_entity, &player_record_query, &player_client_id_query, &raw_tile_ids, &raw_item_ids) {
+							eprintln!("Failed to take player (ID {:X}) out of world {}: {}", id, world.id.as_str(), err);
 						}
 					}
 					
 					commands.entity(player_entity).despawn_recursive();
+					broadcast_message!(server, ServerMessage::RosterLeave(ClientId(*id)));
 				}
 			},
 		}
 	}
 	
 	for client_id in server.clients_id() {
-		for channel_id in 0..=2 {
-			while let Some(buf) = server.receive_message(client_id, channel_id) {
-				let packet = utils::deserialize_be::<Packet>(&buf);
+		for channel in Channel::ALL {
+			while let Some(buf) = server.receive_message(client_id, channel) {
+				let packet = reassemblers.0
+					.entry(client_id)
+					.or_default()
+					.decode(&buf);
+				player_stats.get_mut(ClientId(client_id)).record_received(buf.len(), packet.as_ref().ok().and_then(Option::as_ref));
 				
 				// allow processing all packets regardless of error
-				if let Ok(packet) = packet {
+				if let Ok(Some(packet)) = packet {
 					match packet {
 						Packet::ClientMessage(_) => {
 							commands.spawn(ClientMessageBundle { id: ClientId(client_id), packet });
@@ -214,7 +380,13 @@ fn server(
 						_ => warn!("Received incorrect/server message from client ({}): {:?}", client_id, packet),
 					}
 				} else if let Err(err) = packet {
-					eprintln!("{err}");
+					match handshake::peek_join_version(&buf) {
+						Some(join_version) if !PROTOCOL_VER.is_compatible_with(join_version.protocol_ver()) => {
+							println!("Client (ID {:X}) is using protocol {} instead of {}", client_id, join_version.protocol_ver(), PROTOCOL_VER);
+							deny_protocol(&mut server, client_id, join_version)?;
+						},
+						_ => eprintln!("{err}"),
+					}
 				}
 			}
 		}
@@ -223,16 +395,78 @@ fn server(
 	Ok(())
 }
 
+/// Tells a client whose join request couldn't be decoded which protocol version it needs, and disconnects it.
+fn deny_protocol(server: &mut NetServer, client_id: u64, join_version: JoinVersion) -> Result<(), NetworkError> {
+	let reason = protocol::DisconnectReason::ProtocolReject {
+		required_protocol_ver: PROTOCOL_VER,
+		required_version_string: VERSION_STRING.to_string(),
+	};
+	match join_version {
+		// clients from before envelopes read packets as they are, and only ever listened on the control channel
+		JoinVersion::Legacy(_) => {
+			let deny = utils::serialize_be(&Packet::from(ServerResponse::JoinDeny(reason)))?;
+			server.send_message(client_id, Channel::Control, deny);
+		},
+		JoinVersion::Enveloped(_) => send_message!(server, client_id, ServerResponse::JoinDeny(reason)),
+	}
+	server.disconnect(client_id);
+	Ok(())
+}
+
+/// Returns what every client should know about the player.
+fn roster_entry(client_id: ClientId, data: &PlayerData, world_id: Option<&WorldId>, nicknames: &Nicknames) -> RosterEntry {
+	RosterEntry {
+		client_id,
+		data: data.clone(),
+		nickname: nicknames.get(&data.username).cloned(),
+		world: world_id.cloned(),
+	}
+}
+
+/// Adds the time since the last frame to the play time of every player in a world.
+fn track_play_time(
+	time: Res<Time>,
+	mut stats_query: Query<&mut PlayerStats, (With<Player>, With<WorldId>)>,
+) {
+	for mut stats in stats_query.iter_mut() {
+		stats.play_time += time.delta();
+	}
+}
+
+/// Copies what renet knows about each connection, and which world each player is in, into the [PlayerNetStats].
+fn update_player_net_stats(
+	server: Res<RenetServer>,
+	players: Res<Players>,
+	player_world_id_query: Query<Option<&WorldId>, With<Player>>,
+	mut player_stats: ResMut<PlayerNetStats>,
+) {
+	for (client_id, player_entity) in players.iter() {
+		let stats = player_stats.get_mut(*client_id);
+		if let Ok(network_info) = server.network_info(client_id.0) {
+			stats.rtt = network_info.rtt;
+			stats.packet_loss = network_info.packet_loss;
+			stats.bytes_sent_per_second = network_info.bytes_sent_per_second;
+			stats.bytes_received_per_second = network_info.bytes_received_per_second;
+		}
+		stats.world = player_world_id_query.get(*player_entity).ok().flatten().cloned();
+	}
+}
+
 fn receive_message(
+	server_config: Res<ServerConfig>,
 	message_query: Query<(Entity, &ClientId, &Packet)>,
-	mut server: ResMut<RenetServer>,
+	mut server: NetServer,
 	mut worlds: ResMut<ServerGameWorlds>,
 	players: Res<Players>,
 	player_query: Query<(&PlayerData, Option<&WorldId>), With<Player>>,
-	player_world_id_query: Query<&WorldId, With<Player>>,
+	player_record_query: PlayerRecordQuery,
 	player_client_id_query: Query<&ClientId, With<Player>>,
-	raw_tile_ids: Res<RawTileIds>,
+	tile_defs: TileDefs,
+	raw_item_ids: Res<RawItemIds>,
+	roster_query: Query<(&ClientId, &PlayerData, Option<&WorldId>), (With<Player>, With<Handshake>)>,
 	mut player_stats: ResMut<PlayerNetStats>,
+	ping_timer: Res<PingTimer>,
+	nicknames: Res<Nicknames>,
 	mut commands: Commands,
 ) -> Result<(), NetworkError> {
 	for (entity, client_id, packet) in message_query.iter() {
@@ -247,18 +481,37 @@ fn receive_message(
 		if let Packet::ClientMessage(message) = packet {
 			match message {
 				ClientMessage::Ping { timestamp } => {
-					send_message!(server, client_id, DefaultChannel::ReliableUnordered, ServerResponse::PingAck { timestamp: *timestamp });
-					
-					let now = time_since_epoch().as_millis();
-					let stats = player_stats.get_mut(*client_id);
-					stats.ping = now - timestamp;
+					// the timestamp is on the client's clock, so only the client can make sense of it
+					send_message!(server, client_id, ServerResponse::PingAck { timestamp: *timestamp });
 				},
-				ClientMessage::JoinRequest { protocol_ver } => {
-					if *protocol_ver != PROTOCOL_VER {
-						send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerResponse::JoinDeny(protocol::DisconnectReason::ProtocolReject { required_protocol_ver: PROTOCOL_VER, required_version_string: VERSION_STRING.to_string() }));
-						server.disconnect(client_id.0);
-					} else {
-						send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerResponse::JoinAccept);
+				ClientMessage::JoinRequest(handshake) => {
+					match Handshake::local().negotiate(handshake) {
+						Ok(negotiated) => {
+							println!("Client (ID {:X}) is running {} (protocol {}, capabilities: {})", client_id.0, handshake.version_string, handshake.protocol_ver, handshake.capabilities);
+							let Some(&player_entity) = players.get(client_id) else {
+								continue
+							};
+							commands.entity(player_entity).insert(negotiated.clone());
+							server.capabilities.0.insert(client_id.0, negotiated.capabilities);
+							send_message!(server, client_id, ServerResponse::JoinAccept(negotiated));
+							
+							// tell the new player about everyone, and everyone who has already joined about the new player
+							let (player_data, world_id) = player_query.get(player_entity)?;
+							let entry = roster_entry(*client_id, player_data, world_id, &nicknames);
+							let mut roster: Vec<RosterEntry> = roster_query
+								.iter()
+								.map(|(other_client_id, other_data, other_world_id)| roster_entry(*other_client_id, other_data, other_world_id, &nicknames))
+								.collect();
+							for other in roster.iter() {
+								send_message!(server, other.client_id, ServerMessage::RosterJoin(entry.clone()));
+							}
+							roster.push(entry);
+							send_message!(server, client_id, ServerMessage::Roster(roster));
+						},
+						Err(reason) => {
+							send_message!(server, client_id, ServerResponse::JoinDeny(reason));
+							server.disconnect(client_id.0);
+						},
 					}
 				},
 				ClientMessage::PlayerPosition(position) => {
@@ -267,25 +520,29 @@ fn receive_message(
 						continue
 					}
 					
-					let world = worlds.get_world(player_world_id_query.get(player_entity.unwrap().clone())?.as_str());
+					let Some(world_id) = player_query.get(*player_entity.unwrap())?.1 else {
+						continue
+					};
+					let world = worlds.get_world(world_id.as_str());
 					if world.is_none() {
 						continue
 					}
 					
-					broadcast_world_message!(server, world.unwrap(), player_client_id_query, DefaultChannel::Unreliable, ServerMessage::PlayerPosition(*client_id, *position));
+					commands.entity(*player_entity.unwrap()).insert(*position);
+					broadcast_world_message!(server, world.unwrap(), player_client_id_query, ServerMessage::PlayerPosition(*client_id, *position));
 				},
 				ClientMessage::EnterWorldRequest(world_name) => {
 					let world_name = utils::sanitize::sanitize_alphanumeric_dash(world_name);
 					if world_name.len() == 0 {
-						send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerResponse::EnterWorldDeny(WorldDenyReason::InvalidWorldName));
+						send_message!(server, client_id, ServerResponse::EnterWorldDeny(WorldDenyReason::InvalidWorldName));
 						continue
 					}
 					
-					let world = worlds.get_or_gen_world_mut(world_name.as_str(), &*raw_tile_ids)?;
+					let world = worlds.get_or_gen_world_mut(&server_config.save_dir, world_name.as_str(), &tile_defs, &*raw_item_ids)?;
 					
 					let player_entity = players.get(client_id);
 					if player_entity.is_none() {
-						send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerMessage::Disconnect(protocol::DisconnectReason::PlayerNonexistent));
+						send_message!(server, client_id, ServerMessage::Disconnect(protocol::DisconnectReason::PlayerNonexistent));
 						server.disconnect(client_id.0);
 						continue
 					}
@@ -294,21 +551,39 @@ fn receive_message(
 					// check if this player is banned & kick 'em if they are
 					if world.bans.contains_key(&player.0.username) {
 						let ban = world.bans.get(&player.0.username).unwrap();
-						send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerResponse::EnterWorldDeny(WorldDenyReason::Banned(ban.reason().to_string(), ban.until())));
+						send_message!(server, client_id, ServerResponse::EnterWorldDeny(WorldDenyReason::Banned(ban.reason().to_string(), ban.until())));
 						// todo: unload world if no other players are present
 						continue
 					}
 					
+					// switching worlds leaves the old one first, which also makes entering the same world again harmless
+					if let Some(current_world) = player.1.and_then(|world_id| worlds.get_world_mut(world_id.as_str())) {
+						remove_from_world(&mut server, &server_config, current_world, *client_id, *player_entity.unwrap(), &player_record_query, &player_client_id_query, tile_defs.raw_tile_ids(), &raw_item_ids)?;
+					}
+					let world = worlds.get_or_gen_world_mut(&server_config.save_dir, world_name.as_str(), &tile_defs, &*raw_item_ids)?;
+					
 					// add player to world
 					if !world.players.contains(player_entity.unwrap()) {
 						world.players.push(player_entity.unwrap().clone());
 					}
 					
-					commands.entity(player_entity.unwrap().clone()).insert(world.id.clone());
+					// players come back where they left, with what they had
+					let record = world.records.remove(&player.0.username).unwrap_or_else(PlayerRecord::first_visit);
+					let position = record.position.unwrap_or(world.spawnpoint);
+					let inventory = record.inventory;
+					commands.entity(player_entity.unwrap().clone()).insert((world.id.clone(), position, inventory.clone(), SelectedSlot::default(), record.stats, FirstJoined(record.first_joined)));
 					
-					send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerResponse::EnterWorldAccept(world.id.clone()));
-					send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerMessage::WorldTiles(world.tiles.clone()));
-					broadcast_world_message!(server, world, player_client_id_query, DefaultChannel::ReliableOrdered, ServerMessage::PlayerJoin(*client_id, player.0.clone(), world.spawnpoint));
+					send_message!(server, client_id, ServerResponse::EnterWorldAccept(world.id.clone()));
+					for batch in TileUpdateBatch::from_tiles(&world.tiles) {
+						send_message!(server, client_id, ServerMessage::TileUpdateBatch(batch));
+					}
+					for (chunk_pos, tile_entities) in world.tile_entities.chunks() {
+						send_message!(server, client_id, ServerMessage::TileEntities(*chunk_pos, tile_entities.clone()));
+					}
+					send_message!(server, client_id, ServerMessage::Inventory(inventory));
+					send_message!(server, client_id, ServerMessage::WorldTime(world.time));
+					broadcast_world_message!(server, world, player_client_id_query, ServerMessage::PlayerJoin(*client_id, player.0.clone(), position));
+					broadcast_message!(server, ServerMessage::RosterWorld(*client_id, Some(world.id.clone())));
 				},
 				ClientMessage::ChatMessage(target, content) => {
 					let player = player_query.get(*players.0.get(client_id).unwrap())?;
@@ -317,14 +592,32 @@ fn receive_message(
 						source: Source::Player(*client_id, player.1.cloned()),
 						target: target.clone(),
 					};
-					send_message!(server, client_id, DefaultChannel::ReliableOrdered, ServerMessage::ChatMessage(chat_message)); // todo: broadcast chat message to players in target range
+					send_message!(server, client_id, ServerMessage::ChatMessage(chat_message)); // todo: broadcast chat message to players in target range
+				},
+				ClientMessage::LeaveWorld => {
+					let Some(&player_entity) = players.get(client_id) else {
+						continue
+					};
+					let world = player_query
+						.get(player_entity)?
+						.1
+						.and_then(|world_id| worlds.get_world_mut(world_id.as_str()));
+					if let Some(world) = world {
+						remove_from_world(&mut server, &server_config, world, *client_id, player_entity, &player_record_query, &player_client_id_query, tile_defs.raw_tile_ids(), &raw_item_ids)?;
+						broadcast_message!(server, ServerMessage::RosterWorld(*client_id, None));
+					}
+					
+					// the world has their record now
+					commands.entity(player_entity).remove::<(WorldId, Position, Inventory, SelectedSlot, PlayerStats, FirstJoined)>();
+					send_message!(server, client_id, ServerResponse::LeaveWorldAccept);
 				},
+				// handled by the systems in their respective modules
+				ClientMessage::BreakTile(..) | ClientMessage::PlaceTile(..) | ClientMessage::InventoryAction(_) | ClientMessage::CraftRequest(_) | ClientMessage::NickRequest(_) => {},
 			}
 		} else if let Packet::ClientResponse(response) = packet {
 			match response {
 				ClientResponse::PingAck { timestamp } => {
-					let now = time_since_epoch().as_millis();
-					player_stats.get_mut(*client_id).ping = now - timestamp;
+					player_stats.get_mut(*client_id).record_ping(ping_timer.round_trip_time(*timestamp));
 				},
 			}
 		}
@@ -333,8 +626,50 @@ fn receive_message(
 	Ok(())
 }
 
+/// Takes the player out of the world: the world remembers their record and is saved, and the players still in it are
+/// told that they've left. Leaves the player's components alone.
+pub fn remove_from_world(
+	server: &mut NetServer,
+	server_config: &ServerConfig,
+	world: &mut ServerGameWorld,
+	client_id: ClientId,
+	player_entity: Entity,
+	player_record_query: &PlayerRecordQuery,
+	player_client_id_query: &Query<&ClientId, With<Player>>,
+	raw_tile_ids: &RawTileIds,
+	raw_item_ids: &RawItemIds,
+) -> Result<(), NetworkError> {
+	let index = world.players.iter().position(|x| *x == player_entity);
+	if let Some(index) = index {
+		world.players.remove(index);
+	}
+	
+	// remember where they were and what they had for when they come back
+	if let Some((username, record)) = player_record(player_record_query, player_entity) {
+		world.records.insert(username, record);
+	}
+	
+	broadcast_world_message!(server, world, player_client_id_query, ServerMessage::PlayerLeave(client_id));
+	save_server_world(&server_config.save_dir, world, player_record_query, raw_tile_ids, raw_item_ids)?;
+	Ok(())
+}
+
+/// Saves the world, including the records of the players that are still in it.
+pub fn save_server_world(
+	save_dir: &Path,
+	world: &ServerGameWorld,
+	player_record_query: &PlayerRecordQuery,
+	raw_tile_ids: &RawTileIds,
+	raw_item_ids: &RawItemIds,
+) -> Result<(), SaveError> {
+	let online_records = world.players
+		.iter()
+		.filter_map(|player| player_record(player_record_query, *player));
+	save::save_world(save_dir, world.id.as_str(), &world.to_save(raw_item_ids, online_records), raw_tile_ids)
+}
+
 pub fn send_chat(
-	mut server: ResMut<RenetServer>,
+	mut server: NetServer,
 	source: Source,
 	target: Target,
 	message: String,
@@ -349,17 +684,17 @@ pub fn send_chat(
 	});
 	match target {
 		Target::Player(id) => {
-			send_message!(server, id.0, DefaultChannel::ReliableOrdered, message);
+			send_message!(server, id.0, message);
 		}
 		Target::Players(players) => {
 			for id in players {
-				send_message!(server, id.0, DefaultChannel::ReliableOrdered, message.clone());
+				send_message!(server, id.0, message.clone());
 			}
 		}
 		Target::World => { // todo: send chat message to world
 		}
 		Target::All => {
-			broadcast_message!(server, DefaultChannel::ReliableOrdered, message);
+			broadcast_message!(server, message);
 		}
 	}
 	
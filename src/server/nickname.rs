@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::creature::player::Player;
+use crate::networking::error::NetworkError;
+use crate::networking::handshake::Handshake;
+use crate::networking::nickname::{nickname_key, validate_nickname, NickDenyReason};
+use crate::networking::protocol::{ClientId, ClientMessage, Packet, PlayerData, ServerMessage, ServerResponse};
+use crate::save::error::SaveError;
+use crate::Username;
+
+use super::networking::{broadcast_message, send_message, NetServer, Players, ServerConfig};
+
+/// The file in the save directory that nicknames are kept in.
+const NICKNAMES_FILE: &'static str = "nicknames.ron";
+
+/// Every player's nickname, by username. Kept across restarts in [NICKNAMES_FILE].
+#[derive(Debug, Default, Clone, Resource)]
+pub struct Nicknames(BTreeMap<String, String>);
+
+impl Nicknames {
+	fn path(save_dir: &Path) -> PathBuf {
+		let mut path = std::env::current_dir().unwrap();
+		path.push(save_dir);
+		path.push(NICKNAMES_FILE);
+		path
+	}
+	
+	/// Reads the nicknames from the save directory. A missing file has no nicknames.
+	pub fn load(save_dir: &Path) -> Result<Self, SaveError> {
+		let path = Self::path(save_dir);
+		if !path.exists() {
+			return Ok(Self::default())
+		}
+		
+		Ok(Self(ron::from_str(&std::fs::read_to_string(path)?)?))
+	}
+	
+	/// Writes the nicknames to the save directory, creating it if needed.
+	pub fn save(&self, save_dir: &Path) -> Result<(), SaveError> {
+		let path = Self::path(save_dir);
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		
+		std::fs::write(path, ron::ser::to_string_pretty(&self.0, ron::ser::PrettyConfig::default())?)?;
+		Ok(())
+	}
+	
+	pub fn get(&self, username: &Username) -> Option<&String> {
+		self.0.get(&username.0)
+	}
+	
+	/// Sets the player's nickname, or clears it if [None].
+	pub fn set(&mut self, username: &Username, nickname: Option<String>) {
+		match nickname {
+			Some(nickname) => self.0.insert(username.0.clone(), nickname),
+			None => self.0.remove(&username.0),
+		};
+	}
+	
+	/// Returns whether another player has the nickname, including players that aren't online.
+	fn is_taken_by_other(&self, username: &Username, key: &str) -> bool {
+		self.0
+			.iter()
+			.any(|(other, nickname)| *other != username.0 && nickname_key(nickname) == key)
+	}
+}
+
+/// Loads the nicknames. A broken nicknames file isn't worth stopping the server over, so it starts without any instead.
+pub fn setup_nicknames(
+	server_config: Res<ServerConfig>,
+	mut commands: Commands,
+) {
+	let nicknames = Nicknames::load(&server_config.save_dir).unwrap_or_else(|err| {
+		eprintln!("Failed to load the nicknames: {}", err);
+		Nicknames::default()
+	});
+	commands.insert_resource(nicknames);
+}
+
+/// Handles [ClientMessage::NickRequest]s.
+///
+/// A nickname must pass [validate_nickname], and can't look like (ignoring formatting and case) another player's
+/// nickname, or the username of another player who is online. Accepted nicknames are saved right away.
+pub fn change_nickname(
+	server_config: Res<ServerConfig>,
+	message_query: Query<(&ClientId, &Packet)>,
+	mut server: NetServer,
+	players: Res<Players>,
+	player_query: Query<(&ClientId, &PlayerData), (With<Player>, With<Handshake>)>,
+	mut nicknames: ResMut<Nicknames>,
+) -> Result<(), NetworkError> {
+	for (client_id, packet) in message_query.iter() {
+		let Packet::ClientMessage(ClientMessage::NickRequest(nickname)) = packet else {
+			continue
+		};
+		
+		let Some(&player_entity) = players.get(client_id) else {
+			continue
+		};
+		
+		// only players that have joined are in everyone's roster
+		let Ok((_, player_data)) = player_query.get(player_entity) else {
+			continue
+		};
+		
+		let nickname = nickname.trim();
+		let nickname = if nickname.is_empty() {
+			None
+		} else {
+			if let Err(reason) = validate_nickname(nickname) {
+				send_message!(server, client_id, ServerResponse::NickDeny(reason));
+				continue
+			}
+			
+			let key = nickname_key(nickname);
+			let taken_by_online = player_query
+				.iter()
+				.any(|(other_client_id, other_data)| other_client_id != client_id && nickname_key(&other_data.username.0) == key);
+			if taken_by_online || nicknames.is_taken_by_other(&player_data.username, &key) {
+				send_message!(server, client_id, ServerResponse::NickDeny(NickDenyReason::Taken));
+				continue
+			}
+			
+			Some(nickname.to_string())
+		};
+		
+		if nicknames.get(&player_data.username) == nickname.as_ref() {
+			continue
+		}
+		
+		println!("Player {} (ID {:X}) is now nicknamed {:?}", player_data.username, client_id.0, nickname);
+		nicknames.set(&player_data.username, nickname.clone());
+		nicknames.save(&server_config.save_dir)?;
+		broadcast_message!(server, ServerMessage::PlayerNick(*client_id, nickname.unwrap_or_default()));
+	}
+	
+	Ok(())
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::creature::player::Player;
+use crate::networking::channel::MessageChannel;
+use crate::networking::error::NetworkError;
+use crate::networking::handshake::Handshake;
+use crate::networking::protocol::{ClientId, Packet, ServerMessage};
+use crate::networking::stats::PlayerNetStats;
+
+use super::networking::{send_message, NetServer};
+
+/// How often (in seconds) every player is pinged.
+pub const PING_INTERVAL: f64 = 1.0;
+
+/// Decides when the players are next pinged, and is the clock ping timestamps are measured on.
+///
+/// Timestamps are microseconds since the server started, so the round-trip time only ever depends on the server's own
+/// clock.
+#[derive(Resource)]
+pub struct PingTimer {
+	timer: Timer,
+	epoch: Instant,
+}
+
+impl Default for PingTimer {
+	fn default() -> Self {
+		Self {
+			timer: Timer::new(Duration::from_secs_f64(PING_INTERVAL), TimerMode::Repeating),
+			epoch: Instant::now(),
+		}
+	}
+}
+
+impl PingTimer {
+	/// Returns the timestamp to send in a ping right now.
+	pub fn timestamp(&self) -> u128 {
+		self.epoch.elapsed().as_micros()
+	}
+	
+	/// Returns how long ago the ping with the timestamp was sent.
+	pub fn round_trip_time(&self, timestamp: u128) -> Duration {
+		let micros = self.timestamp().saturating_sub(timestamp);
+		Duration::from_micros(micros.try_into().unwrap_or(u64::MAX))
+	}
+}
+
+/// Pings every player that has finished the handshake, and tells them how the last round of pings went. Players whose
+/// first ping hasn't come back yet are left out, rather than reported as having no latency at all.
+pub fn ping_players(
+	time: Res<Time>,
+	mut ping_timer: ResMut<PingTimer>,
+	mut server: NetServer,
+	joined_query: Query<&ClientId, (With<Player>, With<Handshake>)>,
+	player_stats: Res<PlayerNetStats>,
+) -> Result<(), NetworkError> {
+	ping_timer.timer.tick(time.delta());
+	if !ping_timer.timer.just_finished() || joined_query.is_empty() {
+		return Ok(())
+	}
+	
+	let timestamp = ping_timer.timestamp();
+	for client_id in joined_query.iter() {
+		send_message!(server, *client_id, ServerMessage::Ping { timestamp });
+	}
+	
+	let pings = joined_query
+		.iter()
+		.filter_map(|client_id| {
+			let stats = player_stats.get(*client_id)?;
+			(!stats.smoothed_rtt.is_zero()).then(|| (*client_id, stats.smoothed_rtt.as_millis().try_into().unwrap_or(u32::MAX)))
+		})
+		.collect();
+	let packet = Packet::from(ServerMessage::PlayerPings(pings));
+	server.send_packet(&packet, packet.channel(), joined_query.iter().map(|client_id| client_id.0))?;
+	
+	Ok(())
+}
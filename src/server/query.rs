@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use bevy::prelude::*;
+
+use crate::networking::protocol::PROTOCOL_VER;
+use crate::networking::query::{self, QueryError, ServerStatus, WorldStatus, REQUEST_SIZE};
+use crate::save;
+use crate::world::ServerGameWorlds;
+use crate::VERSION_STRING;
+
+use super::networking::{Players, ServerConfig};
+
+/// The most status queries answered per frame, so that a flood of them can't stall the server.
+const MAX_QUERIES_PER_FRAME: usize = 32;
+
+/// The socket that status queries are answered on. Only exists if the server has a query port.
+#[derive(Debug, Resource)]
+pub struct QuerySocket(UdpSocket);
+
+/// Opens the query socket. A server that can't open it still runs, it just can't be queried.
+pub fn setup_query(
+	server_config: Res<ServerConfig>,
+	mut commands: Commands,
+) {
+	let Some(port) = server_config.query_port else {
+		return
+	};
+	
+	let address = format!("{}:{}", server_config.address.0, port.0);
+	let socket = address
+		.parse::<SocketAddr>()
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+		.and_then(UdpSocket::bind)
+		.and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+	
+	match socket {
+		Ok(socket) => {
+			println!("Answering status queries @ {}", address);
+			commands.insert_resource(QuerySocket(socket));
+		},
+		Err(err) => eprintln!("Failed to open the query socket @ {}: {}", address, err),
+	}
+}
+
+/// Answers every pending status query.
+pub fn answer_queries(
+	socket: Option<Res<QuerySocket>>,
+	server_config: Res<ServerConfig>,
+	players: Res<Players>,
+	worlds: Res<ServerGameWorlds>,
+) -> Result<(), QueryError> {
+	let Some(socket) = socket else {
+		return Ok(())
+	};
+	
+	let mut buf = [0u8; REQUEST_SIZE * 2];
+	let mut status: Option<ServerStatus> = None;
+	
+	for _ in 0..MAX_QUERIES_PER_FRAME {
+		let (len, sender) = match socket.0.recv_from(&mut buf) {
+			Ok(received) => received,
+			Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+			Err(err) => return Err(err.into()),
+		};
+		
+		let Some(token) = query::decode_request(&buf[..len]) else {
+			continue
+		};
+		
+		// only build the status once per frame, since it reads the save directory
+		let status = status.get_or_insert_with(|| server_status(&server_config, &players, &worlds));
+		socket.0.send_to(&query::encode_response(token, status.clone())?, sender)?;
+	}
+	
+	Ok(())
+}
+
+fn server_status(server_config: &ServerConfig, players: &Players, worlds: &ServerGameWorlds) -> ServerStatus {
+	// saved worlds that aren't loaded have nobody in them
+	let mut world_players: BTreeMap<String, u32> = save::world_names(&server_config.save_dir)
+		.into_iter()
+		.map(|name| (name, 0))
+		.collect();
+	for (name, world) in worlds.iter() {
+		world_players.insert(name.clone(), world.players.len() as u32);
+	}
+	
+	ServerStatus {
+		motd: server_config.motd.clone(),
+		version_string: VERSION_STRING.to_string(),
+		protocol_ver: PROTOCOL_VER,
+		players: players.len() as u32,
+		max_clients: server_config.max_clients as u32,
+		worlds: world_players
+			.into_iter()
+			.map(|(name, players)| WorldStatus { name, players })
+			.collect(),
+	}
+}
@@ -0,0 +1,91 @@
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+
+use crate::asset::tile::TileDef;
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::raw_id::tile::RawTileIds;
+use crate::registry::tile::{TileDefs, TileRegistry};
+use crate::tile::{TileData, WorldTile};
+use crate::tile::layer::TileLayer;
+use crate::world::{DAY_LENGTH, ServerGameWorld, WorldId, WorldTime};
+use crate::{Position, TilePos};
+
+/// The namespace of every tile made by [TestTiles].
+pub const NAMESPACE: &'static str = "test";
+
+/// Tile definitions for tests, without an asset server. The definitions are written in RON just like the ones in the
+/// assets, and each tile's raw ID is its index in the list it was made from.
+pub struct TestTiles {
+	world: World,
+	tile_defs: SystemState<TileDefs<'static>>,
+}
+
+impl TestTiles {
+	pub fn new(defs: &[(&str, &str)]) -> Self {
+		let mut registry = TileRegistry::default();
+		let mut assets = Assets::<TileDef>::default();
+		let mut raw_tile_ids = RawTileIds::default();
+		for (index, (path, def)) in defs.iter().enumerate() {
+			let id = Identifier::from_str(NAMESPACE, path);
+			let def: TileDef = ron::from_str(def).expect("test tile definition should be valid");
+			registry.register(assets.add(def), id.clone());
+			raw_tile_ids.register(id, RawId(index as i32));
+		}
+		
+		let mut world = World::new();
+		world.insert_resource(registry);
+		world.insert_resource(assets);
+		world.insert_resource(raw_tile_ids);
+		let tile_defs = SystemState::new(&mut world);
+		
+		Self {
+			world,
+			tile_defs,
+		}
+	}
+	
+	pub fn id(path: &str) -> Identifier {
+		Identifier::from_str(NAMESPACE, path)
+	}
+	
+	/// Returns the tile with the path, holding the data.
+	pub fn tile(&self, path: &str, data: u8) -> WorldTile {
+		let raw_id = self.world
+			.resource::<RawTileIds>()
+			.get_raw_id(&Self::id(path))
+			.expect("test tile should exist");
+		WorldTile(raw_id, TileData(data))
+	}
+	
+	pub fn defs(&mut self) -> TileDefs<'_> {
+		self.tile_defs.get(&self.world)
+	}
+	
+	/// Moves the tile definitions into the app, for tests that run systems.
+	pub fn add_to(mut self, app: &mut App) {
+		app
+			.insert_resource(self.world.remove_resource::<TileRegistry>().unwrap())
+			.insert_resource(self.world.remove_resource::<Assets<TileDef>>().unwrap())
+			.insert_resource(self.world.remove_resource::<RawTileIds>().unwrap());
+	}
+}
+
+/// Returns a world holding nothing but the tiles, at noon.
+pub fn test_world(tiles: impl IntoIterator<Item = (TileLayer, TilePos, WorldTile)>) -> ServerGameWorld {
+	let mut world = ServerGameWorld {
+		id: WorldId("test".to_string()),
+		tiles: default(),
+		tile_entities: default(),
+		light: default(),
+		time: WorldTime(DAY_LENGTH * 0.5),
+		players: default(),
+		bans: default(),
+		spawnpoint: Position { x: 0.0, y: 0.0 },
+		records: default(),
+	};
+	for (layer, pos, tile) in tiles {
+		world.tiles.insert(layer, pos, tile);
+	}
+	world
+}
@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+
+use crate::creature::player::Player;
+use crate::inventory::{Inventory, ItemStack, SelectedSlot};
+use crate::networking::batch::TileChanges;
+use crate::networking::error::NetworkError;
+use crate::networking::protocol::{ClientId, ClientMessage, Packet, ServerMessage};
+use crate::registry::item::ItemDefs;
+use crate::registry::tile::TileDefs;
+use crate::registry::tile::liquid::MAX_LIQUID_LEVEL;
+use crate::tile::{TileData, WorldTile};
+use crate::tile::layer::TileLayer;
+use crate::utils::BevyHashMap;
+use crate::world::{PlayerStats, ServerGameWorld, ServerGameWorlds, TileEventError, WorldId};
+use crate::{Position, TilePos};
+
+use super::networking::{broadcast_world_message, send_message, NetServer, Players};
+
+/// How far away (in tiles) a player may break or place tiles.
+pub const TILE_REACH: f32 = 8.0;
+
+/// Tile changes that haven't been sent to players yet, per world.
+///
+/// Everything that changes tiles records the change here, and [send_tile_updates] sends them all at once as one
+/// [TileUpdateBatch](crate::networking::batch::TileUpdateBatch) per chunk.
+#[derive(Resource, Debug, Default)]
+pub struct PendingTileUpdates(BevyHashMap<WorldId, TileChanges>);
+
+impl PendingTileUpdates {
+	pub fn push(&mut self, world: &WorldId, layer: TileLayer, pos: TilePos, tile: WorldTile) {
+		self.0
+			.entry(world.clone())
+			.or_default()
+			.push(layer, pos, tile);
+	}
+}
+
+/// Sends the pending tile changes of each world to its players, followed by the tile entities of the changed tiles.
+pub fn send_tile_updates(
+	mut pending: ResMut<PendingTileUpdates>,
+	mut server: NetServer,
+	worlds: Res<ServerGameWorlds>,
+	player_client_id_query: Query<&ClientId, With<Player>>,
+) -> Result<(), NetworkError> {
+	if pending.0.is_empty() {
+		return Ok(())
+	}
+	
+	for (world_id, changes) in std::mem::take(&mut pending.0) {
+		let Some(world) = worlds.get_world(world_id.as_str()) else {
+			continue
+		};
+		
+		for batch in changes.to_batches() {
+			broadcast_world_message!(server, world, player_client_id_query, ServerMessage::TileUpdateBatch(batch));
+		}
+		
+		for (layer, pos, _) in changes.iter() {
+			if let Some(tile_entity) = world.tile_entities.get(layer, pos) {
+				broadcast_world_message!(server, world, player_client_id_query, ServerMessage::SetTileEntity(layer, *pos, Some(tile_entity.clone())));
+			}
+		}
+	}
+	
+	Ok(())
+}
+
+/// Returns whether the position is within [TILE_REACH] of the player.
+fn in_reach(position: Option<&Position>, pos: &TilePos) -> bool {
+	match position {
+		Some(position) => Vec2::new(pos.x as f32 - position.x, pos.y as f32 - position.y).length() <= TILE_REACH,
+		None => true,
+	}
+}
+
+/// Handles [ClientMessage::BreakTile] requests and gives the tile's drops to the player who broke it.
+pub fn break_tile(
+	message_query: Query<(&ClientId, &Packet)>,
+	mut server: NetServer,
+	mut worlds: ResMut<ServerGameWorlds>,
+	mut pending: ResMut<PendingTileUpdates>,
+	players: Res<Players>,
+	mut player_query: Query<(&WorldId, Option<&Position>, &mut Inventory, &mut PlayerStats), With<Player>>,
+	tile_defs: TileDefs,
+	item_defs: ItemDefs,
+) -> Result<(), NetworkError> {
+	for (client_id, packet) in message_query.iter() {
+		let Packet::ClientMessage(ClientMessage::BreakTile(layer, pos)) = packet else {
+			continue
+		};
+		
+		let Some(&player_entity) = players.get(client_id) else {
+			continue
+		};
+		
+		// players that aren't in a world don't have an inventory either
+		let Ok((world_id, position, mut inventory, mut stats)) = player_query.get_mut(player_entity) else {
+			continue
+		};
+		
+		let Some(world) = worlds.get_world_mut(world_id.as_str()) else {
+			continue
+		};
+		
+		if !in_reach(position, pos) {
+			continue
+		}
+		
+		let tile = match world.tiles.get(*layer, pos) {
+			Some(tile) if !tile.is_air() => tile,
+			_ => continue,
+		};
+		
+		let id = match tile_defs.raw_tile_ids().get_id(tile.0) {
+			Some(id) => id.clone(),
+			None => return Err(TileEventError::InvalidRawId(tile.0, *pos).into()),
+		};
+		
+		world.remove_tile(*layer, pos);
+		world.update_light([*pos], &tile_defs);
+		pending.push(world_id, *layer, *pos, WorldTile::air());
+		stats.tiles_broken += 1;
+		
+		let Some(def) = tile_defs.get(&id) else {
+			return Err(TileEventError::TileDefNotFound(id, *pos).into())
+		};
+		
+		if def.drops().is_empty() {
+			continue
+		}
+		
+		for drop in def.drops() {
+			let Some(raw_id) = item_defs.raw_item_ids().get_raw_id(drop.item()) else {
+				warn!("Tile {} drops nonexistent item {}", id, drop.item());
+				continue
+			};
+			
+			let leftover = inventory.insert(ItemStack::new(raw_id, drop.count()), item_defs.max_stack(raw_id));
+			if let Some(leftover) = leftover {
+				// todo: spawn dropped item entities
+				warn!("Inventory of client {:X} is full; {} x{} was lost", client_id.0, drop.item(), leftover.count);
+			}
+		}
+		
+		send_message!(server, client_id, ServerMessage::Inventory(inventory.clone()));
+	}
+	
+	Ok(())
+}
+
+/// Returns whether a tile placed at the position would be attached to another tile, either in another layer at the same
+/// position or next to it in any layer.
+fn is_attached(world: &ServerGameWorld, layer: TileLayer, pos: &TilePos) -> bool {
+	let occupied = |layer: TileLayer, pos: &TilePos| world.tiles.get(layer, pos).is_some_and(|tile| !tile.is_air());
+	let neighbors = [
+		TilePos { x: pos.x - 1, y: pos.y },
+		TilePos { x: pos.x + 1, y: pos.y },
+		TilePos { x: pos.x, y: pos.y - 1 },
+		TilePos { x: pos.x, y: pos.y + 1 },
+	];
+	
+	TileLayer::ALL.iter().any(|&other| {
+		(other != layer && occupied(other, pos)) || neighbors.iter().any(|neighbor| occupied(other, neighbor))
+	})
+}
+
+/// Handles [ClientMessage::PlaceTile] requests by placing the tile of the player's held item.
+///
+/// Tiles always go in the layer of their salience, whatever layer was asked for. See
+/// [TileSalience::layer](crate::registry::tile::settings::TileSalience::layer).
+pub fn place_tile(
+	message_query: Query<(&ClientId, &Packet)>,
+	mut server: NetServer,
+	mut worlds: ResMut<ServerGameWorlds>,
+	mut pending: ResMut<PendingTileUpdates>,
+	players: Res<Players>,
+	mut player_query: Query<(&WorldId, Option<&Position>, &mut Inventory, &SelectedSlot, &mut PlayerStats), With<Player>>,
+	tile_defs: TileDefs,
+	item_defs: ItemDefs,
+) -> Result<(), NetworkError> {
+	for (client_id, packet) in message_query.iter() {
+		let Packet::ClientMessage(ClientMessage::PlaceTile(_, pos)) = packet else {
+			continue
+		};
+		
+		let Some(&player_entity) = players.get(client_id) else {
+			continue
+		};
+		
+		let Ok((world_id, position, mut inventory, selected_slot, mut stats)) = player_query.get_mut(player_entity) else {
+			continue
+		};
+		
+		let Some(world) = worlds.get_world_mut(world_id.as_str()) else {
+			continue
+		};
+		
+		if !in_reach(position, pos) {
+			continue
+		}
+		
+		let Some(mut stack) = inventory.get(selected_slot.0).copied() else {
+			continue
+		};
+		
+		let Some(tile_id) = item_defs.get_raw(stack.item).and_then(|def| def.places()) else {
+			continue
+		};
+		
+		let Some(def) = tile_defs.get(tile_id) else {
+			return Err(TileEventError::TileDefNotFound(tile_id.clone(), *pos).into())
+		};
+		
+		let layer = def.settings().salience().layer();
+		
+		if world.tiles.get(layer, pos).is_some_and(|tile| !tile.is_air()) || !is_attached(world, layer, pos) {
+			continue
+		}
+		
+		// liquids are placed full
+		let data = TileData(if def.liquid().is_some() { MAX_LIQUID_LEVEL } else { 0 });
+		let Some(tile) = WorldTile::new_data(tile_id, tile_defs.raw_tile_ids(), data) else {
+			return Err(TileEventError::InvalidId(tile_id.clone(), *pos).into())
+		};
+		
+		world.set_tile(layer, *pos, tile.clone(), def);
+		world.update_light([*pos], &tile_defs);
+		pending.push(world_id, layer, *pos, tile);
+		stats.tiles_placed += 1;
+		
+		stack.count -= 1;
+		inventory.set(selected_slot.0, Some(stack));
+		send_message!(server, client_id, ServerMessage::Inventory(inventory.clone()));
+	}
+	
+	Ok(())
+}
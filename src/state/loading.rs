@@ -1,12 +1,16 @@
-use bevy::asset::{LoadState, LoadedFolder};
+use bevy::asset::{Asset, LoadState, LoadedFolder};
 use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
 
 use crate::DEFAULT_LOCALE;
 use crate::asset::locale::LocaleAsset;
-use crate::asset::tile::TileDef;
 use crate::i18n::{TranslationServer, CurrentLocale};
 use crate::identifier::Identifier;
 use crate::raw_id::RawId;
+use crate::raw_id::item::RawItemIds;
+use crate::registry::Registry;
+use crate::registry::item::ItemRegistry;
+use crate::registry::recipe::RecipeRegistry;
 use crate::registry::tile::TileRegistry;
 use crate::utils::fatal_error_systems;
 use crate::{EnvType, from_asset_loc, GameState, NAMESPACE, raw_id::{RawIds, tile::RawTileIds}};
@@ -56,32 +60,10 @@ fn load_assets(
 	asset_server: Res<AssetServer>,
 	mut loading: ResMut<AssetsLoading>,
 	asset_paths: Res<AssetPaths>,
-	mut tile_registry: ResMut<TileRegistry>,
-	loaded_folder_assets: Res<Assets<LoadedFolder>>,
 ) -> anyhow::Result<()> {
 	println!("Loading assets");
 	
 	for asset_path in asset_paths.iter() {
-		// add handles to tile definitions in tile registry
-		let tiles_handle = asset_server.load_folder(from_asset_loc(asset_path, "tiles"));
-		let tiles_folder: Option<&LoadedFolder> =  loaded_folder_assets.get(tiles_handle);
-		if let Some(tiles_folder) = tiles_folder {
-			for handle in tiles_folder.handles {
-				let handle: Handle<TileDef> = handle.typed();
-				let path = asset_server.get_path(handle);
-				if let Some(path) = path {
-					let mut path_buf = path.path().to_path_buf();
-					path_buf.set_extension("");
-					path_buf.set_extension("");
-					let name = path_buf.file_name();
-					if let Some(name) = name {
-						let name = name.to_string_lossy().to_string();
-						tile_registry.register(handle, Identifier::new(asset_path.clone(), name));
-					}
-				}
-			}
-		}
-		
 		// load all locale files
 		let locale = asset_server.load_folder(from_asset_loc(asset_path, "locale"));
 		// load all fonts
@@ -92,9 +74,13 @@ fn load_assets(
 		let ids = asset_server.load_folder(from_asset_loc(asset_path, "ids"));
 		// load all tiles
 		let tiles = asset_server.load_folder(from_asset_loc(asset_path, "tiles"));
+		// load all items
+		let items = asset_server.load_folder(from_asset_loc(asset_path, "items"));
+		// load all recipes
+		let recipes = asset_server.load_folder(from_asset_loc(asset_path, "recipes"));
 		
 		// add all assets to tracker
-		for folder in [locale, fonts, textures, ids, tiles] {
+		for folder in [locale, fonts, textures, ids, tiles, items, recipes] {
 			loading.assets.push(folder.clone());
 		}
 	}
@@ -112,6 +98,9 @@ fn check_assets_ready(
 	locale_assets: Res<Assets<LocaleAsset>>,
 	raw_ids_assets: Res<Assets<RawIds>>,
 	loaded_folder_assets: Res<Assets<LoadedFolder>>,
+	mut tile_registry: ResMut<TileRegistry>,
+	mut item_registry: ResMut<ItemRegistry>,
+	mut recipe_registry: ResMut<RecipeRegistry>,
 ) -> anyhow::Result<()> {
 	let path_expect = "asset handle should have path";
 	
@@ -148,18 +137,41 @@ fn check_assets_ready(
 	if loaded {
 		loading.finished = true;
 		
+		// add handles to tile, item & recipe definitions in their registries
+		for asset_path in asset_paths.iter() {
+			if let Some(tiles_folder) = loaded_folder_assets.get(&asset_server.load_folder(from_asset_loc(asset_path, "tiles"))) {
+				register_folder(&mut tile_registry, tiles_folder, &asset_server, asset_path);
+			}
+			if let Some(items_folder) = loaded_folder_assets.get(&asset_server.load_folder(from_asset_loc(asset_path, "items"))) {
+				register_folder(&mut item_registry, items_folder, &asset_server, asset_path);
+			}
+			if let Some(recipes_folder) = loaded_folder_assets.get(&asset_server.load_folder(from_asset_loc(asset_path, "recipes"))) {
+				register_folder(&mut recipe_registry, recipes_folder, &asset_server, asset_path);
+			}
+		}
+		
 		if *env == EnvType::Server {
 			let mut raw_tile_ids: RawTileIds = default();
+			let mut raw_item_ids: RawItemIds = default();
 			for asset_path in asset_paths.iter() {
-				let raw_ids_folder_handle: Handle<LoadedFolder> = asset_server.load(format!("{asset_path}/ids"));
-				let raw_ids_folder: Option<&LoadedFolder> = loaded_folder_assets.get(raw_ids_folder_handle);
+				let raw_ids_folder_handle: Handle<LoadedFolder> = asset_server.load_folder(from_asset_loc(asset_path, "ids"));
+				let raw_ids_folder: Option<&LoadedFolder> = loaded_folder_assets.get(&raw_ids_folder_handle);
 				if let Some(raw_ids_folder) = raw_ids_folder {
-					for handle in raw_ids_folder.handles {
-						let raw_tile_ids_asset = raw_ids_assets.get(handle.clone()).unwrap();
-						let file_name = asset_server.get_path(handle.typed::<RawIds>()).unwrap().path().file_name().unwrap().to_string_lossy().to_string();
-						for id in raw_tile_ids_asset.get_ids() {
-							let raw_id = raw_tile_ids_asset.get_raw_id(id).unwrap();
-							raw_tile_ids.register(id.clone(), raw_id);
+					for handle in raw_ids_folder.handles.iter() {
+						let handle = handle.clone().typed::<RawIds>();
+						let raw_ids_asset = raw_ids_assets.get(&handle).unwrap();
+						let file_name = asset_server.get_path(&handle).unwrap().path().file_name().unwrap().to_string_lossy().to_string();
+						let raw_ids: &mut RawIds = match file_name.as_str() {
+							"tile.ids.ron" => &mut raw_tile_ids,
+							"item.ids.ron" => &mut raw_item_ids,
+							_ => {
+								warn!("Unknown raw ID file {}/ids/{}", asset_path, file_name);
+								continue
+							},
+						};
+						for id in raw_ids_asset.get_ids() {
+							let raw_id = raw_ids_asset.get_raw_id(id).unwrap();
+							raw_ids.register(id.clone(), raw_id);
 						}
 					}
 				}
@@ -169,8 +181,11 @@ fn check_assets_ready(
 			raw_tile_ids.register(Identifier::from_str("null", "air"), RawId(-1));
 			// add missingno tile
 			raw_tile_ids.register(Identifier::from_str("null", "null"), RawId(-2));
+			// add missingno item
+			raw_item_ids.register(Identifier::from_str("null", "null"), RawId(-2));
 			
 			commands.insert_resource(raw_tile_ids);
+			commands.insert_resource(raw_item_ids);
 		}
 		
 		let current_locale = CurrentLocale::new(DEFAULT_LOCALE.to_string()); // todo: change locale based on settings
@@ -196,3 +211,22 @@ fn check_assets_ready(
 	
 	Ok(())
 }
+
+/// Registers every definition in the folder with the identifier `<namespace>:<file name>`.
+fn register_folder<T>(registry: &mut Registry<T>, folder: &LoadedFolder, asset_server: &AssetServer, namespace: &str)
+	where T: TypeUuid + TypePath + Asset {
+	for handle in folder.handles.iter() {
+		let handle: Handle<T> = handle.clone().typed();
+		let path = asset_server.get_path(&handle);
+		if let Some(path) = path {
+			let mut path_buf = path.path().to_path_buf();
+			path_buf.set_extension("");
+			path_buf.set_extension("");
+			let name = path_buf.file_name();
+			if let Some(name) = name {
+				let name = name.to_string_lossy().to_string();
+				registry.register(handle, Identifier::new(namespace.to_string(), name));
+			}
+		}
+	}
+}
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use crate::client::networking::{send_message, NetClient};
+use crate::inventory::{HOTBAR_SIZE, InventoryAction};
+use crate::menu::inventory::{InventorySlotUi, SLOT_BORDER_COLOR, SLOT_SIZE, spawn_slot};
+use crate::networking::error::{NETWORK_ERROR_MESSAGE, NetworkError};
+use crate::networking::protocol::ClientMessage;
+use crate::utils::nonfatal_error_systems;
+use crate::{despawn_with, from_asset_loc, GameState, NAMESPACE};
+
+const SELECTED_SLOT_BORDER_COLOR: BorderColor = BorderColor(Color::WHITE);
+
+/// The number keys in hotbar order. The last slot is bound to zero, like on the keyboard.
+const HOTBAR_KEYS: [KeyCode; HOTBAR_SIZE] = [
+	KeyCode::Key1,
+	KeyCode::Key2,
+	KeyCode::Key3,
+	KeyCode::Key4,
+	KeyCode::Key5,
+	KeyCode::Key6,
+	KeyCode::Key7,
+	KeyCode::Key8,
+	KeyCode::Key9,
+	KeyCode::Key0,
+];
+
+pub struct HotbarPlugin;
+
+impl Plugin for HotbarPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<SelectedHotbarSlot>()
+			.add_systems(OnEnter(GameState::InWorld), setup)
+			.add_systems(
+				Update,
+				(
+					nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, select_slot),
+					highlight_selected_slot,
+				)
+					.chain()
+					.run_if(in_state(GameState::InWorld))
+			)
+			.add_systems(OnExit(GameState::InWorld), (despawn_with::<OnHotbar>, reset_selected_slot));
+	}
+}
+
+/// The hotbar slot the local player is holding.
+///
+/// This is changed locally right away so that the highlight doesn't lag behind the server.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq, Deref)]
+pub struct SelectedHotbarSlot(pub usize);
+
+#[derive(Component)]
+struct OnHotbar;
+
+fn setup(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+) {
+	let monogram = asset_server.get_handle(from_asset_loc(NAMESPACE, "fonts/monogram/monogram-extended.ttf")).unwrap_or_default();
+	
+	commands
+		.spawn(
+			NodeBundle {
+				style: Style {
+					width: Val::Percent(100.0),
+					height: Val::Px(SLOT_SIZE * 1.5),
+					justify_content: JustifyContent::Center,
+					align_items: AlignItems::Center,
+					position_type: PositionType::Absolute,
+					bottom: Val::Px(0.0),
+					..default()
+				},
+				..default()
+			}
+		)
+		.insert(OnHotbar)
+		.with_children(|parent| {
+			for slot in 0..HOTBAR_SIZE {
+				spawn_slot(parent, slot, monogram.clone());
+			}
+		});
+}
+
+fn reset_selected_slot(mut selected_slot: ResMut<SelectedHotbarSlot>) {
+	selected_slot.0 = 0;
+}
+
+/// Selects a hotbar slot with the number keys and tells the server about it.
+fn select_slot(
+	keyboard_input: Res<Input<KeyCode>>,
+	mut selected_slot: ResMut<SelectedHotbarSlot>,
+	mut client: NetClient,
+) -> Result<(), NetworkError> {
+	let Some(slot) = HOTBAR_KEYS.iter().position(|key| keyboard_input.just_pressed(*key)) else {
+		return Ok(())
+	};
+	
+	if slot == selected_slot.0 {
+		return Ok(())
+	}
+	
+	selected_slot.0 = slot;
+	send_message!(client, ClientMessage::InventoryAction(InventoryAction::SelectHotbar(slot)));
+	
+	Ok(())
+}
+
+fn highlight_selected_slot(
+	selected_slot: Res<SelectedHotbarSlot>,
+	mut slot_query: Query<(Ref<InventorySlotUi>, &mut BorderColor)>,
+	hotbar_query: Query<&Children, With<OnHotbar>>,
+) {
+	for children in hotbar_query.iter() {
+		for &child in children.iter() {
+			let Ok((slot, mut border_color)) = slot_query.get_mut(child) else {
+				continue
+			};
+			
+			if !selected_slot.is_changed() && !slot.is_added() {
+				continue
+			}
+			
+			*border_color = if slot.0 == selected_slot.0 { SELECTED_SLOT_BORDER_COLOR } else { SLOT_BORDER_COLOR };
+		}
+	}
+}
@@ -0,0 +1,467 @@
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use bevy::window::PrimaryWindow;
+
+use crate::client::networking::{LocalPlayer, NetClient, send_message};
+use crate::i18n::{CurrentLocale, TranslationServer};
+use crate::inventory::{HOTBAR_SIZE, INVENTORY_SIZE, Inventory, InventoryAction};
+use crate::menu::{BACKGROUND, TEXT_MARGIN};
+use crate::networking::error::{NETWORK_ERROR_MESSAGE, NetworkError};
+use crate::networking::protocol::ClientMessage;
+use crate::registry::item::ItemDefs;
+use crate::utils::asset::load_image;
+use crate::utils::nonfatal_error_systems;
+use crate::{despawn_with, from_asset_loc, GameState, NAMESPACE};
+
+/// The width & height of an inventory slot in pixels.
+pub(super) const SLOT_SIZE: f32 = 40.0;
+/// The width & height of an item icon in pixels.
+const ICON_SIZE: f32 = 32.0;
+const SLOT_MARGIN: f32 = 2.0;
+const SLOT_BORDER: f32 = 2.0;
+const ITEM_COUNT_TEXT_SIZE: f32 = 16.0;
+const TOOLTIP_TEXT_SIZE: f32 = 16.0;
+
+pub(super) const SLOT_BACKGROUND: BackgroundColor = BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.5));
+pub(super) const SLOT_BORDER_COLOR: BorderColor = BorderColor(Color::rgb(0.25, 0.25, 0.25));
+
+pub struct InventoryScreenPlugin;
+
+impl Plugin for InventoryScreenPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<InventoryOpen>()
+			.init_resource::<DraggedSlot>()
+			.add_systems(
+				Update,
+				(
+					toggle_inventory,
+					update_slots,
+					begin_drag,
+					nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, end_drag),
+					move_drag_icon,
+					tooltip,
+				)
+					.chain()
+					.run_if(in_state(GameState::InWorld))
+			)
+			.add_systems(
+				OnExit(GameState::InWorld),
+				(despawn_with::<OnInventoryScreen>, despawn_with::<DragIcon>, despawn_with::<Tooltip>, close_inventory)
+			);
+	}
+}
+
+/// Whether the full inventory screen is open.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct InventoryOpen(pub bool);
+
+/// The slot whose stack is being dragged, if any.
+#[derive(Resource, Debug, Default, Copy, Clone)]
+struct DraggedSlot(Option<usize>);
+
+#[derive(Component)]
+struct OnInventoryScreen;
+
+/// A UI node that displays the stack in the local player's inventory slot.
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InventorySlotUi(pub usize);
+
+#[derive(Component)]
+struct SlotIcon;
+
+#[derive(Component)]
+struct SlotCount;
+
+#[derive(Component)]
+struct DragIcon;
+
+#[derive(Component)]
+struct Tooltip;
+
+/// Spawns an inventory slot. The icon and count are filled in by [update_slots].
+pub(super) fn spawn_slot(parent: &mut ChildBuilder, slot: usize, font: Handle<Font>) {
+	parent
+		.spawn(
+			ButtonBundle {
+				style: Style {
+					width: Val::Px(SLOT_SIZE),
+					height: Val::Px(SLOT_SIZE),
+					margin: UiRect::all(Val::Px(SLOT_MARGIN)),
+					border: UiRect::all(Val::Px(SLOT_BORDER)),
+					justify_content: JustifyContent::Center,
+					align_items: AlignItems::Center,
+					..default()
+				},
+				background_color: SLOT_BACKGROUND,
+				border_color: SLOT_BORDER_COLOR,
+				..default()
+			}
+		)
+		.insert(InventorySlotUi(slot))
+		.insert(RelativeCursorPosition::default())
+		.with_children(|parent| {
+			parent
+				.spawn(
+					ImageBundle {
+						style: Style {
+							width: Val::Px(ICON_SIZE),
+							height: Val::Px(ICON_SIZE),
+							..default()
+						},
+						visibility: Visibility::Hidden,
+						..default()
+					}
+				)
+				.insert(SlotIcon);
+			parent
+				.spawn(
+					TextBundle {
+						style: Style {
+							position_type: PositionType::Absolute,
+							right: Val::Px(0.0),
+							bottom: Val::Px(0.0),
+							..default()
+						},
+						text: Text::from_section(
+							"",
+							TextStyle {
+								font,
+								font_size: ITEM_COUNT_TEXT_SIZE,
+								color: Color::WHITE,
+							},
+						),
+						..default()
+					}
+				)
+				.insert(SlotCount);
+		});
+}
+
+/// Loads the icon of the item, or missingno if it doesn't have one.
+fn item_icon(asset_server: &AssetServer, item_defs: &ItemDefs, item: crate::raw_id::RawId) -> Handle<Image> {
+	match item_defs.get_raw(item) {
+		Some(def) if !def.is_missingno() => {
+			load_image(asset_server, format!("{}/textures/item/{}.png", def.identifier().namespace(), def.identifier().path()))
+		},
+		_ => Handle::default(),
+	}
+}
+
+fn toggle_inventory(
+	keyboard_input: Res<Input<KeyCode>>,
+	mut inventory_open: ResMut<InventoryOpen>,
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	current_locale: Res<CurrentLocale>,
+	translation_server: Res<TranslationServer>,
+	screen_query: Query<Entity, With<OnInventoryScreen>>,
+) {
+	let open = if keyboard_input.just_pressed(KeyCode::E) {
+		!inventory_open.0
+	} else if keyboard_input.just_pressed(KeyCode::Escape) {
+		false
+	} else {
+		return
+	};
+	
+	if open == inventory_open.0 {
+		return
+	}
+	inventory_open.0 = open;
+	
+	if !open {
+		for entity in screen_query.iter() {
+			commands.entity(entity).despawn_recursive();
+		}
+		return
+	}
+	
+	let monogram = asset_server.get_handle(from_asset_loc(NAMESPACE, "fonts/monogram/monogram-extended.ttf")).unwrap_or_default();
+	
+	// root
+	commands
+		.spawn(
+			NodeBundle {
+				style: Style {
+					width: Val::Percent(100.0),
+					height: Val::Percent(100.0),
+					justify_content: JustifyContent::Center,
+					align_items: AlignItems::Center,
+					position_type: PositionType::Absolute,
+					..default()
+				},
+				..default()
+			}
+		)
+		.insert(OnInventoryScreen)
+		.with_children(|parent| {
+			parent
+				.spawn(
+					NodeBundle {
+						style: Style {
+							flex_direction: FlexDirection::Column,
+							align_items: AlignItems::Center,
+							padding: UiRect::all(TEXT_MARGIN),
+							..default()
+						},
+						background_color: BACKGROUND,
+						..default()
+					}
+				)
+				.with_children(|parent| {
+					// title
+					parent
+						.spawn(
+							TextBundle {
+								style: Style {
+									margin: UiRect::all(TEXT_MARGIN),
+									..default()
+								},
+								text: Text::from_section(
+									translation_server.translate(
+										NAMESPACE,
+										"ui.inventory.text.title",
+										&current_locale,
+									).unwrap(),
+									TextStyle {
+										font: monogram.clone(),
+										font_size: 32.0,
+										color: Color::WHITE,
+									},
+								),
+								..default()
+							}
+						);
+					
+					// slots (the hotbar is the first row, so skip it)
+					for row_start in (HOTBAR_SIZE..INVENTORY_SIZE).step_by(HOTBAR_SIZE) {
+						parent
+							.spawn(
+								NodeBundle {
+									style: Style {
+										flex_direction: FlexDirection::Row,
+										..default()
+									},
+									..default()
+								}
+							)
+							.with_children(|parent| {
+								for slot in row_start..(row_start + HOTBAR_SIZE).min(INVENTORY_SIZE) {
+									spawn_slot(parent, slot, monogram.clone());
+								}
+							});
+					}
+				});
+		});
+}
+
+fn close_inventory(mut inventory_open: ResMut<InventoryOpen>, mut dragged_slot: ResMut<DraggedSlot>) {
+	inventory_open.0 = false;
+	dragged_slot.0 = None;
+}
+
+/// Updates the icons & counts of the slots whenever the local player's inventory changes.
+fn update_slots(
+	inventory_query: Query<Ref<Inventory>, With<LocalPlayer>>,
+	slot_query: Query<(Ref<InventorySlotUi>, &Children)>,
+	mut icon_query: Query<(&mut UiImage, &mut Visibility), With<SlotIcon>>,
+	mut count_query: Query<&mut Text, With<SlotCount>>,
+	asset_server: Res<AssetServer>,
+	item_defs: ItemDefs,
+) {
+	let Ok(inventory) = inventory_query.get_single() else {
+		return
+	};
+	
+	for (slot, children) in slot_query.iter() {
+		if !inventory.is_changed() && !slot.is_added() {
+			continue
+		}
+		
+		let stack = inventory.get(slot.0);
+		for &child in children.iter() {
+			if let Ok((mut image, mut visibility)) = icon_query.get_mut(child) {
+				match stack {
+					Some(stack) => {
+						image.texture = item_icon(&asset_server, &item_defs, stack.item);
+						*visibility = Visibility::Inherited;
+					},
+					None => *visibility = Visibility::Hidden,
+				}
+			}
+			
+			if let Ok(mut text) = count_query.get_mut(child) {
+				text.sections[0].value = match stack {
+					Some(stack) if stack.count > 1 => stack.count.to_string(),
+					_ => String::new(),
+				};
+			}
+		}
+	}
+}
+
+fn begin_drag(
+	interaction_query: Query<(&Interaction, &InventorySlotUi), Changed<Interaction>>,
+	inventory_query: Query<&Inventory, With<LocalPlayer>>,
+	mut dragged_slot: ResMut<DraggedSlot>,
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	item_defs: ItemDefs,
+) {
+	let Ok(inventory) = inventory_query.get_single() else {
+		return
+	};
+	
+	for (interaction, slot) in interaction_query.iter() {
+		if *interaction != Interaction::Pressed || dragged_slot.0.is_some() {
+			continue
+		}
+		
+		let Some(stack) = inventory.get(slot.0) else {
+			continue
+		};
+		
+		dragged_slot.0 = Some(slot.0);
+		commands
+			.spawn(
+				ImageBundle {
+					style: Style {
+						width: Val::Px(ICON_SIZE),
+						height: Val::Px(ICON_SIZE),
+						position_type: PositionType::Absolute,
+						..default()
+					},
+					image: item_icon(&asset_server, &item_defs, stack.item).into(),
+					z_index: ZIndex::Global(i32::MAX),
+					..default()
+				}
+			)
+			.insert(DragIcon);
+	}
+}
+
+/// Drops the dragged stack onto the slot under the cursor.
+///
+/// Holding shift splits the stack instead of moving all of it.
+fn end_drag(
+	mouse_input: Res<Input<MouseButton>>,
+	keyboard_input: Res<Input<KeyCode>>,
+	slot_query: Query<(&InventorySlotUi, &RelativeCursorPosition)>,
+	drag_icon_query: Query<Entity, With<DragIcon>>,
+	mut dragged_slot: ResMut<DraggedSlot>,
+	mut client: NetClient,
+	mut commands: Commands,
+) -> Result<(), NetworkError> {
+	if !mouse_input.just_released(MouseButton::Left) {
+		return Ok(())
+	}
+	
+	let Some(from) = dragged_slot.0.take() else {
+		return Ok(())
+	};
+	
+	for entity in drag_icon_query.iter() {
+		commands.entity(entity).despawn_recursive();
+	}
+	
+	let target = slot_query
+		.iter()
+		.find_map(|(slot, cursor)| if cursor.mouse_over() { Some(slot.0) } else { None });
+	let Some(to) = target else {
+		return Ok(())
+	};
+	
+	if to == from {
+		return Ok(())
+	}
+	
+	let action = if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+		InventoryAction::Split { from, to }
+	} else {
+		InventoryAction::Move { from, to }
+	};
+	send_message!(client, ClientMessage::InventoryAction(action));
+	
+	Ok(())
+}
+
+fn move_drag_icon(
+	window_query: Query<&Window, With<PrimaryWindow>>,
+	mut drag_icon_query: Query<&mut Style, With<DragIcon>>,
+) {
+	let Some(cursor) = window_query.get_single().ok().and_then(|window| window.cursor_position()) else {
+		return
+	};
+	
+	for mut style in drag_icon_query.iter_mut() {
+		style.left = Val::Px(cursor.x - ICON_SIZE / 2.0);
+		style.top = Val::Px(cursor.y - ICON_SIZE / 2.0);
+	}
+}
+
+/// Shows the localized name & description of the hovered item next to the cursor.
+fn tooltip(
+	window_query: Query<&Window, With<PrimaryWindow>>,
+	slot_query: Query<(&InventorySlotUi, &Interaction)>,
+	inventory_query: Query<&Inventory, With<LocalPlayer>>,
+	tooltip_query: Query<Entity, With<Tooltip>>,
+	dragged_slot: Res<DraggedSlot>,
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	current_locale: Res<CurrentLocale>,
+	translation_server: Res<TranslationServer>,
+	item_defs: ItemDefs,
+) {
+	for entity in tooltip_query.iter() {
+		commands.entity(entity).despawn_recursive();
+	}
+	
+	if dragged_slot.0.is_some() {
+		return
+	}
+	
+	let (Ok(window), Ok(inventory)) = (window_query.get_single(), inventory_query.get_single()) else {
+		return
+	};
+	
+	let Some(cursor) = window.cursor_position() else {
+		return
+	};
+	
+	let hovered = slot_query
+		.iter()
+		.find_map(|(slot, interaction)| if *interaction == Interaction::Hovered { Some(slot.0) } else { None });
+	let Some(def) = hovered.and_then(|slot| inventory.get(slot)).and_then(|stack| item_defs.get_raw(stack.item)) else {
+		return
+	};
+	
+	let monogram = asset_server.get_handle(from_asset_loc(NAMESPACE, "fonts/monogram/monogram-extended.ttf")).unwrap_or_default();
+	let text_style = |color: Color| TextStyle {
+		font: monogram.clone(),
+		font_size: TOOLTIP_TEXT_SIZE,
+		color,
+	};
+	
+	commands
+		.spawn(
+			TextBundle {
+				style: Style {
+					position_type: PositionType::Absolute,
+					left: Val::Px(cursor.x + SLOT_SIZE / 2.0),
+					top: Val::Px(cursor.y),
+					padding: UiRect::all(TEXT_MARGIN),
+					..default()
+				},
+				text: Text::from_sections([
+					TextSection::new(translation_server.translate_or_key(def.name(), &current_locale), text_style(Color::WHITE)),
+					TextSection::new("\n", text_style(Color::WHITE)),
+					TextSection::new(translation_server.translate_or_key(def.description(), &current_locale), text_style(Color::GRAY)),
+				]),
+				background_color: BACKGROUND,
+				z_index: ZIndex::Global(i32::MAX),
+				..default()
+			}
+		)
+		.insert(Tooltip);
+}
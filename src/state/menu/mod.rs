@@ -7,11 +7,18 @@ use bevy::render::camera::ScalingMode;
 use bevy_egui::{egui, EguiContext, EguiContexts};
 use futures::executor;
 
+use crate::camera::{CameraZoom, MainCamera};
+use crate::utils::math::TILE_SIZE_PX;
+
 pub mod title_screen;
 pub mod button;
 pub mod bevy_splash;
 pub mod server_select;
 pub mod world_select;
+pub mod inventory;
+pub mod hotbar;
+pub mod player_list;
+pub mod pause;
 
 const TEXT_MARGIN: Val = Val::Px(5.0);
 
@@ -42,15 +49,16 @@ pub fn init_ui(
 	commands.spawn(
 		Camera2dBundle {
 			projection: OrthographicProjection {
-				scale: 1.0 / 4.0,
-				scaling_mode: ScalingMode::WindowSize(16.0),
+				scale: CameraZoom::default().scale(),
+				scaling_mode: ScalingMode::WindowSize(TILE_SIZE_PX),
 				near: -1000.0,
 				far: 1000.0,
 				..default()
 			},
 			..default()
 		}
-	);
+	)
+		.insert(MainCamera);
 	
 	let mut style = egui::Style::default();
 	let ref mut visuals = style.visuals;
@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::client::networking::{send_message, NetClient};
+use crate::i18n::{CurrentLocale, TranslationServer};
+use crate::menu::inventory::InventoryOpen;
+use crate::networking::error::{NetworkError, NETWORK_ERROR_MESSAGE};
+use crate::networking::protocol::ClientMessage;
+use crate::utils::nonfatal_error_systems;
+use crate::{GameState, NAMESPACE};
+
+/// The key that opens and closes the pause menu, as long as the inventory is closed.
+const PAUSE_KEY: KeyCode = KeyCode::Escape;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<PauseOpen>()
+			.add_systems(
+				Update,
+				(
+					toggle_pause,
+					nonfatal_error_systems!(NETWORK_ERROR_MESSAGE, NetworkError, pause_menu),
+				)
+					.chain()
+					.run_if(in_state(GameState::InWorld).or_else(in_state(GameState::LoadingWorld)))
+					.run_if(resource_equals(InventoryOpen(false)))
+			)
+			.add_systems(OnEnter(GameState::WorldSelect), close_pause);
+	}
+}
+
+/// Whether the pause menu is open.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct PauseOpen(bool);
+
+fn toggle_pause(
+	keyboard_input: Res<Input<KeyCode>>,
+	inventory_open: Res<InventoryOpen>,
+	mut pause_open: ResMut<PauseOpen>,
+) {
+	// the same press that closed the inventory shouldn't open the pause menu
+	if inventory_open.is_changed() {
+		return
+	}
+	
+	if keyboard_input.just_pressed(PAUSE_KEY) {
+		pause_open.0 = !pause_open.0;
+	}
+}
+
+/// Shows the pause menu. Leaving the world asks the server first; the client goes back to the world select screen once
+/// the server has let the player go. Nickname changes are asked for here too, and an empty nickname clears it.
+fn pause_menu(
+	contexts: EguiContexts,
+	mut pause_open: ResMut<PauseOpen>,
+	mut nickname: Local<String>,
+	mut client: NetClient,
+	current_locale: Res<CurrentLocale>,
+	translation_server: Res<TranslationServer>,
+) -> Result<(), NetworkError> {
+	if !pause_open.0 {
+		return Ok(())
+	}
+	
+	let translate = |key: &str| -> String {
+		translation_server
+			.translate(NAMESPACE, key, &current_locale)
+			.cloned()
+			.unwrap_or_else(|| key.to_string())
+	};
+	
+	let mut resume = false;
+	let mut leave_world = false;
+	let mut set_nickname = false;
+	egui::Window::new(translate("ui.pause.window.title"))
+		.anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+		.collapsible(false)
+		.resizable(false)
+		.show(contexts.ctx(), |ui| {
+			ui.vertical_centered_justified(|ui| {
+				resume = ui.button(translate("ui.pause.button.resume")).clicked();
+				leave_world = ui.button(translate("ui.pause.button.leave_world")).clicked();
+				
+				ui.separator();
+				ui.label(translate("ui.pause.text.nickname"));
+				let text_box = ui.text_edit_singleline(&mut *nickname);
+				set_nickname = ui.button(translate("ui.pause.button.set_nickname")).clicked()
+					|| (text_box.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)));
+			});
+		});
+	
+	if resume {
+		pause_open.0 = false;
+	}
+	if leave_world {
+		pause_open.0 = false;
+		send_message!(client, ClientMessage::LeaveWorld);
+	}
+	if set_nickname {
+		send_message!(client, ClientMessage::NickRequest(nickname.trim().to_string()));
+	}
+	
+	Ok(())
+}
+
+fn close_pause(mut pause_open: ResMut<PauseOpen>) {
+	pause_open.0 = false;
+}
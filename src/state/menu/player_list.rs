@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::client::networking::{PlayerPings, Roster};
+use crate::i18n::{CurrentLocale, TranslationServer};
+use crate::{GameState, NAMESPACE};
+
+/// The key to hold to show the player list.
+const PLAYER_LIST_KEY: KeyCode = KeyCode::Tab;
+
+/// The most bars a ping can get.
+const PING_BARS: usize = 5;
+
+/// The highest ping, in milliseconds, that still gets each number of bars, from all of them down to one.
+const PING_BAR_THRESHOLDS: [u32; PING_BARS - 1] = [50, 100, 200, 400];
+
+pub struct PlayerListPlugin;
+
+impl Plugin for PlayerListPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(
+				Update,
+				player_list
+					.run_if(in_state(GameState::InWorld))
+			);
+	}
+}
+
+/// Shows every player on the server, with their nickname, world and ping, while [PLAYER_LIST_KEY] is held.
+fn player_list(
+	keyboard_input: Res<Input<KeyCode>>,
+	contexts: EguiContexts,
+	roster: Option<Res<Roster>>,
+	pings: Option<Res<PlayerPings>>,
+	current_locale: Res<CurrentLocale>,
+	translation_server: Res<TranslationServer>,
+) {
+	if !keyboard_input.pressed(PLAYER_LIST_KEY) {
+		return
+	}
+	
+	let Some(roster) = roster else {
+		return
+	};
+	
+	let translate = |key: &str| -> String {
+		translation_server
+			.translate(NAMESPACE, key, &current_locale)
+			.cloned()
+			.unwrap_or_else(|| key.to_string())
+	};
+	
+	let mut entries: Vec<_> = roster.iter().collect();
+	entries.sort_by(|a, b| a.data.username.0.cmp(&b.data.username.0));
+	
+	egui::Window::new(format!("{} ({})", translate("ui.player_list.window.title"), entries.len()))
+		.anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 16.0))
+		.collapsible(false)
+		.resizable(false)
+		.show(contexts.ctx(), |ui| {
+			egui::Grid::new("player list").striped(true).show(ui, |ui| {
+				ui.strong(translate("ui.player_list.text.player"));
+				ui.strong(translate("ui.player_list.text.nickname"));
+				ui.strong(translate("ui.player_list.text.world"));
+				ui.strong(translate("ui.player_list.text.ping"));
+				ui.end_row();
+				
+				for entry in entries {
+					ui.label(entry.data.username.to_string());
+					ui.label(entry.nickname.as_deref().unwrap_or(""));
+					ui.label(match &entry.world {
+						Some(world) => world.as_str().to_string(),
+						None => translate("ui.player_list.text.no_world"),
+					});
+					
+					let ping = pings.as_ref().and_then(|pings| pings.get(&entry.client_id)).copied();
+					ping_bars(ui, ping);
+					ui.end_row();
+				}
+			});
+		});
+}
+
+/// Draws the ping as bars, with the exact number on hover. An unknown ping has no bars.
+fn ping_bars(ui: &mut egui::Ui, ping: Option<u32>) {
+	let bars = match ping {
+		Some(ping) => PING_BARS - PING_BAR_THRESHOLDS.iter().take_while(|threshold| ping > **threshold).count(),
+		None => 0,
+	};
+	
+	let color = match bars {
+		4..=PING_BARS => egui::Color32::GREEN,
+		2..=3 => egui::Color32::YELLOW,
+		_ => egui::Color32::RED,
+	};
+	
+	let text = egui::RichText::new(format!("{}{}", "▮".repeat(bars), "▯".repeat(PING_BARS - bars)))
+		.color(color)
+		.monospace();
+	let hover = match ping {
+		Some(ping) => format!("{} ms", ping),
+		None => "?".to_string(),
+	};
+	ui.label(text).on_hover_text(hover);
+}
@@ -4,8 +4,8 @@ use std::fmt::Formatter;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use bevy_egui::egui::style::Margin;
-use futures::task::SpawnExt;
 
+use crate::client::servers::{PingStatus, poll_pings, SavedServer, SavedServers, SERVER_LIST_ERROR_MESSAGE, ServerStatuses};
 use crate::i18n::{TranslationServer, CurrentLocale};
 // jesus fucking christ
 use crate::{asset, DEFAULT_LOCALE, despawn_with, from_asset_loc, GameState, LocaleAsset, menu, NAMESPACE, ServerConnectAddress, Translatable};
@@ -17,7 +17,8 @@ pub struct ServerSelectPlugin;
 impl Plugin for ServerSelectPlugin {
 	fn build(&self, app: &mut App) {
 		app
-			.add_systems(OnEnter(GameState::ServerSelect), setup)
+			.init_resource::<ServerStatuses>()
+			.add_systems(OnEnter(GameState::ServerSelect), (setup, load_server_list))
 			.add_systems(OnExit(GameState::ServerSelect), despawn_with::<OnServerSelect>)
 			.add_systems(
 				Update,
@@ -31,7 +32,7 @@ impl Plugin for ServerSelectPlugin {
 			)
 			.add_systems(
 				Update,
-				text_box
+				(text_box, poll_pings, server_list)
 					.run_if(in_state(GameState::ServerSelect))
 			);
 	}
@@ -260,6 +261,200 @@ fn text_box(
 	});
 }
 
+/// The add/edit form of the server list.
+#[derive(Debug, Default)]
+struct ServerForm {
+	/// The index of the server being edited, or [None] when adding a server.
+	editing: Option<usize>,
+	name: String,
+	address: String,
+	/// Left empty for the conventional query port.
+	query_port: String,
+}
+
+fn load_server_list(
+	mut commands: Commands,
+	mut statuses: ResMut<ServerStatuses>,
+) {
+	let servers = SavedServers::load().unwrap_or_else(|err| {
+		eprintln!("Failed to load the server list: {}", err);
+		SavedServers::default()
+	});
+	statuses.ping_all(&servers);
+	commands.insert_resource(servers);
+}
+
+fn server_list(
+	mut contexts: EguiContexts,
+	mut servers: ResMut<SavedServers>,
+	mut statuses: ResMut<ServerStatuses>,
+	mut form: Local<ServerForm>,
+	mut server_address: ResMut<ServerConnectAddress>,
+	mut next_state: ResMut<NextState<GameState>>,
+	current_locale: Res<CurrentLocale>,
+	translation_server: Res<TranslationServer>,
+) {
+	let translate = |key: &str| -> String {
+		translation_server
+			.translate(NAMESPACE, key, &current_locale)
+			.cloned()
+			.unwrap_or_else(|| key.to_string())
+	};
+	
+	let mut changed = false;
+	
+	egui::Window::new(translate("ui.server_select.window.title.servers")).show(contexts.ctx(), |ui| {
+		if servers.iter().next().is_none() {
+			ui.label(translate("ui.server_select.text.no_servers"));
+		}
+		
+		// act on the list after drawing it, since the buttons change it
+		let mut action: Option<(usize, ServerListAction)> = None;
+		
+		for (index, server) in servers.iter().enumerate() {
+			ui.separator();
+			ui.horizontal(|ui| {
+				ui.strong(&server.name);
+				ui.weak(&server.address);
+			});
+			
+			match statuses.get(&server.address) {
+				Some(PingStatus::Online(status, ping)) => {
+					ui.label(&status.motd);
+					ui.label(format!(
+						"{} · {}/{} · {} ms",
+						status.version_string,
+						status.players,
+						status.max_clients,
+						ping.as_millis(),
+					));
+				},
+				Some(PingStatus::Offline(err)) => {
+					ui.label(translate("ui.server_select.text.offline")).on_hover_text(err);
+				},
+				Some(PingStatus::Pinging(_)) | None => {
+					ui.label(translate("ui.server_select.text.pinging"));
+				},
+			}
+			
+			ui.horizontal(|ui| {
+				if ui.button(translate("ui.server_select.button.join")).clicked() {
+					action = Some((index, ServerListAction::Join));
+				}
+				if ui.button(translate("ui.server_select.button.edit")).clicked() {
+					action = Some((index, ServerListAction::Edit));
+				}
+				if ui.button(translate("ui.server_select.button.delete")).clicked() {
+					action = Some((index, ServerListAction::Delete));
+				}
+				if ui.add_enabled(index > 0, egui::Button::new("▲")).clicked() {
+					action = Some((index, ServerListAction::MoveUp));
+				}
+				if ui.add_enabled(index + 1 < servers.iter().count(), egui::Button::new("▼")).clicked() {
+					action = Some((index, ServerListAction::MoveDown));
+				}
+			});
+		}
+		
+		match action {
+			Some((index, ServerListAction::Join)) => {
+				if let Some(server) = servers.get(index) {
+					server_address.0 = server.address.clone();
+					next_state.set(GameState::ClientConnecting);
+				}
+			},
+			Some((index, ServerListAction::Edit)) => {
+				if let Some(server) = servers.get(index) {
+					*form = ServerForm {
+						editing: Some(index),
+						name: server.name.clone(),
+						address: server.address.clone(),
+						query_port: server.query_port.map(|port| port.to_string()).unwrap_or_default(),
+					};
+				}
+			},
+			Some((index, ServerListAction::Delete)) => {
+				servers.remove(index);
+				*form = ServerForm::default();
+				changed = true;
+			},
+			Some((index, ServerListAction::MoveUp)) => {
+				servers.move_up(index);
+				*form = ServerForm::default();
+				changed = true;
+			},
+			Some((index, ServerListAction::MoveDown)) => {
+				servers.move_down(index);
+				*form = ServerForm::default();
+				changed = true;
+			},
+			None => {},
+		}
+		
+		ui.separator();
+		egui::Grid::new("server_form").num_columns(2).show(ui, |ui| {
+			ui.label(translate("ui.server_select.text.name"));
+			ui.text_edit_singleline(&mut form.name);
+			ui.end_row();
+			ui.label(translate("ui.server_select.text.address"));
+			ui.text_edit_singleline(&mut form.address);
+			ui.end_row();
+			ui.label(translate("ui.server_select.text.query_port"));
+			ui.add(egui::TextEdit::singleline(&mut form.query_port).hint_text(translate("ui.server_select.text.query_port_hint")));
+			ui.end_row();
+		});
+		
+		ui.horizontal(|ui| {
+			let query_port = form.query_port.trim();
+			let query_port = (!query_port.is_empty()).then(|| query_port.parse::<u16>());
+			let valid = !form.name.trim().is_empty()
+				&& !form.address.trim().is_empty()
+				&& !matches!(query_port, Some(Err(_)));
+			let submit = match form.editing {
+				Some(_) => translate("ui.server_select.button.save"),
+				None => translate("ui.server_select.button.add"),
+			};
+			if ui.add_enabled(valid, egui::Button::new(submit)).clicked() {
+				let server = SavedServer {
+					name: form.name.trim().to_string(),
+					address: form.address.trim().to_string(),
+					query_port: query_port.and_then(Result::ok),
+				};
+				statuses.ping(&server);
+				match form.editing {
+					Some(index) => servers.replace(index, server),
+					None => servers.push(server),
+				}
+				*form = ServerForm::default();
+				changed = true;
+			}
+			
+			if form.editing.is_some() && ui.button(translate("ui.server_select.button.cancel")).clicked() {
+				*form = ServerForm::default();
+			}
+			
+			if ui.button(translate("ui.server_select.button.refresh")).clicked() {
+				statuses.ping_all(&servers);
+			}
+		});
+	});
+	
+	if changed {
+		if let Err(err) = servers.save() {
+			eprintln!("{}: {}", SERVER_LIST_ERROR_MESSAGE, err);
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+enum ServerListAction {
+	Join,
+	Edit,
+	Delete,
+	MoveUp,
+	MoveDown,
+}
+
 fn button_action(
 	interaction_query: Query<
 		(&Interaction, &PreviousButtonInteraction, &ButtonAction),
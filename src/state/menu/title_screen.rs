@@ -6,7 +6,8 @@ use bevy::prelude::*;
 
 use crate::i18n::{CurrentLocale, TranslationServer};
 use crate::raw_id::tile::RawTileIds;
-use crate::{asset, DEFAULT_LOCALE, despawn_with, from_asset_loc, GameState, LocaleAsset, menu, NAMESPACE, Translatable};
+use crate::server::integrated::{IntegratedServer, stop_integrated_server};
+use crate::{asset, DEFAULT_LOCALE, despawn_with, from_asset_loc, GameState, LocaleAsset, menu, NAMESPACE, ServerConnectAddress, Translatable};
 use crate::menu::{BUTTON_BOTTOM_PADDING, BUTTON_HEIGHT, BUTTON_SCALE, BUTTON_TEXT_SIZE, BUTTON_WIDTH};
 use crate::menu::button::{PreviousButtonInteraction, PreviousButtonProperties};
 use crate::state::menu::{BACKGROUND, NORMAL_BUTTON, TEXT_MARGIN};
@@ -19,11 +20,11 @@ pub struct TitleScreenPlugin;
 impl Plugin for TitleScreenPlugin {
 	fn build(&self, app: &mut App) {
 		app
-			.add_systems(OnEnter(GameState::TitleScreen), setup)
+			.add_systems(OnEnter(GameState::TitleScreen), (setup, stop_integrated_server))
 			.add_systems(OnExit(GameState::TitleScreen), despawn_with::<OnTitleScreen>)
 			.add_systems(
 				Update,
-				(menu::button::style, button_action)
+				(menu::button::style, button_action, join_integrated_server)
 					.run_if(in_state(GameState::TitleScreen))
 			);
 	}
@@ -232,12 +233,24 @@ fn button_action(
 	>,
 	mut app_exit_events: EventWriter<AppExit>,
 	mut next_state: ResMut<NextState<GameState>>,
+	integrated_server: Option<Res<IntegratedServer>>,
+	mut commands: Commands,
 ) {
 	for (interaction, previous_interaction, button_action) in interaction_query.iter() {
 		// only execute action if still hovering after click ends
 		if *interaction == Interaction::Hovered && *previous_interaction == Interaction::Pressed.into() {
 			match button_action {
-				// todo: singleplayer
+				ButtonAction::Singleplayer => {
+					// the server is still starting
+					if integrated_server.is_some() {
+						continue
+					}
+					
+					match IntegratedServer::start() {
+						Ok(integrated_server) => commands.insert_resource(integrated_server),
+						Err(err) => eprintln!("Failed to start the integrated server: {}", err),
+					}
+				},
 				ButtonAction::Multiplayer => next_state.set(GameState::ServerSelect),
 				ButtonAction::Quit => app_exit_events.send(AppExit),
 				_ => unimplemented!("{}", button_action),
@@ -245,3 +258,26 @@ fn button_action(
 		}
 	}
 }
+
+/// Connects to the integrated server once it has started. If it stops before then, it has failed to start; it's
+/// removed again so that singleplayer can be retried.
+fn join_integrated_server(
+	integrated_server: Option<ResMut<IntegratedServer>>,
+	mut server_address: ResMut<ServerConnectAddress>,
+	mut next_state: ResMut<NextState<GameState>>,
+	mut commands: Commands,
+) {
+	let Some(mut integrated_server) = integrated_server else {
+		return
+	};
+	
+	if integrated_server.is_ready() {
+		server_address.0 = integrated_server.address().to_string();
+		next_state.set(GameState::ClientConnecting);
+	} else if integrated_server.has_stopped() {
+		// reports a panic, if that's why it stopped
+		integrated_server.stop();
+		eprintln!("The integrated server stopped before it was ready");
+		commands.remove_resource::<IntegratedServer>();
+	}
+}
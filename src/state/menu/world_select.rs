@@ -4,8 +4,6 @@ use std::fmt::Formatter;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use bevy_egui::egui::style::Margin;
-use renet::{DefaultChannel, RenetClient};
-use renet::transport::NetcodeClientTransport;
 
 use crate::i18n::{CurrentLocale, TranslationServer};
 use crate::world::{ClientGameWorld, WorldId};
@@ -13,9 +11,10 @@ use crate::{asset, DEFAULT_LOCALE, despawn_with, from_asset_loc, GameState, Loca
 use crate::menu::{BACKGROUND, BUTTON_BOTTOM_PADDING, BUTTON_HEIGHT, BUTTON_SCALE, BUTTON_TEXT_SIZE, BUTTON_WIDTH, NORMAL_BUTTON, TEXT_MARGIN};
 use crate::menu::button::{ButtonColor, ButtonDownImage, ButtonImageBundle, ButtonUpImage, PreviousButtonInteraction, PreviousButtonProperties};
 use crate::client;
-use crate::client::networking::disconnect;
+use crate::client::networking::{disconnect, NetClient};
 use crate::networking::{DisconnectReason, protocol};
 use crate::networking::error::{NetworkError, NETWORK_ERROR_MESSAGE};
+use crate::networking::transport::ClientTransport;
 use crate::utils::nonfatal_error_systems;
 
 #[derive(Resource, Default)]
@@ -269,8 +268,8 @@ fn button_action(
 		(&Interaction, &PreviousButtonInteraction, &ButtonAction),
 		(Changed<Interaction>, With<Button>),
 	>,
-	mut transport: ResMut<NetcodeClientTransport>,
-	mut client: ResMut<RenetClient>,
+	mut transport: ResMut<ClientTransport>,
+	mut client: NetClient,
 	mut next_state: ResMut<NextState<GameState>>,
 	world_name: Res<WorldSelection>,
 	mut commands: Commands,
@@ -283,10 +282,13 @@ fn button_action(
 						ClientGameWorld {
 							id: WorldId(world_name.0.clone()),
 							tiles: default(),
+							tile_entities: default(),
 							tile_sprites: default(),
+							light: default(),
+							time: default(),
 						}
 					);
-					client::networking::send_message!(client, DefaultChannel::ReliableOrdered, protocol::ClientMessage::EnterWorldRequest(world_name.0.clone()));
+					client::networking::send_message!(client, protocol::ClientMessage::EnterWorldRequest(world_name.0.clone()));
 				},
 				ButtonAction::Cancel => {
 					disconnect(DisconnectReason::Client(renet::DisconnectReason::DisconnectedByClient), &mut transport, &mut client, true);
@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::identifier::Identifier;
+use crate::raw_id::RawId;
+use crate::utils::BevyHashMap;
+use crate::world::ChunkPos;
+use crate::TilePos;
+
+use super::layer::{TileLayer, TileLayers};
+
+/// A single value stored in a [TileEntity].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TileValue {
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	String(String),
+	/// A list of item stacks, e.g. the contents of a chest.
+	///
+	/// Items are stored by [Identifier] so that they survive raw ID changes.
+	Items(Vec<Option<(Identifier, u16)>>),
+}
+
+impl TileValue {
+	pub fn value_type(&self) -> TileValueType {
+		match self {
+			TileValue::Bool(_) => TileValueType::Bool,
+			TileValue::Int(_) => TileValueType::Int,
+			TileValue::Float(_) => TileValueType::Float,
+			TileValue::String(_) => TileValueType::String,
+			TileValue::Items(items) => TileValueType::Items(items.len() as u16),
+		}
+	}
+}
+
+/// The type of a field in a [TileDataSchema].
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TileValueType {
+	Bool,
+	Int,
+	Float,
+	String,
+	/// A list of item stacks with the given number of slots.
+	Items(u16),
+}
+
+impl TileValueType {
+	/// Returns the value a new tile entity starts with.
+	pub fn default_value(&self) -> TileValue {
+		match self {
+			TileValueType::Bool => TileValue::Bool(false),
+			TileValueType::Int => TileValue::Int(0),
+			TileValueType::Float => TileValue::Float(0.0),
+			TileValueType::String => TileValue::String(String::new()),
+			TileValueType::Items(slots) => TileValue::Items(vec![None; *slots as usize]),
+		}
+	}
+}
+
+/// Structured data attached to a single tile, for anything that doesn't fit in [TileData](super::TileData).
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TileEntity(pub BevyHashMap<String, TileValue>);
+
+impl TileEntity {
+	pub fn get(&self, field: &str) -> Option<&TileValue> {
+		self.0.get(field)
+	}
+	
+	/// Replaces the value of a field, returning the previous value.
+	pub fn set(&mut self, field: impl Into<String>, value: TileValue) -> Option<TileValue> {
+		self.0.insert(field.into(), value)
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum TileEntityError {
+	#[error("field \"{0}\" is missing")]
+	MissingField(String),
+	#[error("field \"{0}\" is not part of the schema")]
+	UnknownField(String),
+	#[error("field \"{field}\" should be {expected:?} but is {found:?}")]
+	WrongType {
+		field: String,
+		expected: TileValueType,
+		found: TileValueType,
+	},
+	#[error("the tile has no tile entity")]
+	Missing,
+	#[error("the tile doesn't have tile entities")]
+	Unexpected,
+}
+
+/// The fields a tile's [TileEntity] must have, as declared in its [TileDef](crate::asset::tile::TileDef).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TileDataSchema(pub BevyHashMap<String, TileValueType>);
+
+impl TileDataSchema {
+	/// Creates a tile entity with every field set to its default value.
+	pub fn default_entity(&self) -> TileEntity {
+		TileEntity(
+			self.0
+				.iter()
+				.map(|(field, value_type)| (field.clone(), value_type.default_value()))
+				.collect()
+		)
+	}
+	
+	/// Checks that the tile entity has exactly the fields of this schema, with the right types.
+	pub fn validate(&self, entity: &TileEntity) -> Result<(), TileEntityError> {
+		for (field, expected) in self.0.iter() {
+			let found = entity
+				.get(field)
+				.ok_or_else(|| TileEntityError::MissingField(field.clone()))?
+				.value_type();
+			if found != *expected {
+				return Err(TileEntityError::WrongType { field: field.clone(), expected: *expected, found })
+			}
+		}
+		
+		match entity.0.keys().find(|field| !self.0.contains_key(*field)) {
+			Some(field) => Err(TileEntityError::UnknownField(field.clone())),
+			None => Ok(()),
+		}
+	}
+}
+
+/// The tile entities in a single chunk.
+pub type ChunkTileEntities = BevyHashMap<(TileLayer, TilePos), TileEntity>;
+
+/// The tile entities of a world, grouped by chunk so that they can be sent and saved a chunk at a time.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TileEntities(BevyHashMap<ChunkPos, ChunkTileEntities>);
+
+impl TileEntities {
+	pub fn get(&self, layer: TileLayer, pos: &TilePos) -> Option<&TileEntity> {
+		self.0.get(&ChunkPos::from(*pos))?.get(&(layer, *pos))
+	}
+	
+	pub fn get_mut(&mut self, layer: TileLayer, pos: &TilePos) -> Option<&mut TileEntity> {
+		self.0.get_mut(&ChunkPos::from(*pos))?.get_mut(&(layer, *pos))
+	}
+	
+	pub fn insert(&mut self, layer: TileLayer, pos: TilePos, entity: TileEntity) -> Option<TileEntity> {
+		self.0
+			.entry(ChunkPos::from(pos))
+			.or_default()
+			.insert((layer, pos), entity)
+	}
+	
+	pub fn remove(&mut self, layer: TileLayer, pos: &TilePos) -> Option<TileEntity> {
+		let chunk_pos = ChunkPos::from(*pos);
+		let chunk = self.0.get_mut(&chunk_pos)?;
+		let entity = chunk.remove(&(layer, *pos));
+		if chunk.is_empty() {
+			self.0.remove(&chunk_pos);
+		}
+		entity
+	}
+	
+	/// Returns the tile entities in the chunk, if it has any.
+	pub fn chunk(&self, chunk_pos: &ChunkPos) -> Option<&ChunkTileEntities> {
+		self.0.get(chunk_pos)
+	}
+	
+	/// Replaces every tile entity in the chunk.
+	pub fn set_chunk(&mut self, chunk_pos: ChunkPos, entities: ChunkTileEntities) {
+		if entities.is_empty() {
+			self.0.remove(&chunk_pos);
+		} else {
+			self.0.insert(chunk_pos, entities);
+		}
+	}
+	
+	pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &ChunkTileEntities)> {
+		self.0.iter()
+	}
+	
+	/// Makes the tile entities fit the tiles: every tile with a data schema gets a tile entity that conforms to it, and
+	/// every other tile entity is dropped. Tile entities that don't conform are reset to the schema's defaults.
+	/// 
+	/// Returns what was wrong with each tile entity that was reset, added or dropped.
+	pub fn conform<'a>(&mut self, tiles: &TileLayers, schema: impl Fn(RawId) -> Option<&'a TileDataSchema>) -> Vec<(TileLayer, TilePos, TileEntityError)> {
+		let mut old = std::mem::take(&mut self.0);
+		let mut problems = vec![];
+		for (layer, pos, tile) in tiles.iter() {
+			let Some(schema) = schema(tile.0) else {
+				continue
+			};
+			
+			let entity = old
+				.get_mut(&ChunkPos::from(*pos))
+				.and_then(|chunk| chunk.remove(&(layer, *pos)));
+			let entity = match entity.map(|entity| schema.validate(&entity).map(|_| entity)) {
+				Some(Ok(entity)) => entity,
+				Some(Err(err)) => {
+					problems.push((layer, *pos, err));
+					schema.default_entity()
+				},
+				None => {
+					problems.push((layer, *pos, TileEntityError::Missing));
+					schema.default_entity()
+				},
+			};
+			self.insert(layer, *pos, entity);
+		}
+		
+		for (layer, pos) in old.into_values().flat_map(|chunk| chunk.into_keys()) {
+			problems.push((layer, pos, TileEntityError::Unexpected));
+		}
+		problems
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::tile::{TileData, WorldTile};
+	
+	use super::*;
+	
+	const CHEST: RawId = RawId(0);
+	const DIRT: RawId = RawId(1);
+	
+	fn chest_schema() -> TileDataSchema {
+		let mut schema = TileDataSchema::default();
+		schema.0.insert("items".to_string(), TileValueType::Items(4));
+		schema.0.insert("locked".to_string(), TileValueType::Bool);
+		schema
+	}
+	
+	fn chest() -> TileEntity {
+		let mut entity = chest_schema().default_entity();
+		entity.set("items", TileValue::Items(vec![Some((Identifier::from_str("test", "dirt"), 3)), None, None, None]));
+		entity
+	}
+	
+	#[test]
+	fn validation() {
+		let schema = chest_schema();
+		assert!(schema.validate(&schema.default_entity()).is_ok());
+		assert!(schema.validate(&chest()).is_ok());
+		
+		let mut missing = chest();
+		missing.0.remove("locked");
+		assert!(matches!(schema.validate(&missing), Err(TileEntityError::MissingField(field)) if field == "locked"));
+		
+		let mut unknown = chest();
+		unknown.set("owner", TileValue::String("steve".to_string()));
+		assert!(matches!(schema.validate(&unknown), Err(TileEntityError::UnknownField(field)) if field == "owner"));
+		
+		let mut wrong_type = chest();
+		wrong_type.set("locked", TileValue::Int(1));
+		assert!(matches!(schema.validate(&wrong_type), Err(TileEntityError::WrongType { found: TileValueType::Int, .. })));
+		
+		// the number of slots is part of the type
+		let mut wrong_size = chest();
+		wrong_size.set("items", TileValue::Items(vec![None; 5]));
+		assert!(matches!(schema.validate(&wrong_size), Err(TileEntityError::WrongType { expected: TileValueType::Items(4), found: TileValueType::Items(5), .. })));
+	}
+	
+	#[test]
+	fn entities_are_made_to_fit_the_tiles() {
+		let schema = chest_schema();
+		let pos = |x, y| TilePos { x, y };
+		let mut tiles = TileLayers::default();
+		tiles.insert(TileLayer::Foreground, pos(0, 0), WorldTile(CHEST, TileData(0)));
+		tiles.insert(TileLayer::Foreground, pos(1, 0), WorldTile(CHEST, TileData(0)));
+		tiles.insert(TileLayer::Foreground, pos(2, 0), WorldTile(CHEST, TileData(0)));
+		tiles.insert(TileLayer::Foreground, pos(3, 0), WorldTile(DIRT, TileData(0)));
+		
+		let mut broken = chest();
+		broken.set("locked", TileValue::String("yes".to_string()));
+		let mut entities = TileEntities::default();
+		entities.insert(TileLayer::Foreground, pos(0, 0), chest());
+		entities.insert(TileLayer::Foreground, pos(1, 0), broken);
+		// nothing at (2, 0)
+		entities.insert(TileLayer::Foreground, pos(3, 0), chest());
+		entities.insert(TileLayer::Background, pos(0, 0), chest());
+		entities.insert(TileLayer::Foreground, pos(-100, 40), chest());
+		
+		let problems: BevyHashMap<_, _> = entities
+			.conform(&tiles, |raw_id| (raw_id == CHEST).then_some(&schema))
+			.into_iter()
+			.map(|(layer, pos, err)| ((layer, pos), err))
+			.collect();
+		assert_eq!(problems.len(), 5);
+		assert!(matches!(problems[&(TileLayer::Foreground, pos(1, 0))], TileEntityError::WrongType { .. }));
+		assert!(matches!(problems[&(TileLayer::Foreground, pos(2, 0))], TileEntityError::Missing));
+		assert!(matches!(problems[&(TileLayer::Foreground, pos(3, 0))], TileEntityError::Unexpected));
+		assert!(matches!(problems[&(TileLayer::Background, pos(0, 0))], TileEntityError::Unexpected));
+		assert!(matches!(problems[&(TileLayer::Foreground, pos(-100, 40))], TileEntityError::Unexpected));
+		
+		assert_eq!(entities.get(TileLayer::Foreground, &pos(0, 0)), Some(&chest()));
+		assert_eq!(entities.get(TileLayer::Foreground, &pos(1, 0)), Some(&schema.default_entity()));
+		assert_eq!(entities.get(TileLayer::Foreground, &pos(2, 0)), Some(&schema.default_entity()));
+		assert_eq!(entities.get(TileLayer::Foreground, &pos(3, 0)), None);
+		assert_eq!(entities.get(TileLayer::Background, &pos(0, 0)), None);
+		assert_eq!(entities.chunks().count(), 1);
+	}
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::registry::tile::settings::TileSalience;
+use crate::utils::BevyHashMap;
+use crate::TilePos;
+
+use super::WorldTile;
+
+/// A layer of the world. Each position can hold one tile per layer, e.g. a background wall behind a foreground block.
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TileLayer {
+	#[serde(alias = "bg")]
+	Background = 0,
+	#[default]
+	#[serde(alias = "fg")]
+	Foreground = 1,
+	Overlay = 2,
+}
+
+impl TileLayer {
+	/// Every layer, from back to front.
+	pub const ALL: [TileLayer; 3] = [TileLayer::Background, TileLayer::Foreground, TileLayer::Overlay];
+	
+	/// Translates the layer into a Z coordinate.
+	#[inline]
+	pub const fn into_z(&self) -> f32 {
+		TileSalience::from_layer(*self).into_z()
+	}
+}
+
+impl TryFrom<u8> for TileLayer {
+	type Error = u8;
+	
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(TileLayer::Background),
+			1 => Ok(TileLayer::Foreground),
+			2 => Ok(TileLayer::Overlay),
+			_ => Err(value),
+		}
+	}
+}
+
+/// The tiles of a world, stored separately for each [TileLayer].
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TileLayers(BevyHashMap<TileLayer, BevyHashMap<TilePos, WorldTile>>);
+
+impl TileLayers {
+	pub fn get(&self, layer: TileLayer, pos: &TilePos) -> Option<&WorldTile> {
+		self.0.get(&layer)?.get(pos)
+	}
+	
+	pub fn insert(&mut self, layer: TileLayer, pos: TilePos, tile: WorldTile) -> Option<WorldTile> {
+		self.0
+			.entry(layer)
+			.or_default()
+			.insert(pos, tile)
+	}
+	
+	pub fn remove(&mut self, layer: TileLayer, pos: &TilePos) -> Option<WorldTile> {
+		self.0.get_mut(&layer)?.remove(pos)
+	}
+	
+	/// Returns the tiles in the layer, if it has any.
+	pub fn layer(&self, layer: TileLayer) -> Option<&BevyHashMap<TilePos, WorldTile>> {
+		self.0.get(&layer)
+	}
+	
+	/// Returns the frontmost tile at the position and its layer.
+	pub fn top(&self, pos: &TilePos) -> Option<(TileLayer, &WorldTile)> {
+		TileLayer::ALL
+			.iter()
+			.rev()
+			.find_map(|&layer| Some((layer, self.get(layer, pos)?)))
+	}
+	
+	/// Iterates over every tile in every layer.
+	pub fn iter(&self) -> impl Iterator<Item = (TileLayer, &TilePos, &WorldTile)> {
+		self.0
+			.iter()
+			.flat_map(|(&layer, tiles)| tiles.iter().map(move |(pos, tile)| (layer, pos, tile)))
+	}
+	
+	/// Iterates over the position of every tile in every layer. Positions with tiles in several layers appear more than once.
+	pub fn positions(&self) -> impl Iterator<Item = &TilePos> {
+		self.0
+			.values()
+			.flat_map(|tiles| tiles.keys())
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.0.values().all(|tiles| tiles.is_empty())
+	}
+}
@@ -0,0 +1,410 @@
+use std::collections::VecDeque;
+
+use crate::raw_id::RawId;
+use crate::registry::tile::settings::TileSettings;
+use crate::utils::BevyHashMap;
+use crate::TilePos;
+
+use super::layer::{TileLayer, TileLayers};
+
+/// The brightest light level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// How many changed tiles [LightMap::update_many] handles one at a time before falling back to relighting everything.
+const INCREMENTAL_LIMIT: usize = 64;
+
+/// The light at a single tile.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LightLevel {
+	/// Light from the sky, before daylight is taken into account.
+	pub sky: u8,
+	/// Light from emissive tiles.
+	pub block: u8,
+}
+
+impl LightLevel {
+	/// Returns how bright the tile is when the sky is at the given daylight level.
+	pub fn with_daylight(&self, daylight: u8) -> u8 {
+		self.block.max(self.sky.saturating_sub(MAX_LIGHT.saturating_sub(daylight)))
+	}
+}
+
+/// Returns the opacity and light emission at the position, combined across every layer.
+fn tile_light(tiles: &TileLayers, pos: &TilePos, settings: &impl Fn(RawId) -> Option<TileSettings>) -> (u8, u8) {
+	TileLayer::ALL
+		.iter()
+		.filter_map(|&layer| settings(tiles.get(layer, pos)?.0))
+		.fold((0, 0), |(opacity, emission), settings| {
+			(opacity.max(settings.opacity()), emission.max(settings.light_emission()))
+		})
+}
+
+fn neighbors(pos: TilePos) -> [TilePos; 4] {
+	[
+		TilePos { x: pos.x - 1, y: pos.y },
+		TilePos { x: pos.x + 1, y: pos.y },
+		TilePos { x: pos.x, y: pos.y - 1 },
+		TilePos { x: pos.x, y: pos.y + 1 },
+	]
+}
+
+/// The light levels of a world.
+///
+/// Sky light starts at [MAX_LIGHT] in every tile that has no opaque tile above it, and block light starts at each tile's
+/// light emission. Both then flood outwards, losing one level per tile plus the opacity of the tile they enter.
+///
+/// Light is stored for the area around the world's tiles, reaching [MAX_LIGHT] tiles past them. Outside of that area
+/// there is only sky light.
+#[derive(Debug, Default, Clone)]
+pub struct LightMap {
+	levels: BevyHashMap<TilePos, LightLevel>,
+	/// The highest opaque tile in each column.
+	tops: BevyHashMap<i32, i32>,
+	/// The inclusive corners of the lit area, or [None] if the light hasn't been computed yet.
+	bounds: Option<(TilePos, TilePos)>,
+	/// Changes whenever any light level might have changed.
+	revision: u64,
+}
+
+impl LightMap {
+	/// Returns whether the light has been computed since the map was created.
+	pub fn is_computed(&self) -> bool {
+		self.bounds.is_some()
+	}
+	
+	/// A number that changes whenever the light levels change, for cheaply checking whether anything needs redrawing.
+	pub fn revision(&self) -> u64 {
+		self.revision
+	}
+	
+	pub fn get(&self, pos: &TilePos) -> LightLevel {
+		match self.levels.get(pos) {
+			Some(level) => *level,
+			None => LightLevel {
+				sky: if self.exposed(pos) { MAX_LIGHT } else { 0 },
+				block: 0,
+			},
+		}
+	}
+	
+	/// Returns the brightest light at the position or next to it, which is the light that reaches the surface of an
+	/// opaque tile.
+	pub fn surface(&self, pos: &TilePos) -> LightLevel {
+		neighbors(*pos)
+			.iter()
+			.map(|neighbor| self.get(neighbor))
+			.fold(self.get(pos), |max, level| LightLevel {
+				sky: max.sky.max(level.sky),
+				block: max.block.max(level.block),
+			})
+	}
+	
+	/// Returns whether the sky is visible from the position.
+	fn exposed(&self, pos: &TilePos) -> bool {
+		self.tops.get(&pos.x).map_or(true, |&top| pos.y > top)
+	}
+	
+	fn in_bounds(&self, pos: &TilePos) -> bool {
+		self.bounds.is_some_and(|(min, max)| (min.x..=max.x).contains(&pos.x) && (min.y..=max.y).contains(&pos.y))
+	}
+	
+	/// Throws away every light level and computes them all again.
+	pub fn compute(&mut self, tiles: &TileLayers, settings: &impl Fn(RawId) -> Option<TileSettings>) {
+		self.levels.clear();
+		self.tops.clear();
+		self.bounds = None;
+		self.revision += 1;
+		
+		let mut positions = tiles.positions().peekable();
+		let Some(&first) = positions.peek() else {
+			// nothing to block the sky, so the defaults are already right
+			self.bounds = Some((TilePos::default(), TilePos::default()));
+			return
+		};
+		
+		let (mut min, mut max) = (first, first);
+		for pos in positions {
+			min = TilePos { x: min.x.min(pos.x), y: min.y.min(pos.y) };
+			max = TilePos { x: max.x.max(pos.x), y: max.y.max(pos.y) };
+			
+			if tile_light(tiles, pos, settings).0 > 0 {
+				let top = self.tops.entry(pos.x).or_insert(pos.y);
+				*top = (*top).max(pos.y);
+			}
+		}
+		
+		let reach = MAX_LIGHT as i32;
+		let min = TilePos { x: min.x - reach, y: min.y - reach };
+		let max = TilePos { x: max.x + reach, y: max.y + reach };
+		self.bounds = Some((min, max));
+		self.relight(min, max, tiles, settings);
+	}
+	
+	/// Updates the light after the tiles at the position have changed.
+	pub fn update(&mut self, tiles: &TileLayers, pos: TilePos, settings: &impl Fn(RawId) -> Option<TileSettings>) {
+		self.update_many(tiles, [pos], settings);
+	}
+	
+	/// Updates the light after the tiles at the positions have changed.
+	///
+	/// Each change only relights the columns within [MAX_LIGHT] tiles of it. Changes outside of the lit area, or too many
+	/// changes at once, relight the whole map instead.
+	pub fn update_many(&mut self, tiles: &TileLayers, positions: impl IntoIterator<Item = TilePos>, settings: &impl Fn(RawId) -> Option<TileSettings>) {
+		let positions: Vec<TilePos> = positions.into_iter().collect();
+		if positions.is_empty() {
+			return
+		}
+		
+		let Some((min, max)) = self.bounds else {
+			self.compute(tiles, settings);
+			return
+		};
+		
+		if positions.len() > INCREMENTAL_LIMIT || positions.iter().any(|pos| !self.in_bounds(pos)) {
+			self.compute(tiles, settings);
+			return
+		}
+		
+		self.revision += 1;
+		for pos in positions {
+			self.update_top(tiles, pos, min.y, settings);
+			
+			let reach = MAX_LIGHT as i32;
+			let area_min = TilePos { x: (pos.x - reach).max(min.x), y: min.y };
+			let area_max = TilePos { x: (pos.x + reach).min(max.x), y: max.y };
+			self.relight(area_min, area_max, tiles, settings);
+		}
+	}
+	
+	/// Keeps the highest opaque tile of the position's column up to date.
+	fn update_top(&mut self, tiles: &TileLayers, pos: TilePos, bottom: i32, settings: &impl Fn(RawId) -> Option<TileSettings>) {
+		let opaque = tile_light(tiles, &pos, settings).0 > 0;
+		match self.tops.get(&pos.x).copied() {
+			Some(top) if opaque && pos.y > top => {
+				self.tops.insert(pos.x, pos.y);
+			},
+			Some(top) if !opaque && pos.y == top => {
+				// the top was removed, so look for the next opaque tile further down
+				let next = (bottom..top)
+					.rev()
+					.find(|&y| tile_light(tiles, &TilePos { x: pos.x, y }, settings).0 > 0);
+				match next {
+					Some(y) => self.tops.insert(pos.x, y),
+					None => self.tops.remove(&pos.x),
+				};
+			},
+			None if opaque => {
+				self.tops.insert(pos.x, pos.y);
+			},
+			_ => {},
+		}
+	}
+	
+	/// Recomputes the light inside the inclusive area, taking the light just outside of it as given.
+	fn relight(&mut self, min: TilePos, max: TilePos, tiles: &TileLayers, settings: &impl Fn(RawId) -> Option<TileSettings>) {
+		let in_area = |pos: &TilePos| (min.x..=max.x).contains(&pos.x) && (min.y..=max.y).contains(&pos.y);
+		let mut queue: VecDeque<TilePos> = VecDeque::new();
+		
+		// sources inside the area
+		for x in min.x..=max.x {
+			for y in min.y..=max.y {
+				let pos = TilePos { x, y };
+				let level = LightLevel {
+					sky: if self.exposed(&pos) { MAX_LIGHT } else { 0 },
+					block: tile_light(tiles, &pos, settings).1,
+				};
+				self.levels.insert(pos, level);
+				if level != LightLevel::default() {
+					queue.push_back(pos);
+				}
+			}
+		}
+		
+		// light coming in from around the area
+		let border = (min.y..=max.y)
+			.flat_map(|y| [TilePos { x: min.x - 1, y }, TilePos { x: max.x + 1, y }])
+			.chain((min.x..=max.x).flat_map(|x| [TilePos { x, y: min.y - 1 }, TilePos { x, y: max.y + 1 }]));
+		for pos in border {
+			if self.get(&pos) != LightLevel::default() {
+				queue.push_back(pos);
+			}
+		}
+		
+		while let Some(pos) = queue.pop_front() {
+			let level = self.get(&pos);
+			for neighbor in neighbors(pos) {
+				if !in_area(&neighbor) {
+					continue
+				}
+				
+				let cost = tile_light(tiles, &neighbor, settings).0.saturating_add(1);
+				let spread = LightLevel {
+					sky: level.sky.saturating_sub(cost),
+					block: level.block.saturating_sub(cost),
+				};
+				let current = self.get(&neighbor);
+				if spread.sky > current.sky || spread.block > current.block {
+					self.levels.insert(neighbor, LightLevel {
+						sky: current.sky.max(spread.sky),
+						block: current.block.max(spread.block),
+					});
+					queue.push_back(neighbor);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::tile::{TileData, WorldTile};
+	
+	use super::*;
+	
+	const STONE: RawId = RawId(0);
+	const GLASS: RawId = RawId(1);
+	const TORCH: RawId = RawId(2);
+	const LEAVES: RawId = RawId(3);
+	
+	fn settings(raw_id: RawId) -> Option<TileSettings> {
+		let settings = match raw_id {
+			STONE => "(salience: fg)",
+			GLASS => "(salience: fg, opacity: Some(0))",
+			TORCH => "(salience: bg, light_emission: 12)",
+			LEAVES => "(salience: bg, opacity: Some(2))",
+			_ => return None,
+		};
+		Some(ron::from_str(settings).unwrap())
+	}
+	
+	fn pos(x: i32, y: i32) -> TilePos {
+		TilePos { x, y }
+	}
+	
+	/// A floor of stone from x = -20 to 20 at y = 0.
+	fn floor() -> TileLayers {
+		let mut tiles = TileLayers::default();
+		for x in -20..=20 {
+			tiles.insert(TileLayer::Foreground, pos(x, 0), WorldTile(STONE, TileData(0)));
+		}
+		tiles
+	}
+	
+	fn computed(tiles: &TileLayers) -> LightMap {
+		let mut light = LightMap::default();
+		light.compute(tiles, &settings);
+		light
+	}
+	
+	/// Checks that the light is the same as if it had been computed from scratch, in and around the lit area.
+	fn assert_recomputed(light: &LightMap, tiles: &TileLayers) {
+		let fresh = computed(tiles);
+		let (min, max) = fresh.bounds.unwrap();
+		for x in min.x - 2..=max.x + 2 {
+			for y in min.y - 2..=max.y + 2 {
+				assert_eq!(light.get(&pos(x, y)), fresh.get(&pos(x, y)), "light at {:?}", pos(x, y));
+			}
+		}
+	}
+	
+	#[test]
+	fn sky_light() {
+		let mut tiles = floor();
+		tiles.insert(TileLayer::Foreground, pos(0, 5), WorldTile(STONE, TileData(0)));
+		tiles.insert(TileLayer::Foreground, pos(8, 3), WorldTile(GLASS, TileData(0)));
+		let light = computed(&tiles);
+		
+		assert_eq!(light.get(&pos(0, 10)).sky, MAX_LIGHT);
+		// under the floating stone, lit from the side
+		assert_eq!(light.get(&pos(0, 4)).sky, MAX_LIGHT - 1);
+		assert_eq!(light.get(&pos(0, 5)).sky, 0);
+		// glass doesn't block the sky
+		assert_eq!(light.get(&pos(8, 2)).sky, MAX_LIGHT);
+		// under the floor, lit from past its end
+		assert_eq!(light.get(&pos(21, -1)).sky, MAX_LIGHT);
+		assert_eq!(light.get(&pos(18, -1)).sky, MAX_LIGHT - 3);
+		assert_eq!(light.get(&pos(0, -1)).sky, 0);
+		
+		// outside of the lit area
+		assert_eq!(light.get(&pos(0, 1000)).sky, MAX_LIGHT);
+		assert_eq!(light.get(&pos(0, -1000)).sky, 0);
+		assert_eq!(light.get(&pos(1000, -1000)).sky, MAX_LIGHT);
+	}
+	
+	#[test]
+	fn block_light() {
+		let mut tiles = floor();
+		tiles.insert(TileLayer::Background, pos(0, 1), WorldTile(TORCH, TileData(0)));
+		tiles.insert(TileLayer::Background, pos(-1, 1), WorldTile(LEAVES, TileData(0)));
+		let light = computed(&tiles);
+		
+		assert_eq!(light.get(&pos(0, 1)).block, 12);
+		assert_eq!(light.get(&pos(2, 1)).block, 10);
+		assert_eq!(light.get(&pos(0, 5)).block, 8);
+		// leaves take two more levels
+		assert_eq!(light.get(&pos(-1, 1)).block, 9);
+		assert_eq!(light.get(&pos(-2, 1)).block, 8);
+		// stone takes everything
+		assert_eq!(light.get(&pos(0, 0)).block, 0);
+		assert_eq!(light.surface(&pos(0, 0)).block, 12);
+		assert_eq!(light.get(&pos(100, 1)).block, 0);
+		
+		assert_eq!(light.get(&pos(0, 1)).with_daylight(0), 12);
+		assert_eq!(light.get(&pos(0, 1)).with_daylight(MAX_LIGHT), MAX_LIGHT);
+	}
+	
+	#[test]
+	fn incremental_updates() {
+		let mut tiles = floor();
+		let mut light = computed(&tiles);
+		
+		let changes = [
+			(TileLayer::Foreground, pos(0, 3), Some(STONE)),
+			(TileLayer::Background, pos(3, 1), Some(TORCH)),
+			(TileLayer::Foreground, pos(3, 2), Some(STONE)),
+			(TileLayer::Foreground, pos(0, 3), None),
+			// breaking the floor lets the sky in underneath
+			(TileLayer::Foreground, pos(-4, 0), None),
+			(TileLayer::Background, pos(3, 1), None),
+			(TileLayer::Foreground, pos(-4, 0), Some(GLASS)),
+		];
+		for (layer, pos, raw_id) in changes {
+			let revision = light.revision();
+			match raw_id {
+				Some(raw_id) => tiles.insert(layer, pos, WorldTile(raw_id, TileData(0))),
+				None => tiles.remove(layer, &pos),
+			};
+			light.update(&tiles, pos, &settings);
+			assert_ne!(light.revision(), revision);
+			assert_recomputed(&light, &tiles);
+		}
+	}
+	
+	#[test]
+	fn changes_outside_the_lit_area() {
+		let mut tiles = floor();
+		let mut light = computed(&tiles);
+		assert_eq!(light.get(&pos(100, 49)).sky, MAX_LIGHT);
+		
+		tiles.insert(TileLayer::Foreground, pos(100, 50), WorldTile(STONE, TileData(0)));
+		light.update(&tiles, pos(100, 50), &settings);
+		assert_eq!(light.get(&pos(100, 49)).sky, MAX_LIGHT - 1);
+		assert!(light.in_bounds(&pos(100, 50)));
+		assert_recomputed(&light, &tiles);
+	}
+	
+	#[test]
+	fn many_changes_at_once() {
+		let mut tiles = floor();
+		let mut light = computed(&tiles);
+		
+		let roof: Vec<TilePos> = (-40..40).map(|x| pos(x, 6)).collect();
+		assert!(roof.len() > INCREMENTAL_LIMIT);
+		for pos in roof.iter() {
+			tiles.insert(TileLayer::Foreground, *pos, WorldTile(STONE, TileData(0)));
+		}
+		light.update_many(&tiles, roof, &settings);
+		assert_eq!(light.get(&pos(0, 3)).sky, 0);
+		assert_recomputed(&light, &tiles);
+	}
+}
@@ -3,6 +3,10 @@ use serde::{Serialize, Deserialize};
 
 use crate::{raw_id::{RawId, tile::RawTileIds}, identifier::Identifier};
 
+pub mod entity;
+pub mod layer;
+pub mod light;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WorldTile(pub RawId, pub TileData);
 
@@ -25,8 +29,11 @@ impl WorldTile {
 	}
 }
 
+/// A byte of data stored directly in the tile, e.g. its growth stage.
+/// 
+/// Anything larger is stored in a [TileEntity](entity::TileEntity).
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct TileData(pub u8); // todo: advanced tile data
+pub struct TileData(pub u8);
 
 /// Marker component that indicates that an entity is a tile sprite.
 #[derive(Debug, Default, Copy, Clone, Component)]
@@ -6,12 +6,8 @@ pub fn load_image<'a, P>(asset_server: &AssetServer, path: P) -> Handle<Image>
 		P: Into<AssetPath<'a>>,
 {
 	let handle = asset_server.load(path);
-	let load_state = asset_server.get_load_state(handle.clone());
-	if let Some(load_state) = load_state {
-		if load_state == LoadState::Loaded {
-			handle
-		}
-	} else {
-		Handle::default()
+	match asset_server.get_load_state(handle.clone()) {
+		Some(LoadState::Loaded) => handle,
+		_ => Handle::default(),
 	}
 }
@@ -3,6 +3,8 @@ use std::ops::Div;
 use bevy::prelude::*;
 
 pub const HANDLE_TO_SCALE_ERROR_MESSAGE: &'static str = "An error occurred while rescaling sprites";
+/// The number of texture pixels that make up a single tile (i.e., one world unit).
+pub const TILE_SIZE_PX: f32 = 16.0;
 
 #[derive(Component, Debug, Default, Copy, Clone, PartialEq, Deref, DerefMut)]
 pub struct Velocity(Transform);
@@ -20,7 +22,7 @@ pub fn handle_to_scale(
 		commands.entity(entity)
 			.remove::<ToScale>();
 		let image = image_assets.get(handle).unwrap_or(image_assets.get(&Handle::default()).unwrap());
-		sprite.custom_size = Some(image.size_f32().div(16.0))
+		sprite.custom_size = Some(image.size_f32().div(TILE_SIZE_PX))
 	}
 	
 	Ok(())
@@ -1,3 +1,4 @@
+use bevy::prelude::Color;
 use bincode::{DefaultOptions, Error, Options};
 use bincode::config::{AllowTrailing, BigEndian, FixintEncoding, LittleEndian, RejectTrailing, WithOtherEndian, WithOtherIntEncoding, WithOtherTrailing};
 use once_cell::sync::Lazy;
@@ -81,6 +82,63 @@ pub fn strip_formatting(msg: &str) -> String {
 	re.replace_all(msg, "").to_string()
 }
 
+/// Returns the color that the formatting code switches to, or [None] if the code isn't a color.
+pub fn formatting_color(code: char) -> Option<Color> {
+	let (r, g, b) = match code {
+		'0' => (0x00, 0x00, 0x00),
+		'1' => (0x00, 0x00, 0xAA),
+		'2' => (0x00, 0xAA, 0x00),
+		'3' => (0x00, 0xAA, 0xAA),
+		'4' => (0xAA, 0x00, 0x00),
+		'5' => (0xAA, 0x00, 0xAA),
+		'6' => (0xFF, 0xAA, 0x00),
+		'7' => (0xAA, 0xAA, 0xAA),
+		'8' => (0x55, 0x55, 0x55),
+		'9' => (0x55, 0x55, 0xFF),
+		'a' => (0x55, 0xFF, 0x55),
+		'b' => (0x55, 0xFF, 0xFF),
+		'c' => (0xFF, 0x55, 0x55),
+		'd' => (0xFF, 0x55, 0xFF),
+		'e' => (0xFF, 0xFF, 0x55),
+		'f' => (0xFF, 0xFF, 0xFF),
+		_ => return None,
+	};
+	Some(Color::rgb_u8(r, g, b))
+}
+
+/// Splits the message into runs of text and the color each run is formatted in. The `r` code resets the color to
+/// `default_color`, and codes that aren't colors are left out like [strip_formatting] does.
+pub fn formatted_sections(msg: &str, default_color: Color) -> Vec<(String, Color)> {
+	let mut sections = vec![];
+	let mut text = String::new();
+	let mut color = default_color;
+	let mut chars = msg.chars().peekable();
+	while let Some(c) = chars.next() {
+		let code = match chars.peek() {
+			Some(&code) if c == '`' && code.is_ascii_alphanumeric() => code,
+			_ => {
+				text.push(c);
+				continue
+			},
+		};
+		chars.next();
+		
+		let next_color = match code {
+			'r' => default_color,
+			code => formatting_color(code).unwrap_or(color),
+		};
+		if next_color != color && !text.is_empty() {
+			sections.push((std::mem::take(&mut text), color));
+		}
+		color = next_color;
+	}
+	
+	if !text.is_empty() {
+		sections.push((text, color));
+	}
+	sections
+}
+
 // cursed
 pub trait NewType {
 	type Inner;
@@ -1,5 +1,8 @@
 use crate::utils::BevyHashMap;
-use std::time::SystemTime;
+use std::collections::BTreeSet;
+use std::f64::consts::TAU;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
 
 use bevy::prelude::*;
 use serde::{Serialize, Deserialize};
@@ -7,12 +10,19 @@ use thiserror::Error;
 
 use crate::{TilePos, Position};
 use crate::identifier::Identifier;
+use crate::inventory::{Inventory, SavedInventory};
 use crate::networking::Username;
 use crate::raw_id::RawId;
-use crate::raw_id::tile::RawTileIds;
+use crate::raw_id::item::RawItemIds;
 use crate::save::error::SaveError;
+use crate::save::format::WorldSave;
 use crate::save::open_or_gen_world;
+use crate::asset::tile::TileDef;
+use crate::registry::tile::TileDefs;
 use crate::tile::{WorldTile, TileData};
+use crate::tile::entity::TileEntities;
+use crate::tile::layer::{TileLayer, TileLayers};
+use crate::tile::light::{LightMap, MAX_LIGHT};
 
 #[derive(Resource, Default)]
 pub struct ServerGameWorlds(BevyHashMap<String, ServerGameWorld>);
@@ -28,17 +38,32 @@ impl ServerGameWorlds {
 		self.0.get_mut(world_name)
 	}
 	
-	/// Gets, loads, or generates the specified [GameWorld].
-	pub fn get_or_gen_world_mut(&mut self, world_name: &str, raw_tile_ids: &RawTileIds) -> Result<&mut ServerGameWorld, SaveError> {
+	/// Gets, loads (from the save directory), or generates the specified [GameWorld].
+	/// 
+	/// Tile entities that don't fit the data schemas of their tiles, e.g. because a tile's definition has changed since
+	/// the world was saved, are reset or dropped.
+	pub fn get_or_gen_world_mut(&mut self, save_dir: &Path, world_name: &str, tile_defs: &TileDefs, raw_item_ids: &RawItemIds) -> Result<&mut ServerGameWorld, SaveError> {
 		if self.0.contains_key(world_name) {
 			Ok(self.0.get_mut(world_name).unwrap())
 		} else {
-			let save = open_or_gen_world(world_name, raw_tile_ids)?;
+			let save = open_or_gen_world(save_dir, world_name, tile_defs.raw_tile_ids())?;
+			let mut tile_entities = save.tile_entities;
+			for (layer, pos, err) in tile_entities.conform(&save.tiles, |raw_id| tile_defs.get_raw(raw_id)?.data_schema()) {
+				warn!("Fixed the tile entity of the {:?} tile at {:?} in world {}: {}", layer, pos, world_name, err);
+			}
+			
 			let world = ServerGameWorld {
 				id: WorldId(world_name.to_string()),
 				tiles: save.tiles,
+				tile_entities,
+				light: default(),
+				time: save.time,
 				players: default(),
 				bans: save.bans,
+				records: save.players
+					.iter()
+					.map(|(username, record)| (username.clone(), record.to_record(raw_item_ids)))
+					.collect(),
 				spawnpoint: Position { x: 0.0, y: 1.0 }, // todo: implement customizable spawnpoint
 			};
 			self.add_world(world_name.to_string(), world);
@@ -46,6 +71,16 @@ impl ServerGameWorlds {
 		}
 	}
 	
+	/// Iterates over every loaded world in no particular order.
+	pub fn iter(&self) -> impl Iterator<Item = (&String, &ServerGameWorld)> {
+		self.0.iter()
+	}
+	
+	/// Iterates over every loaded world in no particular order.
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut ServerGameWorld)> {
+		self.0.iter_mut()
+	}
+	
 	pub fn add_world(&mut self, world_name: String, world: ServerGameWorld) {
 		self.0.insert(world_name, world);
 	}
@@ -81,9 +116,110 @@ impl WorldBan {
 	}
 }
 
+/// What a player has done in a world, over every time they've been in it.
+#[derive(Component, Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlayerStats {
+	/// How long the player has spent in the world.
+	pub play_time: Duration,
+	pub tiles_broken: u64,
+	pub tiles_placed: u64,
+}
+
+/// When the player first entered the world they're in, if known. Worlds saved before player records existed don't know.
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct FirstJoined(pub Option<SystemTime>);
+
+/// What a world remembers about a player who isn't in it.
+#[derive(Debug, Clone)]
+pub struct PlayerRecord {
+	/// Where the player was when they left, or [None] to put them at the spawnpoint.
+	pub position: Option<Position>,
+	pub inventory: Inventory,
+	pub stats: PlayerStats,
+	pub first_joined: Option<SystemTime>,
+}
+
+impl PlayerRecord {
+	/// Returns the record of a player who is entering the world for the first time.
+	pub fn first_visit() -> Self {
+		Self {
+			position: None,
+			inventory: default(),
+			stats: default(),
+			first_joined: Some(SystemTime::now()),
+		}
+	}
+	
+	/// Converts the raw IDs in this record into their [Identifier]s, like [Inventory::to_saved].
+	pub fn to_saved(&self, raw_item_ids: &RawItemIds) -> SavedPlayerRecord {
+		SavedPlayerRecord {
+			position: self.position,
+			inventory: self.inventory.to_saved(raw_item_ids),
+			stats: self.stats,
+			first_joined: self.first_joined,
+		}
+	}
+}
+
+/// A [PlayerRecord] with [Identifier]s in place of raw IDs.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SavedPlayerRecord {
+	pub position: Option<Position>,
+	pub inventory: SavedInventory,
+	pub stats: PlayerStats,
+	pub first_joined: Option<SystemTime>,
+}
+
+impl SavedPlayerRecord {
+	/// Converts this back into a [PlayerRecord], dropping any items that no longer exist.
+	pub fn to_record(&self, raw_item_ids: &RawItemIds) -> PlayerRecord {
+		PlayerRecord {
+			position: self.position,
+			inventory: self.inventory.to_inventory(raw_item_ids),
+			stats: self.stats,
+			first_joined: self.first_joined,
+		}
+	}
+}
+
+/// How long a full day lasts, in seconds.
+pub const DAY_LENGTH: f64 = 1200.0;
+/// The daylight level at midnight.
+pub const MIN_DAYLIGHT: u8 = 4;
+
+/// The time in a world, in seconds.
+/// 
+/// A day starts at midnight, so noon is halfway through each [DAY_LENGTH].
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+pub struct WorldTime(pub f64);
+
+impl Default for WorldTime {
+	fn default() -> Self {
+		Self(DAY_LENGTH * 0.3)
+	}
+}
+
+impl WorldTime {
+	pub fn advance(&mut self, delta: Duration) {
+		self.0 += delta.as_secs_f64();
+	}
+	
+	/// Returns how far into the current day it is, from `0.0` (midnight) to `1.0` (the next midnight).
+	pub fn time_of_day(&self) -> f64 {
+		(self.0 / DAY_LENGTH).rem_euclid(1.0)
+	}
+	
+	/// Returns the sky light level, from [MIN_DAYLIGHT] at midnight to [MAX_LIGHT] at noon.
+	pub fn daylight(&self) -> u8 {
+		let sun = (1.0 - (self.time_of_day() * TAU).cos()) / 2.0;
+		MIN_DAYLIGHT + ((MAX_LIGHT - MIN_DAYLIGHT) as f64 * sun).round() as u8
+	}
+}
+
 /// An event that sets a tile.
 #[derive(Event)]
 pub struct SetTileEvent {
+	pub layer: TileLayer,
 	pub pos: TilePos,
 	pub id: Identifier,
 	pub data: TileData,
@@ -104,24 +240,131 @@ pub const TILE_EVENT_ERROR_MESSAGE: &'static str = "A TileEventError has occurre
 #[derive(Clone)]
 pub struct ServerGameWorld {
 	pub id: WorldId,
-	pub tiles: BevyHashMap<TilePos, WorldTile>,
+	pub tiles: TileLayers,
+	pub tile_entities: TileEntities,
+	pub light: LightMap,
+	pub time: WorldTime,
 	pub players: Vec<Entity>,
 	pub bans: BevyHashMap<Username, WorldBan>,
 	pub spawnpoint: Position,
+	/// The records of players that have been in this world but aren't in it right now.
+	pub records: BevyHashMap<Username, PlayerRecord>,
+}
+
+impl ServerGameWorld {
+	/// Returns every chunk that contains at least one tile, in order.
+	pub fn loaded_chunks(&self) -> BTreeSet<ChunkPos> {
+		self.tiles.positions().map(|&pos| ChunkPos::from(pos)).collect()
+	}
+	
+	/// Places the tile in the layer, giving it a fresh [TileEntity](crate::tile::entity::TileEntity) if its definition has a
+	/// data schema.
+	/// 
+	/// Any tile entity of the previous tile in that layer is removed.
+	pub fn set_tile(&mut self, layer: TileLayer, pos: TilePos, tile: WorldTile, def: &TileDef) {
+		match def.data_schema() {
+			Some(schema) => {
+				self.tile_entities.insert(layer, pos, schema.default_entity());
+			},
+			None => {
+				self.tile_entities.remove(layer, &pos);
+			},
+		}
+		self.tiles.insert(layer, pos, tile);
+	}
+	
+	/// Removes the tile in the layer and its tile entity.
+	pub fn remove_tile(&mut self, layer: TileLayer, pos: &TilePos) -> Option<WorldTile> {
+		self.tile_entities.remove(layer, pos);
+		self.tiles.remove(layer, pos)
+	}
+	
+	/// Updates the light around the positions after their tiles have changed.
+	pub fn update_light(&mut self, positions: impl IntoIterator<Item = TilePos>, tile_defs: &TileDefs) {
+		self.light.update_many(&self.tiles, positions, &|raw_id| tile_defs.settings(raw_id));
+	}
+	
+	/// Creates a [WorldSave] from this world.
+	/// 
+	/// Records of players that are still in the world are taken from `online_records`.
+	pub fn to_save(&self, raw_item_ids: &RawItemIds, online_records: impl IntoIterator<Item = (Username, PlayerRecord)>) -> WorldSave {
+		let mut players: BevyHashMap<Username, SavedPlayerRecord> = self.records
+			.iter()
+			.map(|(username, record)| (username.clone(), record.to_saved(raw_item_ids)))
+			.collect();
+		for (username, record) in online_records {
+			players.insert(username, record.to_saved(raw_item_ids));
+		}
+		
+		WorldSave {
+			tiles: self.tiles.clone(),
+			tile_entities: self.tile_entities.clone(),
+			bans: self.bans.clone(),
+			players,
+			time: self.time,
+		}
+	}
 }
 
 #[derive(Clone, Resource)]
 pub struct ClientGameWorld {
 	pub id: WorldId,
-	pub tiles: BevyHashMap<TilePos, WorldTile>,
-	pub tile_sprites: BevyHashMap<TilePos, Entity>,
+	pub tiles: TileLayers,
+	pub tile_entities: TileEntities,
+	pub tile_sprites: BevyHashMap<(TileLayer, TilePos), Entity>,
+	pub light: LightMap,
+	/// The server's time, advanced locally between syncs.
+	pub time: WorldTime,
 }
 
 impl ClientGameWorld {
-	pub fn get_tile(&self, pos: &TilePos) -> Option<&WorldTile> {
-		self.tiles.get(pos)
+	pub fn get_tile(&self, layer: TileLayer, pos: &TilePos) -> Option<&WorldTile> {
+		self.tiles.get(layer, pos)
+	}
+	
+	/// Returns the smallest and largest [ChunkPos] that contain a loaded tile, or [None] if no tiles are loaded.
+	pub fn chunk_bounds(&self) -> Option<(ChunkPos, ChunkPos)> {
+		let mut positions = self.tiles.positions().map(|&pos| ChunkPos::from(pos));
+		let first = positions.next()?;
+		Some(
+			positions.fold((first, first), |(min, max), pos| {
+				(
+					ChunkPos { x: min.x.min(pos.x), y: min.y.min(pos.y) },
+					ChunkPos { x: max.x.max(pos.x), y: max.y.max(pos.y) },
+				)
+			})
+		)
 	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deref, DerefMut, Serialize, Deserialize, Component)]
 pub struct WorldId(pub String);
+
+/// The width and height of a chunk in tiles.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// The position of a chunk, measured in chunks rather than tiles.
+#[derive(Component, Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkPos {
+	pub x: i32,
+	pub y: i32,
+}
+
+impl ChunkPos {
+	/// Returns the position of the bottom-left tile in this chunk.
+	pub fn origin(&self) -> TilePos {
+		TilePos {
+			x: self.x * CHUNK_SIZE,
+			y: self.y * CHUNK_SIZE,
+		}
+	}
+}
+
+impl From<TilePos> for ChunkPos {
+	fn from(pos: TilePos) -> Self {
+		Self {
+			x: pos.x.div_euclid(CHUNK_SIZE),
+			y: pos.y.div_euclid(CHUNK_SIZE),
+		}
+	}
+}